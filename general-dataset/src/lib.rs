@@ -14,7 +14,7 @@ pub mod burn_dataset;
 pub mod cache;
 pub mod presets;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SqliteDatasetConfig {
     pub db_file: PathBuf,
     pub get_sql: String,
@@ -1 +1,43 @@
+use burn::prelude::*;
+use image::{ImageFormat, load_from_memory_with_format};
+
 pub mod autoencoder;
+pub mod classifier;
+pub mod sequence;
+
+/// Decodes a webp image into a `[1, channels, width, height]` tensor with values in `[0, 1]`.
+pub(crate) fn webp_to_tensor<B: Backend>(
+    webp: &[u8],
+    width: usize,
+    height: usize,
+    channels: usize,
+    device: &B::Device,
+) -> Tensor<B, 4> {
+    let img = load_from_memory_with_format(webp, ImageFormat::WebP).unwrap();
+    let data = match channels {
+        1 => img.to_luma32f().into_vec(),
+        2 => img.to_luma_alpha32f().into_vec(),
+        3 => img.into_rgb32f().into_vec(),
+        4 => img.into_rgba32f().into_vec(),
+        _ => unreachable!(),
+    };
+    // assert!(data.iter().all(|x| *x <= 1.0), "{:?}", data);
+    // assert!(data.iter().all(|x| *x >= 0.0), "{:?}", data);
+    Tensor::<B, 1>::from_data(data.as_slice(), device)
+        .reshape([1, width, height, channels])
+        .permute([0, 3, 1, 2])
+        .clamp(0.0, 1.0)
+        .detach()
+}
+
+/// Decodes a blob of little-endian `f32`s.
+pub(crate) fn f32_blob(blob: &[u8]) -> Vec<f32> {
+    assert!(
+        blob.len().is_multiple_of(4),
+        "Expected a blob of f32s, got {} bytes",
+        blob.len()
+    );
+    blob.chunks_exact(4)
+        .map(|bytes| f32::from_le_bytes(bytes.try_into().unwrap()))
+        .collect()
+}
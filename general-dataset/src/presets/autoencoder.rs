@@ -1,8 +1,7 @@
 use burn::prelude::*;
-use image::{ImageFormat, load_from_memory_with_format};
 use rayon::join;
 
-use crate::{StatefulBatcher, sql_object};
+use crate::{FromSqlRow, StatefulBatcher, presets::webp_to_tensor, sql_object};
 
 sql_object!(
     pub struct AutoEncoderImageItem {
@@ -15,10 +14,30 @@ sql_object!(
     }
 );
 
+/// An [`AutoEncoderImageItem`] along with the class of the image, read from an optional `label`
+/// column.
+#[derive(Debug, Clone)]
+pub struct LabeledAutoEncoderImageItem {
+    pub image: AutoEncoderImageItem,
+    pub label: Option<usize>,
+}
+
+impl FromSqlRow for LabeledAutoEncoderImageItem {
+    fn from(row: &rusqlite::Row) -> Self {
+        Self {
+            image: FromSqlRow::from(row),
+            // A missing column is the same as a missing label
+            label: row.get("label").ok().flatten(),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct AutoEncoderImageBatch<B: Backend> {
     pub input: Tensor<B, 4>,
     pub expected: Tensor<B, 4>,
+    /// The one-hot labels of the images, if the items were labeled.
+    pub condition: Option<Tensor<B, 2>>,
 }
 
 // impl<B: Backend> Clone for AutoEncoderImageBatch<B> {
@@ -35,6 +54,9 @@ pub struct AutoEncoderImageBatcher<B: Backend> {
     channels: usize,
     input_tensors: Vec<Tensor<B, 4>>,
     expected_tensors: Vec<Tensor<B, 4>>,
+    /// The number of classes of [`LabeledAutoEncoderImageItem`]s.
+    num_classes: usize,
+    condition_tensors: Vec<Tensor<B, 2>>,
     device: B::Device,
 }
 
@@ -44,9 +66,50 @@ impl<B: Backend> AutoEncoderImageBatcher<B> {
             channels,
             input_tensors: vec![],
             expected_tensors: vec![],
+            num_classes: 0,
+            condition_tensors: vec![],
             device,
         }
     }
+
+    /// Sets the number of classes that the labels of [`LabeledAutoEncoderImageItem`]s are one-hot
+    /// encoded with. Labels are ignored when this is 0, which is the default.
+    pub fn with_num_classes(mut self, num_classes: usize) -> Self {
+        self.num_classes = num_classes;
+        self
+    }
+}
+
+impl<B: Backend> StatefulBatcher<LabeledAutoEncoderImageItem, AutoEncoderImageBatch<B>>
+    for AutoEncoderImageBatcher<B>
+{
+    fn reset(&mut self) {
+        StatefulBatcher::<AutoEncoderImageItem, _>::reset(self);
+    }
+
+    fn ingest(&mut self, item: LabeledAutoEncoderImageItem) {
+        if self.num_classes > 0 {
+            let label = item
+                .label
+                .expect("Expected get_sql to output a `label` column for every row");
+            assert!(
+                label < self.num_classes,
+                "Expected a label below {}, got {label}",
+                self.num_classes,
+            );
+            let mut one_hot = vec![0.0f32; self.num_classes];
+            one_hot[label] = 1.0;
+            self.condition_tensors.push(Tensor::from_data(
+                TensorData::new(one_hot, [1, self.num_classes]),
+                &self.device,
+            ));
+        }
+        self.ingest(item.image);
+    }
+
+    fn finish(&mut self) -> AutoEncoderImageBatch<B> {
+        StatefulBatcher::<AutoEncoderImageItem, _>::finish(self)
+    }
 }
 
 impl<B: Backend> StatefulBatcher<AutoEncoderImageItem, AutoEncoderImageBatch<B>>
@@ -55,26 +118,19 @@ impl<B: Backend> StatefulBatcher<AutoEncoderImageItem, AutoEncoderImageBatch<B>>
     fn reset(&mut self) {
         self.input_tensors.clear();
         self.expected_tensors.clear();
+        self.condition_tensors.clear();
     }
 
     fn ingest(&mut self, item: AutoEncoderImageItem) {
         macro_rules! process {
             ($webp: ident) => {{
-                let img = load_from_memory_with_format(&item.$webp, ImageFormat::WebP).unwrap();
-                let data = match self.channels {
-                    1 => img.to_luma32f().into_vec(),
-                    2 => img.to_luma_alpha32f().into_vec(),
-                    3 => img.into_rgb32f().into_vec(),
-                    4 => img.into_rgba32f().into_vec(),
-                    _ => unreachable!(),
-                };
-                // assert!(data.iter().all(|x| *x <= 1.0), "{:?}", data);
-                // assert!(data.iter().all(|x| *x >= 0.0), "{:?}", data);
-                Tensor::<B, 1>::from_data(data.as_slice(), &self.device)
-                    .reshape([1, item.input_width, item.input_height, self.channels])
-                    .permute([0, 3, 1, 2])
-                    .clamp(0.0, 1.0)
-                    .detach()
+                webp_to_tensor(
+                    &item.$webp,
+                    item.input_width,
+                    item.input_height,
+                    self.channels,
+                    &self.device,
+                )
             }};
         }
         join(
@@ -97,6 +153,8 @@ impl<B: Backend> StatefulBatcher<AutoEncoderImageItem, AutoEncoderImageBatch<B>>
     fn finish(&mut self) -> AutoEncoderImageBatch<B> {
         let input_replace = Vec::with_capacity(self.input_tensors.len());
         let expected_replace = Vec::with_capacity(self.expected_tensors.len());
+        let condition_replace = Vec::with_capacity(self.condition_tensors.len());
+        let condition_tensors = std::mem::replace(&mut self.condition_tensors, condition_replace);
 
         AutoEncoderImageBatch {
             input: Tensor::cat(std::mem::replace(&mut self.input_tensors, input_replace), 0),
@@ -104,6 +162,7 @@ impl<B: Backend> StatefulBatcher<AutoEncoderImageItem, AutoEncoderImageBatch<B>>
                 std::mem::replace(&mut self.expected_tensors, expected_replace),
                 0,
             ),
+            condition: (!condition_tensors.is_empty()).then(|| Tensor::cat(condition_tensors, 0)),
         }
     }
     // fn batch(&self, items: Vec<I>, device: &<B as Backend>::Device) -> AutoEncoderImageBatch<B> {
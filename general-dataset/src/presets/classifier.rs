@@ -0,0 +1,77 @@
+use burn::prelude::*;
+
+use crate::{StatefulBatcher, presets::webp_to_tensor, sql_object};
+
+sql_object!(
+    pub struct ImageClassifierItem {
+        pub webp: Vec<u8>,
+        pub width: usize,
+        pub height: usize,
+        pub label: usize,
+    }
+);
+
+#[derive(Clone, Debug)]
+pub struct ImageClassifierBatch<B: Backend> {
+    pub input: Tensor<B, 4>,
+    /// The class of each image, `[batch_size]`.
+    pub labels: Tensor<B, 1, Int>,
+}
+
+#[derive(Debug)]
+pub struct ImageClassifierBatcher<B: Backend> {
+    channels: usize,
+    num_classes: usize,
+    input_tensors: Vec<Tensor<B, 4>>,
+    labels: Vec<i64>,
+    device: B::Device,
+}
+
+impl<B: Backend> ImageClassifierBatcher<B> {
+    pub fn new(channels: usize, num_classes: usize, device: B::Device) -> Self {
+        Self {
+            channels,
+            num_classes,
+            input_tensors: vec![],
+            labels: vec![],
+            device,
+        }
+    }
+}
+
+impl<B: Backend> StatefulBatcher<ImageClassifierItem, ImageClassifierBatch<B>>
+    for ImageClassifierBatcher<B>
+{
+    fn reset(&mut self) {
+        self.input_tensors.clear();
+        self.labels.clear();
+    }
+
+    fn ingest(&mut self, item: ImageClassifierItem) {
+        assert!(
+            item.label < self.num_classes,
+            "Expected a label below {}, got {}",
+            self.num_classes,
+            item.label,
+        );
+        self.input_tensors.push(webp_to_tensor(
+            &item.webp,
+            item.width,
+            item.height,
+            self.channels,
+            &self.device,
+        ));
+        self.labels.push(item.label as i64);
+    }
+
+    fn finish(&mut self) -> ImageClassifierBatch<B> {
+        let input_replace = Vec::with_capacity(self.input_tensors.len());
+        let labels = std::mem::take(&mut self.labels);
+        let batch_size = labels.len();
+
+        ImageClassifierBatch {
+            input: Tensor::cat(std::mem::replace(&mut self.input_tensors, input_replace), 0),
+            labels: Tensor::from_data(TensorData::new(labels, [batch_size]), &self.device),
+        }
+    }
+}
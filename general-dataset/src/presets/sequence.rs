@@ -0,0 +1,83 @@
+use burn::prelude::*;
+
+use crate::{StatefulBatcher, presets::f32_blob, sql_object};
+
+// `sequence` is a `[seq_len, features]` sequence of little-endian `f32`s, and `target` a vector
+// of little-endian `f32`s.
+sql_object!(
+    pub struct SequenceItem {
+        pub sequence: Vec<u8>,
+        pub target: Vec<u8>,
+    }
+);
+
+#[derive(Clone, Debug)]
+pub struct SequenceBatch<B: Backend> {
+    /// `[batch_size, seq_len, features]`
+    pub input: Tensor<B, 3>,
+    /// `[batch_size, target_size]`
+    pub target: Tensor<B, 2>,
+}
+
+/// Batches [`SequenceItem`]s, whose sequence length is inferred from the size of the sequence
+/// blob. Every sequence in a batch must have the same length.
+#[derive(Debug)]
+pub struct SequenceBatcher<B: Backend> {
+    features: usize,
+    target_size: usize,
+    input_tensors: Vec<Tensor<B, 3>>,
+    target_tensors: Vec<Tensor<B, 2>>,
+    device: B::Device,
+}
+
+impl<B: Backend> SequenceBatcher<B> {
+    pub fn new(features: usize, target_size: usize, device: B::Device) -> Self {
+        Self {
+            features,
+            target_size,
+            input_tensors: vec![],
+            target_tensors: vec![],
+            device,
+        }
+    }
+}
+
+impl<B: Backend> StatefulBatcher<SequenceItem, SequenceBatch<B>> for SequenceBatcher<B> {
+    fn reset(&mut self) {
+        self.input_tensors.clear();
+        self.target_tensors.clear();
+    }
+
+    fn ingest(&mut self, item: SequenceItem) {
+        let sequence = f32_blob(&item.sequence);
+        assert!(
+            sequence.len().is_multiple_of(self.features),
+            "Expected a sequence of {} features per step, got {} values",
+            self.features,
+            sequence.len(),
+        );
+        let seq_len = sequence.len() / self.features;
+        let target = f32_blob(&item.target);
+        assert_eq!(
+            target.len(),
+            self.target_size,
+            "Expected a target of size {}",
+            self.target_size,
+        );
+        self.input_tensors.push(Tensor::from_data(
+            TensorData::new(sequence, [1, seq_len, self.features]),
+            &self.device,
+        ));
+        self.target_tensors.push(Tensor::from_data(
+            TensorData::new(target, [1, self.target_size]),
+            &self.device,
+        ));
+    }
+
+    fn finish(&mut self) -> SequenceBatch<B> {
+        SequenceBatch {
+            input: Tensor::cat(std::mem::take(&mut self.input_tensors), 0),
+            target: Tensor::cat(std::mem::take(&mut self.target_tensors), 0),
+        }
+    }
+}
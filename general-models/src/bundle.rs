@@ -0,0 +1,178 @@
+//! Single-file model bundles, which hold the config of a model along with its weights, so that a
+//! model can be loaded without its model.json.
+//!
+//! A bundle starts with [`BUNDLE_MAGIC`], then the length of its header as a little-endian `u64`,
+//! the [`BundleHeader`] as JSON, and the weights as safetensors with the readable names of
+//! [`crate::weights`].
+
+use std::{collections::BTreeMap, io::Write, path::Path};
+
+use burn::prelude::*;
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
+
+use crate::{
+    Init,
+    error::{LoadModelError, StoreError},
+    weights::{load_safetensors_bytes, save_safetensors_bytes},
+};
+
+pub const BUNDLE_MAGIC: &[u8; 8] = b"GMBUNDLE";
+/// The version of the format of the bundles written by [`Bundle::save`]. Bundles of other
+/// versions can't be loaded.
+pub const BUNDLE_FORMAT_VERSION: u32 = 1;
+
+/// Everything in a bundle except the weights.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleHeader {
+    pub format_version: u32,
+    /// The config of the model, as it would be written in model.json.
+    pub config: serde_json::Value,
+    /// The [`config_hash`] of the training config the model was trained with.
+    pub training_config_hash: Option<String>,
+    /// Anything else worth knowing about the model, such as its type or the epoch it was saved
+    /// at.
+    #[serde(default)]
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// The config and metadata of a bundled model, whose config is of type `C`.
+#[derive(Debug, Clone)]
+pub struct Bundle<C> {
+    pub config: C,
+    pub training_config_hash: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+impl<C> Bundle<C> {
+    pub fn new(config: C) -> Self {
+        Self {
+            config,
+            training_config_hash: None,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_training_config_hash(mut self, hash: impl Into<String>) -> Self {
+        self.training_config_hash = Some(hash.into());
+        self
+    }
+
+    pub fn with_metadata(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.metadata.insert(key.into(), value.into());
+        self
+    }
+
+    /// Writes `model` and its config to a bundle at `path`, overwriting any existing file.
+    pub fn save<B: Backend, M: Module<B>>(
+        &self,
+        model: &M,
+        path: impl AsRef<Path>,
+    ) -> Result<(), StoreError>
+    where
+        C: Serialize,
+    {
+        let header = BundleHeader {
+            format_version: BUNDLE_FORMAT_VERSION,
+            config: serde_json::to_value(&self.config)
+                .expect("Expected model config to be serializable"),
+            training_config_hash: self.training_config_hash.clone(),
+            metadata: self.metadata.clone(),
+        };
+        let header =
+            serde_json::to_vec(&header).expect("Expected bundle header to be serializable");
+        let weights = save_safetensors_bytes(model)?;
+
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        file.write_all(BUNDLE_MAGIC)?;
+        file.write_all(&(header.len() as u64).to_le_bytes())?;
+        file.write_all(&header)?;
+        file.write_all(&weights)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Builds the model from the config in the bundle at `path`, then loads its weights.
+    pub fn load<B, M>(
+        path: impl AsRef<Path>,
+        device: &B::Device,
+    ) -> Result<(M, Self), LoadModelError>
+    where
+        B: Backend,
+        C: DeserializeOwned + Init<B, M> + Clone,
+        M: Module<B>,
+    {
+        let bytes = std::fs::read(path)?;
+        let (header, weights) = split_bundle(&bytes)?;
+        let bundle = Self {
+            config: serde_json::from_value(header.config)?,
+            training_config_hash: header.training_config_hash,
+            metadata: header.metadata,
+        };
+        let model = load_safetensors_bytes(bundle.config.clone().init(device), weights.to_vec())?;
+        Ok((model, bundle))
+    }
+}
+
+/// Reads the header of the bundle at `path`, such as to find out which type of model it holds
+/// before loading it.
+pub fn read_bundle_header(path: impl AsRef<Path>) -> Result<BundleHeader, LoadModelError> {
+    let bytes = std::fs::read(path)?;
+    Ok(split_bundle(&bytes)?.0)
+}
+
+/// Loads the weights of the bundle at `path` into `model`, ignoring the config in the bundle.
+pub fn load_bundle_weights<B: Backend, M: Module<B>>(
+    model: M,
+    path: impl AsRef<Path>,
+) -> Result<M, LoadModelError> {
+    load_safetensors_bytes(model, read_bundle_weights(path)?)
+}
+
+/// Reads the weights of the bundle at `path` as safetensors, such as to load part of them with
+/// [`crate::transfer::load_submodule`].
+pub fn read_bundle_weights(path: impl AsRef<Path>) -> Result<Vec<u8>, LoadModelError> {
+    let bytes = std::fs::read(path)?;
+    Ok(split_bundle(&bytes)?.1.to_vec())
+}
+
+/// Splits a bundle into its header and its weights, checking its format version.
+fn split_bundle(bytes: &[u8]) -> Result<(BundleHeader, &[u8]), LoadModelError> {
+    let rest = bytes
+        .strip_prefix(BUNDLE_MAGIC)
+        .ok_or_else(|| LoadModelError::InvalidBundle("missing magic bytes".into()))?;
+    let (len, rest) = rest
+        .split_first_chunk::<8>()
+        .ok_or_else(|| LoadModelError::InvalidBundle("missing header length".into()))?;
+    let len = usize::try_from(u64::from_le_bytes(*len))
+        .ok()
+        .filter(|&len| len <= rest.len())
+        .ok_or_else(|| LoadModelError::InvalidBundle("truncated header".into()))?;
+    let (header, weights) = rest.split_at(len);
+
+    // The version is checked first, since the rest of the header may have changed with it
+    #[derive(Deserialize)]
+    struct Version {
+        format_version: u32,
+    }
+    let Version { format_version } = serde_json::from_slice(header)?;
+    if format_version != BUNDLE_FORMAT_VERSION {
+        return Err(LoadModelError::VersionMismatch {
+            expected: BUNDLE_FORMAT_VERSION,
+            found: format_version,
+        });
+    }
+    Ok((serde_json::from_slice(header)?, weights))
+}
+
+/// A stable hash of a config, to tell whether two models were trained with the same config. Keys
+/// are sorted and whitespace and comments are ignored, so only the values matter.
+pub fn config_hash(config: &serde_json::Value) -> String {
+    // 64-bit FNV-1a, which unlike the hasher of the standard library is the same on every version
+    let hash = config
+        .to_string()
+        .bytes()
+        .fold(0xcbf29ce484222325u64, |hash, byte| {
+            (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+        });
+    format!("{hash:016x}")
+}
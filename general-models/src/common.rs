@@ -3,7 +3,10 @@ use std::{fmt::Debug, marker::PhantomData};
 use burn::{
     Tensor,
     config::Config,
-    module::{AutodiffModule, ConstantRecord, Module, ModuleDisplay, ModuleDisplayDefault},
+    module::{
+        AutodiffModule, ConstantRecord, Module, ModuleDisplay, ModuleDisplayDefault, ModuleMapper,
+        Param,
+    },
     nn::{
         BatchNorm, BatchNormConfig, GroupNorm, GroupNormConfig, Initializer, InstanceNorm,
         InstanceNormConfig, LayerNorm, LayerNormConfig, RmsNorm, RmsNormConfig,
@@ -327,11 +330,110 @@ impl<T> Optional<T> {
     }
 }
 
+/// The initializer of the weights of a layer, mirroring the initializers of PyTorch.
+///
+/// The gains are applied on top of the standard deviation computed from the fans, so the default
+/// Kaiming gain of `sqrt(2)` matches `kaiming_normal_` with a ReLU.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum InitializerConfig {
+    KaimingNormal {
+        #[serde(default = "default_kaiming_gain")]
+        gain: f64,
+        #[serde(default)]
+        fan_out_only: bool,
+    },
+    KaimingUniform {
+        #[serde(default = "default_kaiming_gain")]
+        gain: f64,
+        #[serde(default)]
+        fan_out_only: bool,
+    },
+    XavierNormal {
+        #[serde(default = "default_gain")]
+        gain: f64,
+    },
+    XavierUniform {
+        #[serde(default = "default_gain")]
+        gain: f64,
+    },
+    Orthogonal {
+        #[serde(default = "default_gain")]
+        gain: f64,
+    },
+    Normal {
+        #[serde(default)]
+        mean: f64,
+        std: f64,
+    },
+    Uniform {
+        min: f64,
+        max: f64,
+    },
+    Constant {
+        value: f64,
+    },
+    Zeros,
+}
+
+impl InitializerConfig {
+    pub fn init(self) -> Initializer {
+        match self {
+            InitializerConfig::KaimingNormal { gain, fan_out_only } => {
+                Initializer::KaimingNormal { gain, fan_out_only }
+            }
+            InitializerConfig::KaimingUniform { gain, fan_out_only } => {
+                Initializer::KaimingUniform { gain, fan_out_only }
+            }
+            InitializerConfig::XavierNormal { gain } => Initializer::XavierNormal { gain },
+            InitializerConfig::XavierUniform { gain } => Initializer::XavierUniform { gain },
+            InitializerConfig::Orthogonal { gain } => Initializer::Orthogonal { gain },
+            InitializerConfig::Normal { mean, std } => Initializer::Normal { mean, std },
+            InitializerConfig::Uniform { min, max } => Initializer::Uniform { min, max },
+            InitializerConfig::Constant { value } => Initializer::Constant { value },
+            InitializerConfig::Zeros => Initializer::Zeros,
+        }
+    }
+}
+
+impl Config for InitializerConfig {}
+
+/// Replaces every bias of `module` with zeros when `initializer` is orthogonal, as orthogonal
+/// initialization only makes sense for weights with at least two dimensions.
+pub(crate) fn orthogonal_biases<B: Backend, M: Module<B>>(
+    module: M,
+    initializer: &Initializer,
+) -> M {
+    struct ZeroBiases;
+
+    impl<B: Backend> ModuleMapper<B> for ZeroBiases {
+        fn map_float<const D: usize>(&mut self, param: Param<Tensor<B, D>>) -> Param<Tensor<B, D>> {
+            if D != 1 {
+                return param;
+            }
+            // The parameter is replaced without being initialized, as that would panic
+            Initializer::Zeros.init(param.lazy_shape(), &param.lazy_device())
+        }
+    }
+
+    match initializer {
+        Initializer::Orthogonal { .. } => module.map(&mut ZeroBiases),
+        _ => module,
+    }
+}
+
+/// Initializes the norm and activation of a layer, along with the initializer of its weights.
+///
+/// An explicit `initializer` takes precedence over `default_initializer`. Without either, the
+/// initializer is picked from the activation, scaled by `default_weights_gain` if given.
+#[allow(clippy::too_many_arguments)]
 pub(crate) fn handle_norm_activation<B: Backend>(
     norm: Optional<NormConfig>,
     activation: Optional<ActivationConfig>,
+    initializer: Option<InitializerConfig>,
     default_norm: &Option<NormConfig>,
     default_activation: &Option<ActivationConfig>,
+    default_initializer: &Option<InitializerConfig>,
     default_weights_gain: Option<f64>,
     input_size: usize,
     device: &B::Device,
@@ -344,6 +446,10 @@ pub(crate) fn handle_norm_activation<B: Backend>(
         .resolve(|| default_activation.clone())
         .map(|x| x.init(device));
 
+    if let Some(initializer) = initializer.or(*default_initializer) {
+        return (norm, activation, initializer.init());
+    }
+
     let init = match &activation {
         Some(Activation::Gelu(_) | Activation::Relu(_) | Activation::PRelu(_)) => {
             Initializer::KaimingNormal {
@@ -375,3 +481,5 @@ pub(crate) fn handle_norm_activation<B: Backend>(
 default_f!(default_epsilon, f64, 1e-5);
 default_f!(default_momentum, f64, 0.1);
 default_f!(default_affine, bool, true);
+default_f!(default_kaiming_gain, f64, 2.0f64.sqrt());
+default_f!(default_gain, f64, 1.0);
@@ -1,2 +1,5 @@
 pub mod autoencoder;
+pub mod classifier;
+pub mod ensemble;
 pub mod image;
+pub mod sequence;
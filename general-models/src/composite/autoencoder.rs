@@ -4,9 +4,18 @@ use burn::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::{Init, SimpleInfer, SimpleTrain, common::PhantomBackend};
+use crate::{
+    Init, SimpleInfer, SimpleTrain,
+    common::PhantomBackend,
+    error::{ExportError, ShapeError},
+    onnx::{OnnxGraph, OnnxValue, ToOnnx},
+    quantize::{ActivationRanges, Quantize},
+    shape::{InferShape, LayerShape, infer_nested},
+};
 
+pub mod cvae;
 pub mod vae;
+pub mod vq;
 
 #[derive(Debug, Module)]
 pub struct AutoEncoderModel<B: Backend, E, D> {
@@ -48,6 +57,29 @@ macro_rules! impls {
                 self.decoder.train(self.encoder.train(tensor))
             }
         }
+
+        /// Only the encoder is quantized, the decoder stays in f32.
+        impl<B, E, D> Quantize<B, $input, $input> for AutoEncoderModel<B, E, D>
+        where
+            B: Backend,
+            E: Quantize<B, $input, 2> + ModuleDisplay,
+            E::Quantized: ModuleDisplay,
+            D: SimpleInfer<B, 2, $input> + ModuleDisplay + Clone,
+        {
+            type Quantized = AutoEncoderModel<B, E::Quantized, D>;
+
+            fn observe(
+                &self,
+                tensor: Tensor<B, $input>,
+                ranges: &mut ActivationRanges,
+            ) -> Tensor<B, $input> {
+                self.decoder.infer(self.encoder.observe(tensor, ranges))
+            }
+
+            fn quantize(&self, ranges: &mut ActivationRanges) -> Self::Quantized {
+                AutoEncoderModel::new(self.encoder.quantize(ranges), self.decoder.clone())
+            }
+        }
     };
 }
 
@@ -55,6 +87,13 @@ impls!(2);
 impls!(3);
 impls!(4);
 
+impl<B: Backend, E: ToOnnx, D: ToOnnx> ToOnnx for AutoEncoderModel<B, E, D> {
+    fn to_onnx(&self, graph: &mut OnnxGraph, input: OnnxValue) -> Result<OnnxValue, ExportError> {
+        let latent = self.encoder.to_onnx(graph, input)?;
+        self.decoder.to_onnx(graph, latent)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Copy)]
 pub struct AutoEncoderModelConfig<E, D> {
     pub encoder: E,
@@ -75,3 +114,12 @@ where
         }
     }
 }
+
+impl<E: InferShape, D: InferShape> InferShape for AutoEncoderModelConfig<E, D> {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        let mut shapes = vec![];
+        let latent_shape = infer_nested(&mut shapes, "encoder", &self.encoder, input_shape)?;
+        infer_nested(&mut shapes, "decoder", &self.decoder, &latent_shape)?;
+        Ok(shapes)
+    }
+}
@@ -0,0 +1,200 @@
+use burn::{module::ModuleDisplay, prelude::*, tensor::Distribution};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Init, SimpleInfer, SimpleTrain,
+    composite::autoencoder::vae::{VariationalEncoderModel, VariationalEncoderModelConfig},
+    error::{ExportError, ShapeError},
+    linear::{LinearModel, LinearModelConfig},
+    onnx::{Attribute, OnnxGraph, OnnxValue, ToOnnx},
+    shape::{InferShape, LayerShape, expect_rank, infer_nested},
+};
+
+/// A variational autoencoder whose encoder and decoder also see a condition, such as a one-hot
+/// class label or a small conditioning vector.
+///
+/// The condition is embedded by `condition`, then concatenated to the output of the encoder's
+/// `model` before the mean and log variance, and to the latent before the decoder.
+#[derive(Module, Debug)]
+pub struct ConditionalVaeModel<B: Backend, M, D> {
+    pub encoder: VariationalEncoderModel<B, M>,
+    pub decoder: D,
+    pub condition: LinearModel<B>,
+}
+
+impl<B: Backend, M, D> ConditionalVaeModel<B, M, D> {
+    pub fn get_condition_size(&self) -> usize {
+        self.condition.get_input_size()
+    }
+
+    pub fn get_latent_size(&self) -> usize {
+        self.encoder.mean.get_output_size()
+    }
+
+    /// Returns the reconstruction along with the mean and log variance of the latent.
+    pub fn train<const N: usize>(
+        &self,
+        tensor: Tensor<B, N>,
+        condition: Tensor<B, 2>,
+    ) -> (Tensor<B, N>, Tensor<B, 2>, Tensor<B, 2>)
+    where
+        M: SimpleTrain<B, N, 2>,
+        D: SimpleTrain<B, 2, N>,
+    {
+        let condition = self.condition.train(condition);
+        let latent = self.encoder.model.train(tensor);
+        let latent = Tensor::cat(vec![latent, condition.clone()], 1);
+        let mean = self.encoder.mean.train(latent.clone());
+        let logvar = self.encoder.logvar.train(latent);
+        let sampled = self.encoder.reparameterize(mean.clone(), logvar.clone());
+        let reconstructed = self.decoder.train(Tensor::cat(vec![sampled, condition], 1));
+        (reconstructed, mean, logvar)
+    }
+
+    /// Reconstructs `tensor` from the mean of its latent.
+    pub fn infer<const N: usize>(
+        &self,
+        tensor: Tensor<B, N>,
+        condition: Tensor<B, 2>,
+    ) -> Tensor<B, N>
+    where
+        M: SimpleInfer<B, N, 2> + ModuleDisplay,
+        D: SimpleInfer<B, 2, N> + ModuleDisplay,
+    {
+        let condition = self.condition.infer(condition);
+        let latent = self.encoder.model.infer(tensor);
+        let mean = self
+            .encoder
+            .mean
+            .infer(Tensor::cat(vec![latent, condition.clone()], 1));
+        self.decoder.infer(Tensor::cat(vec![mean, condition], 1))
+    }
+
+    /// Decodes `latent` under `condition`.
+    pub fn decode<const N: usize>(
+        &self,
+        latent: Tensor<B, 2>,
+        condition: Tensor<B, 2>,
+    ) -> Tensor<B, N>
+    where
+        D: SimpleInfer<B, 2, N> + ModuleDisplay,
+    {
+        let condition = self.condition.infer(condition);
+        self.decoder.infer(Tensor::cat(vec![latent, condition], 1))
+    }
+
+    /// Generates one sample per row of `condition` from a latent drawn from the prior.
+    pub fn generate<const N: usize>(&self, condition: Tensor<B, 2>) -> Tensor<B, N>
+    where
+        D: SimpleInfer<B, 2, N> + ModuleDisplay,
+    {
+        let [batch_size, _] = condition.dims();
+        let latent = Tensor::random(
+            [batch_size, self.get_latent_size()],
+            Distribution::Normal(0.0, 1.0),
+            &condition.device(),
+        );
+        self.decode(latent, condition)
+    }
+
+    /// Adds the reconstruction of [`Self::infer`] to `graph`, which takes the condition as a
+    /// second input.
+    pub fn to_onnx(
+        &self,
+        graph: &mut OnnxGraph,
+        input: OnnxValue,
+        condition: OnnxValue,
+    ) -> Result<OnnxValue, ExportError>
+    where
+        M: ToOnnx,
+        D: ToOnnx,
+    {
+        let condition = self.condition.to_onnx(graph, condition)?;
+        let concat = |graph: &mut OnnxGraph, latent: OnnxValue| {
+            let name = graph.node(
+                "Concat",
+                &[&latent.name, &condition.name],
+                &[("axis", Attribute::Int(1))],
+            );
+            OnnxValue::new(name, vec![latent.shape[0] + condition.shape[0]])
+        };
+        let latent = self.encoder.model.to_onnx(graph, input)?;
+        let latent = concat(graph, latent);
+        let mean = self.encoder.mean.to_onnx(graph, latent)?;
+        let mean = concat(graph, mean);
+        self.decoder.to_onnx(graph, mean)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ConditionalVaeModelConfig<M, D> {
+    /// The input size of `encoder.mean` and `encoder.logvar` includes the output size of
+    /// `condition`.
+    pub encoder: VariationalEncoderModelConfig<M>,
+    /// The input size of the decoder includes the output size of `condition`.
+    pub decoder: D,
+    /// Embeds the condition. Its input size is the number of classes for one-hot labels.
+    pub condition: LinearModelConfig,
+}
+
+impl<B, M, D, T1, T2> Init<B, ConditionalVaeModel<B, T1, T2>> for ConditionalVaeModelConfig<M, D>
+where
+    B: Backend,
+    M: Init<B, T1>,
+    D: Init<B, T2>,
+{
+    fn init(self, device: &<B as Backend>::Device) -> ConditionalVaeModel<B, T1, T2> {
+        ConditionalVaeModel {
+            encoder: self.encoder.init(device),
+            decoder: self.decoder.init(device),
+            condition: self.condition.init(device),
+        }
+    }
+}
+
+/// Appends the embedded condition to a rank 1 shape.
+fn concat_condition(
+    layer: &str,
+    shape: &[usize],
+    condition_size: usize,
+) -> Result<Vec<usize>, ShapeError> {
+    let [features] = expect_rank(layer, shape)?;
+    Ok(vec![features + condition_size])
+}
+
+impl<M: InferShape, D: InferShape> InferShape for ConditionalVaeModelConfig<M, D> {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        let mut shapes = vec![];
+        let [condition_size] = expect_rank(
+            "condition",
+            &infer_nested(
+                &mut shapes,
+                "condition",
+                &self.condition,
+                &[self.condition.input_size],
+            )?,
+        )?;
+        let latent_shape = infer_nested(
+            &mut shapes,
+            "encoder.model",
+            &self.encoder.model,
+            input_shape,
+        )?;
+        let latent_shape = concat_condition("encoder.model", &latent_shape, condition_size)?;
+        infer_nested(
+            &mut shapes,
+            "encoder.logvar",
+            &self.encoder.logvar,
+            &latent_shape,
+        )?;
+        let mean_shape = infer_nested(
+            &mut shapes,
+            "encoder.mean",
+            &self.encoder.mean,
+            &latent_shape,
+        )?;
+        let mean_shape = concat_condition("encoder.mean", &mean_shape, condition_size)?;
+        infer_nested(&mut shapes, "decoder", &self.decoder, &mean_shape)?;
+        Ok(shapes)
+    }
+}
@@ -3,7 +3,10 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     Init, SimpleInfer, SimpleTrain,
+    error::{ExportError, ShapeError},
     linear::{LinearModel, LinearModelConfig},
+    onnx::{OnnxGraph, OnnxValue, ToOnnx},
+    shape::{InferShape, LayerShape, infer_nested},
 };
 
 #[derive(Module, Debug)]
@@ -23,6 +26,14 @@ where
     }
 }
 
+/// Exports the deterministic encoder, whose output is the mean of the latent.
+impl<B: Backend, M: ToOnnx> ToOnnx for VariationalEncoderModel<B, M> {
+    fn to_onnx(&self, graph: &mut OnnxGraph, input: OnnxValue) -> Result<OnnxValue, ExportError> {
+        let latent = self.model.to_onnx(graph, input)?;
+        self.mean.to_onnx(graph, latent)
+    }
+}
+
 impl<B: Backend, M> VariationalEncoderModel<B, M>
 where
     B: Backend,
@@ -70,3 +81,14 @@ where
         }
     }
 }
+
+impl<M: InferShape> InferShape for VariationalEncoderModelConfig<M> {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        let mut shapes = vec![];
+        let latent_shape = infer_nested(&mut shapes, "model", &self.model, input_shape)?;
+        infer_nested(&mut shapes, "logvar", &self.logvar, &latent_shape)?;
+        // The mean goes last as it is the output of the encoder
+        infer_nested(&mut shapes, "mean", &self.mean, &latent_shape)?;
+        Ok(shapes)
+    }
+}
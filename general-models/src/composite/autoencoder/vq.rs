@@ -0,0 +1,268 @@
+use burn::{
+    module::{ModuleDisplay, Param},
+    nn::loss::{MseLoss, Reduction},
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Init, SimpleInfer, SimpleTrain,
+    common::InitializerConfig,
+    error::{ExportError, ShapeError},
+    onnx::{Attribute, OnnxGraph, OnnxValue, ToOnnx},
+    shape::{InferShape, LayerShape, expect_divisible, expect_rank, infer_nested},
+};
+
+/// Snaps every `code_size` chunk of a latent to the nearest vector of a learned codebook.
+#[derive(Module, Debug)]
+pub struct VectorQuantizer<B: Backend> {
+    /// `[num_codes, code_size]`
+    pub codebook: Param<Tensor<B, 2>>,
+}
+
+/// The output of a [`VectorQuantizer`] on a `[batch_size, features]` latent.
+#[derive(Debug, Clone)]
+pub struct Quantized<B: Backend> {
+    pub latent: Tensor<B, 2>,
+    pub quantized: Tensor<B, 2>,
+    /// The index of the codebook vector of each chunk, `[batch_size, features / code_size]`.
+    pub codes: Tensor<B, 2, Int>,
+}
+
+impl<B: Backend> Quantized<B> {
+    /// The quantized latent, with gradients copied straight through to the unquantized latent.
+    pub fn straight_through(&self) -> Tensor<B, 2> {
+        self.latent.clone() + (self.quantized.clone() - self.latent.clone()).detach()
+    }
+
+    /// Moves the codebook vectors towards the latents that picked them.
+    pub fn codebook_loss(&self) -> Tensor<B, 1> {
+        MseLoss::new().forward(
+            self.quantized.clone(),
+            self.latent.clone().detach(),
+            Reduction::Mean,
+        )
+    }
+
+    /// Keeps the latents close to the codebook vectors they picked.
+    pub fn commitment_loss(&self) -> Tensor<B, 1> {
+        MseLoss::new().forward(
+            self.latent.clone(),
+            self.quantized.clone().detach(),
+            Reduction::Mean,
+        )
+    }
+}
+
+impl<B: Backend> VectorQuantizer<B> {
+    pub fn get_num_codes(&self) -> usize {
+        self.codebook.dims()[0]
+    }
+
+    pub fn get_code_size(&self) -> usize {
+        self.codebook.dims()[1]
+    }
+
+    pub fn quantize(&self, latent: Tensor<B, 2>) -> Quantized<B> {
+        let [batch_size, features] = latent.dims();
+        let code_size = self.get_code_size();
+        let vectors = latent
+            .clone()
+            .reshape([batch_size * features / code_size, code_size]);
+        let codebook = self.codebook.val();
+
+        // |z - e|^2 = |z|^2 - 2 z.e + |e|^2
+        let distances = vectors.clone().powf_scalar(2.0).sum_dim(1)
+            - vectors.matmul(codebook.clone().transpose()).mul_scalar(2.0)
+            + codebook.clone().powf_scalar(2.0).sum_dim(1).transpose();
+        let codes = distances.argmin(1).flatten::<1>(0, 1);
+        let quantized = codebook
+            .select(0, codes.clone())
+            .reshape([batch_size, features]);
+
+        Quantized {
+            latent,
+            quantized,
+            codes: codes.reshape([batch_size, features / code_size]),
+        }
+    }
+}
+
+impl<B: Backend> ToOnnx for VectorQuantizer<B> {
+    fn to_onnx(&self, graph: &mut OnnxGraph, input: OnnxValue) -> Result<OnnxValue, ExportError> {
+        let code_size = self.get_code_size();
+        let features = input.shape.iter().product::<usize>();
+        let vectors = graph.reshape(&input, &[features / code_size, code_size]);
+        let codebook = self.codebook.val();
+
+        // |z|^2 is the same for every code, so only -2 z.e + |e|^2 decides the nearest one
+        let transposed = graph.tensor(codebook.clone().transpose().mul_scalar(-2.0));
+        let products = graph.node("MatMul", &[&vectors.name, &transposed], &[]);
+        let norms = graph.tensor(codebook.clone().powf_scalar(2.0).sum_dim(1).transpose());
+        let distances = graph.node("Add", &[&products, &norms], &[]);
+        let codes = graph.node(
+            "ArgMin",
+            &[&distances],
+            &[
+                ("axis", Attribute::Int(-1)),
+                ("keepdims", Attribute::Int(0)),
+            ],
+        );
+        let codebook = graph.tensor(codebook);
+        let quantized = graph.node("Gather", &[&codebook, &codes], &[]);
+        Ok(graph.reshape(&OnnxValue::new(quantized, vectors.shape), &input.shape))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct VectorQuantizerConfig {
+    pub num_codes: usize,
+    pub code_size: usize,
+    /// Defaults to a uniform distribution within `±1 / num_codes`.
+    pub initializer: Option<InitializerConfig>,
+}
+
+impl VectorQuantizerConfig {
+    pub fn init<B: Backend>(self, device: &B::Device) -> VectorQuantizer<B> {
+        let bound = 1.0 / self.num_codes as f64;
+        let initializer = self
+            .initializer
+            .unwrap_or(InitializerConfig::Uniform {
+                min: -bound,
+                max: bound,
+            })
+            .init();
+        VectorQuantizer {
+            codebook: initializer.init_with(
+                [self.num_codes, self.code_size],
+                Some(self.code_size),
+                Some(self.num_codes),
+                device,
+            ),
+        }
+    }
+}
+
+/// An encoder with a discrete bottleneck, for use in a VQ-VAE.
+#[derive(Module, Debug)]
+pub struct VectorQuantizedEncoderModel<B: Backend, M> {
+    pub model: M,
+    pub quantizer: VectorQuantizer<B>,
+}
+
+impl<B, M, const D: usize> SimpleInfer<B, D, 2> for VectorQuantizedEncoderModel<B, M>
+where
+    B: Backend,
+    M: SimpleInfer<B, D, 2> + ModuleDisplay,
+{
+    fn forward(&self, tensor: Tensor<B, D>) -> Tensor<B, 2> {
+        self.quantizer.quantize(self.model.infer(tensor)).quantized
+    }
+}
+
+impl<B: Backend, M: ToOnnx> ToOnnx for VectorQuantizedEncoderModel<B, M> {
+    fn to_onnx(&self, graph: &mut OnnxGraph, input: OnnxValue) -> Result<OnnxValue, ExportError> {
+        let latent = self.model.to_onnx(graph, input)?;
+        self.quantizer.to_onnx(graph, latent)
+    }
+}
+
+impl<B: Backend, M> VectorQuantizedEncoderModel<B, M> {
+    pub fn train<const D: usize>(&self, tensor: Tensor<B, D>) -> Quantized<B>
+    where
+        M: SimpleTrain<B, D, 2>,
+    {
+        self.quantizer.quantize(self.model.train(tensor))
+    }
+
+    /// Returns the codes of `tensor`, `[batch_size, features / code_size]`.
+    pub fn encode<const D: usize>(&self, tensor: Tensor<B, D>) -> Tensor<B, 2, Int>
+    where
+        M: SimpleInfer<B, D, 2> + ModuleDisplay,
+    {
+        self.quantizer.quantize(self.model.infer(tensor)).codes
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct VectorQuantizedEncoderModelConfig<M> {
+    pub model: M,
+    pub quantizer: VectorQuantizerConfig,
+}
+
+impl<B, M, T> Init<B, VectorQuantizedEncoderModel<B, T>> for VectorQuantizedEncoderModelConfig<M>
+where
+    B: Backend,
+    M: Init<B, T>,
+{
+    fn init(self, device: &<B as Backend>::Device) -> VectorQuantizedEncoderModel<B, T> {
+        VectorQuantizedEncoderModel {
+            model: self.model.init(device),
+            quantizer: self.quantizer.init(device),
+        }
+    }
+}
+
+impl<M: InferShape> InferShape for VectorQuantizedEncoderModelConfig<M> {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        let mut shapes = vec![];
+        let latent_shape = infer_nested(&mut shapes, "model", &self.model, input_shape)?;
+        let [features] = expect_rank("quantizer", &latent_shape)?;
+        expect_divisible("quantizer", features, self.quantizer.code_size)?;
+        shapes.push(LayerShape {
+            params: self.quantizer.num_codes * self.quantizer.code_size,
+            // Every chunk is compared against every codebook vector
+            macs: features * self.quantizer.num_codes,
+            ..LayerShape::new("quantizer", latent_shape)
+        });
+        Ok(shapes)
+    }
+}
+
+/// Counts how often each codebook vector is picked, to spot codebook collapse.
+#[derive(Debug, Clone)]
+pub struct CodebookUsage {
+    counts: Vec<usize>,
+}
+
+impl CodebookUsage {
+    pub fn new(num_codes: usize) -> Self {
+        Self {
+            counts: vec![0; num_codes],
+        }
+    }
+
+    pub fn update<B: Backend>(&mut self, codes: Tensor<B, 2, Int>) {
+        for code in codes.into_data().iter::<i64>() {
+            self.counts[code as usize] += 1;
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.counts.fill(0);
+    }
+
+    /// The number of codebook vectors picked at least once.
+    pub fn used_codes(&self) -> usize {
+        self.counts.iter().filter(|&&count| count > 0).count()
+    }
+
+    /// The exponential of the entropy of the code distribution, which ranges from 1 when a single
+    /// code is used to `num_codes` when all codes are used equally.
+    pub fn perplexity(&self) -> f64 {
+        let total: usize = self.counts.iter().sum();
+        if total == 0 {
+            return 0.0;
+        }
+        let entropy: f64 = self
+            .counts
+            .iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f64 / total as f64;
+                -p * p.ln()
+            })
+            .sum();
+        entropy.exp()
+    }
+}
@@ -0,0 +1,124 @@
+use burn::{
+    module::{Module, ModuleDisplay},
+    prelude::*,
+    tensor::activation::softmax,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Init, SimpleInfer, SimpleTrain,
+    common::PhantomBackend,
+    error::{ExportError, ShapeError},
+    onnx::{Attribute, OnnxGraph, OnnxValue, ToOnnx},
+    quantize::{ActivationRanges, Quantize},
+    shape::{InferShape, LayerShape, expect_rank, last_shape},
+};
+
+pub use crate::metrics::ClassificationStats;
+
+/// A model whose output is a probability for each class, from a softmax over the output of
+/// `model`.
+///
+/// Training outputs the logits rather than the probabilities, so that the softmax can be fused
+/// into the cross-entropy loss.
+#[derive(Debug, Module)]
+pub struct ClassifierModel<B: Backend, M> {
+    pub model: M,
+    _phantom: PhantomBackend<B>,
+}
+
+impl<B: Backend, M> ClassifierModel<B, M> {
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            _phantom: Default::default(),
+        }
+    }
+
+    /// The logits of each class, `[batch_size, num_classes]`.
+    pub fn logits<const N: usize>(&self, tensor: Tensor<B, N>) -> Tensor<B, 2>
+    where
+        M: SimpleInfer<B, N, 2> + ModuleDisplay,
+    {
+        self.model.infer(tensor)
+    }
+
+    /// The most likely class of each input, `[batch_size]`.
+    pub fn predict<const N: usize>(&self, tensor: Tensor<B, N>) -> Tensor<B, 1, Int>
+    where
+        M: SimpleInfer<B, N, 2> + ModuleDisplay,
+    {
+        self.logits(tensor).argmax(1).flatten(0, 1)
+    }
+}
+
+impl<B, M, const N: usize> SimpleInfer<B, N, 2> for ClassifierModel<B, M>
+where
+    B: Backend,
+    M: SimpleInfer<B, N, 2> + ModuleDisplay,
+{
+    fn forward(&self, tensor: Tensor<B, N>) -> Tensor<B, 2> {
+        softmax(self.logits(tensor), 1)
+    }
+}
+
+impl<B, M, const N: usize> SimpleTrain<B, N, 2> for ClassifierModel<B, M>
+where
+    B: Backend,
+    M: SimpleTrain<B, N, 2>,
+{
+    fn forward(&self, tensor: Tensor<B, N>) -> Tensor<B, 2> {
+        self.model.train(tensor)
+    }
+}
+
+impl<B, M, const N: usize> Quantize<B, N, 2> for ClassifierModel<B, M>
+where
+    B: Backend,
+    M: Quantize<B, N, 2> + ModuleDisplay,
+    M::Quantized: ModuleDisplay,
+{
+    type Quantized = ClassifierModel<B, M::Quantized>;
+
+    fn observe(&self, tensor: Tensor<B, N>, ranges: &mut ActivationRanges) -> Tensor<B, 2> {
+        softmax(self.model.observe(tensor, ranges), 1)
+    }
+
+    fn quantize(&self, ranges: &mut ActivationRanges) -> Self::Quantized {
+        ClassifierModel::new(self.model.quantize(ranges))
+    }
+}
+
+impl<B: Backend, M: ToOnnx> ToOnnx for ClassifierModel<B, M> {
+    fn to_onnx(&self, graph: &mut OnnxGraph, input: OnnxValue) -> Result<OnnxValue, ExportError> {
+        let logits = self.model.to_onnx(graph, input)?;
+        Ok(graph.unary("Softmax", &logits, &[("axis", Attribute::Int(-1))]))
+    }
+}
+
+/// The config of a [`ClassifierModel`] is the config of its model, whose output size is the
+/// number of classes.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(transparent)]
+pub struct ClassifierModelConfig<M> {
+    pub model: M,
+}
+
+impl<B, M, T> Init<B, ClassifierModel<B, T>> for ClassifierModelConfig<M>
+where
+    B: Backend,
+    M: Init<B, T>,
+{
+    fn init(self, device: &<B as Backend>::Device) -> ClassifierModel<B, T> {
+        ClassifierModel::new(self.model.init(device))
+    }
+}
+
+impl<M: InferShape> InferShape for ClassifierModelConfig<M> {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        let mut shapes = self.model.infer_shapes(input_shape)?;
+        let [num_classes] = expect_rank("softmax", &last_shape(&shapes, input_shape))?;
+        shapes.push(LayerShape::new("softmax", vec![num_classes]));
+        Ok(shapes)
+    }
+}
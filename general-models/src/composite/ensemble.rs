@@ -0,0 +1,221 @@
+use std::path::Path;
+
+use burn::{
+    module::{Ignored, Module, Param},
+    prelude::*,
+    record::CompactRecorder,
+    tensor::activation::softmax,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Init, SimpleInfer, SimpleTrain,
+    bundle::load_bundle_weights,
+    error::{LoadModelError, ShapeError, ShapeErrorKind},
+    shape::{InferShape, LayerShape, infer_nested, last_shape},
+    weights::load_safetensors,
+};
+
+/// How an [`EnsembleModel`] combines the outputs of its members.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EnsembleCombination {
+    #[default]
+    Mean,
+    /// The median of each element, which ignores a member that is far off from the others.
+    Median,
+    /// A weighted mean whose weights are learned, starting out equal.
+    Weighted,
+}
+
+/// Several models of the same type, such as the same config trained with different seeds, whose
+/// outputs are combined into one.
+#[derive(Debug, Module)]
+pub struct EnsembleModel<B: Backend, M> {
+    pub members: Vec<M>,
+    combination: Ignored<EnsembleCombination>,
+    /// The logits of the weights of the members, if they are learned.
+    weights: Option<Param<Tensor<B, 1>>>,
+}
+
+impl<B: Backend, M> EnsembleModel<B, M> {
+    pub fn new(members: Vec<M>, combination: EnsembleCombination, device: &B::Device) -> Self {
+        assert!(
+            !members.is_empty(),
+            "Expected an ensemble of at least one model"
+        );
+        let weights = (combination == EnsembleCombination::Weighted)
+            .then(|| Param::from_tensor(Tensor::zeros([members.len()], device)));
+        Self {
+            members,
+            combination: Ignored(combination),
+            weights,
+        }
+    }
+
+    /// Builds an ensemble with a member for each checkpoint, which are either saved during
+    /// training, such as `model-9.mpk` in an artifact dir, or exported to safetensors or a bundle.
+    pub fn load<C>(
+        config: C,
+        checkpoints: impl IntoIterator<Item = impl AsRef<Path>>,
+        combination: EnsembleCombination,
+        device: &B::Device,
+    ) -> Result<Self, LoadModelError>
+    where
+        C: Init<B, M> + Clone,
+        M: Module<B>,
+    {
+        let members = checkpoints
+            .into_iter()
+            .map(|path| {
+                let path = path.as_ref();
+                let model = config.clone().init(device);
+                match path.extension().and_then(|x| x.to_str()) {
+                    Some("safetensors") => Ok(load_safetensors(model, path)?),
+                    Some("bundle") => load_bundle_weights(model, path),
+                    _ => Ok(model.load_file(path, &CompactRecorder::new(), device)?),
+                }
+            })
+            .collect::<Result<_, LoadModelError>>()?;
+        Ok(Self::new(members, combination, device))
+    }
+
+    pub fn get_num_members(&self) -> usize {
+        self.members.len()
+    }
+
+    pub fn get_combination(&self) -> EnsembleCombination {
+        *self.combination
+    }
+
+    /// Stops the members from being trained, so that only the weights of a
+    /// [`EnsembleCombination::Weighted`] ensemble are learned.
+    pub fn freeze_members(mut self) -> Self
+    where
+        M: Module<B>,
+    {
+        self.members = self.members.into_iter().map(Module::no_grad).collect();
+        self
+    }
+
+    /// Flattens and stacks the outputs of the members into `[num_members, output_size]`, along
+    /// with the shape of a single output.
+    fn stack<const N: usize>(
+        outputs: impl Iterator<Item = Tensor<B, N>>,
+    ) -> (Tensor<B, 2>, [usize; N]) {
+        let mut shape = None;
+        let outputs = outputs
+            .map(|output| {
+                shape.get_or_insert(output.dims());
+                output.reshape([-1])
+            })
+            .collect();
+        (
+            Tensor::stack(outputs, 0),
+            shape.expect("Expected an ensemble of at least one model"),
+        )
+    }
+
+    fn combine<const N: usize>(&self, stacked: Tensor<B, 2>, shape: [usize; N]) -> Tensor<B, N> {
+        let combined: Tensor<B, 2> = match *self.combination {
+            EnsembleCombination::Mean => stacked.mean_dim(0),
+            EnsembleCombination::Median => {
+                // The mean of the two middle members if there is an even number of them
+                let num_members = self.members.len();
+                stacked
+                    .sort(0)
+                    .narrow(0, (num_members - 1) / 2, 2 - num_members % 2)
+                    .mean_dim(0)
+            }
+            EnsembleCombination::Weighted => {
+                let weights = self
+                    .weights
+                    .as_ref()
+                    .expect("Expected a weighted ensemble to have weights");
+                (stacked * softmax(weights.val(), 0).unsqueeze_dim(1)).sum_dim(0)
+            }
+        };
+        combined.reshape(shape)
+    }
+
+    /// Runs the model like [`SimpleInfer::infer`], along with the spread of the members as an
+    /// estimate of the uncertainty of the output: the standard deviation of each element.
+    pub fn infer_with_spread<const N_I: usize, const N_O: usize>(
+        &self,
+        tensor: Tensor<B, N_I>,
+    ) -> (Tensor<B, N_O>, Tensor<B, N_O>)
+    where
+        M: SimpleInfer<B, N_I, N_O>,
+    {
+        let (stacked, shape) = Self::stack(self.members.iter().map(|x| x.infer(tensor.clone())));
+        let spread = stacked.clone().var_bias(0).sqrt().reshape(shape);
+        (self.combine(stacked, shape), spread)
+    }
+}
+
+impl<B, M, const N_I: usize, const N_O: usize> SimpleInfer<B, N_I, N_O> for EnsembleModel<B, M>
+where
+    B: Backend,
+    M: SimpleInfer<B, N_I, N_O>,
+{
+    fn forward(&self, tensor: Tensor<B, N_I>) -> Tensor<B, N_O> {
+        let (stacked, shape) = Self::stack(self.members.iter().map(|x| x.infer(tensor.clone())));
+        self.combine(stacked, shape)
+    }
+}
+
+impl<B, M, const N_I: usize, const N_O: usize> SimpleTrain<B, N_I, N_O> for EnsembleModel<B, M>
+where
+    B: Backend,
+    M: SimpleTrain<B, N_I, N_O>,
+{
+    fn forward(&self, tensor: Tensor<B, N_I>) -> Tensor<B, N_O> {
+        let (stacked, shape) = Self::stack(self.members.iter().map(|x| x.train(tensor.clone())));
+        self.combine(stacked, shape)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct EnsembleModelConfig<M> {
+    /// The config shared by every member.
+    pub model: M,
+    pub num_members: usize,
+    #[serde(default)]
+    pub combination: EnsembleCombination,
+}
+
+impl<B, M, T> Init<B, EnsembleModel<B, T>> for EnsembleModelConfig<M>
+where
+    B: Backend,
+    M: Init<B, T> + Clone,
+{
+    fn init(self, device: &<B as Backend>::Device) -> EnsembleModel<B, T> {
+        let members = (0..self.num_members)
+            .map(|_| self.model.clone().init(device))
+            .collect();
+        EnsembleModel::new(members, self.combination, device)
+    }
+}
+
+impl<M: InferShape> InferShape for EnsembleModelConfig<M> {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        if self.num_members == 0 {
+            return Err(ShapeError::new("members", ShapeErrorKind::NoMembers));
+        }
+        let mut shapes = vec![];
+        for i in 0..self.num_members {
+            infer_nested(
+                &mut shapes,
+                &format!("members.{i}"),
+                &self.model,
+                input_shape,
+            )?;
+        }
+        let mut combination = LayerShape::new("combination", last_shape(&shapes, input_shape));
+        if self.combination == EnsembleCombination::Weighted {
+            combination.params = self.num_members;
+        }
+        shapes.push(combination);
+        Ok(shapes)
+    }
+}
@@ -10,8 +10,18 @@ use serde::{Deserialize, Serialize};
 
 use crate::{
     Init, SimpleInfer, SimpleTrain,
-    conv::{Conv2dModel, Conv2dModelConfig, ConvTranspose2dModel, ConvTranspose2dModelConfig},
-    linear::{LinearModel, LinearModelConfig},
+    conv::{
+        Conv2dModel, Conv2dModelConfig, ConvTranspose2dModel, ConvTranspose2dModelConfig,
+        QuantizedConv2dModel,
+    },
+    error::{ExportError, ShapeError},
+    linear::{LinearModel, LinearModelConfig, QuantizedLinearModel},
+    onnx::{Attribute, OnnxGraph, OnnxValue, ToOnnx},
+    quantize::{ActivationRanges, Quantize},
+    shape::{
+        InferShape, LayerShape, expect_divisible, expect_rank, expect_size, infer_nested,
+        interpolate_size,
+    },
 };
 
 #[derive(Debug, Module)]
@@ -41,10 +51,66 @@ impl<B: Backend> SimpleTrain<B, 4, 2> for Conv2dLinearModel<B> {
     }
 }
 
+impl<B: Backend> ToOnnx for Conv2dLinearModel<B> {
+    fn to_onnx(
+        &self,
+        graph: &mut OnnxGraph,
+        mut input: OnnxValue,
+    ) -> Result<OnnxValue, ExportError> {
+        input = self.conv.to_onnx(graph, input)?;
+        input = self.adaptive_avg_pooling.to_onnx(graph, input)?;
+        let size = input.shape.iter().product();
+        let name = graph.node("Flatten", &[&input.name], &[("axis", Attribute::Int(1))]);
+        self.linear.to_onnx(graph, OnnxValue::new(name, vec![size]))
+    }
+}
+
 impl<B: Backend> Conv2dLinearModel<B> {
     pub fn get_input_channels(&self) -> usize {
         self.conv.get_input_channels()
     }
+
+    pub fn get_output_size(&self) -> usize {
+        self.linear.get_output_size()
+    }
+}
+
+/// A [`Conv2dLinearModel`] with int8 weights and activations, see [`crate::quantize`].
+#[derive(Debug, Module)]
+pub struct QuantizedConv2dLinearModel<B: Backend> {
+    pub conv: QuantizedConv2dModel<B>,
+    adaptive_avg_pooling: Option<AdaptiveAvgPool2d>,
+    pub linear: QuantizedLinearModel<B>,
+}
+
+impl<B: Backend> SimpleInfer<B, 4, 2> for QuantizedConv2dLinearModel<B> {
+    fn forward(&self, mut tensor: Tensor<B, 4>) -> Tensor<B, 2> {
+        tensor = self.conv.infer(tensor);
+        if let Some(adaptive_avg_pooling) = &self.adaptive_avg_pooling {
+            tensor = adaptive_avg_pooling.forward(tensor);
+        }
+        self.linear.infer(tensor.flatten(1, 3))
+    }
+}
+
+impl<B: Backend> Quantize<B, 4, 2> for Conv2dLinearModel<B> {
+    type Quantized = QuantizedConv2dLinearModel<B>;
+
+    fn observe(&self, mut tensor: Tensor<B, 4>, ranges: &mut ActivationRanges) -> Tensor<B, 2> {
+        tensor = self.conv.observe(tensor, ranges);
+        if let Some(adaptive_avg_pooling) = &self.adaptive_avg_pooling {
+            tensor = adaptive_avg_pooling.forward(tensor);
+        }
+        self.linear.observe(tensor.flatten(1, 3), ranges)
+    }
+
+    fn quantize(&self, ranges: &mut ActivationRanges) -> QuantizedConv2dLinearModel<B> {
+        QuantizedConv2dLinearModel {
+            conv: self.conv.quantize(ranges),
+            adaptive_avg_pooling: self.adaptive_avg_pooling.clone(),
+            linear: self.linear.quantize(ranges),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -54,6 +120,28 @@ pub struct Conv2dLinearModelConfig {
     pub linear: LinearModelConfig,
 }
 
+impl InferShape for Conv2dLinearModelConfig {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        let mut shapes = vec![];
+        let shape = infer_nested(&mut shapes, "conv", &self.conv, input_shape)?;
+        let [channels, mut height, mut width] = expect_rank("conv", &shape)?;
+        if let Some(output_size) = self.adaptive_avg_pooling {
+            [height, width] = output_size;
+            shapes.push(LayerShape::new(
+                "adaptive_avg_pooling",
+                vec![channels, height, width],
+            ));
+        }
+        infer_nested(
+            &mut shapes,
+            "linear",
+            &self.linear,
+            &[channels * height * width],
+        )?;
+        Ok(shapes)
+    }
+}
+
 impl<B: Backend> Init<B, Conv2dLinearModel<B>> for Conv2dLinearModelConfig {
     fn init(self, device: &<B as Backend>::Device) -> Conv2dLinearModel<B> {
         Conv2dLinearModel {
@@ -160,6 +248,30 @@ impl<B: Backend> SimpleTrain<B, 2, 4> for LinearConvTranspose2dModel<B> {
     }
 }
 
+impl<B: Backend> ToOnnx for LinearConvTranspose2dModel<B> {
+    fn to_onnx(
+        &self,
+        graph: &mut OnnxGraph,
+        mut input: OnnxValue,
+    ) -> Result<OnnxValue, ExportError> {
+        input = self.linear.to_onnx(graph, input)?;
+        let channels = self.conv.get_input_channels();
+
+        input = if let Some(interpolate) = &self.intermediate_interpolate {
+            let aspect = self.conv_input_size[0] as f64 / self.conv_input_size[1] as f64;
+            let (width, height) = resize_to_aspect(input.shape[0] / channels, aspect);
+            let input = graph.reshape(&input, &[channels, width, height]);
+            interpolate.to_onnx(graph, input)?
+        } else {
+            let [width, height] = *self.conv_input_size;
+            graph.reshape(&input, &[channels, width, height])
+        };
+
+        input = self.conv.to_onnx(graph, input)?;
+        self.output_interpolate.to_onnx(graph, input)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LinearConvTranspose2dModelConfig {
     pub linear: LinearModelConfig,
@@ -169,6 +281,35 @@ pub struct LinearConvTranspose2dModelConfig {
     pub output_interpolate: Option<Interpolate2dConfig>,
 }
 
+impl InferShape for LinearConvTranspose2dModelConfig {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        let mut shapes = vec![];
+        let shape = infer_nested(&mut shapes, "linear", &self.linear, input_shape)?;
+        let [len] = expect_rank("linear", &shape)?;
+        let channels = self.conv.input_channels;
+        let [height, width] = self.conv_input_size;
+        if self.intermediate_interpolate.is_some() {
+            expect_divisible("intermediate_interpolate", len, channels)?;
+            shapes.push(LayerShape::new(
+                "intermediate_interpolate",
+                vec![channels, height, width],
+            ));
+        } else {
+            expect_size("conv", channels * height * width, len)?;
+        }
+        let shape = infer_nested(&mut shapes, "conv", &self.conv, &[channels, height, width])?;
+        if let Some(interpolate) = &self.output_interpolate {
+            let [channels, height, width] = expect_rank("conv", &shape)?;
+            let [height, width] = interpolate_size(interpolate, [height, width]);
+            shapes.push(LayerShape::new(
+                "output_interpolate",
+                vec![channels, height, width],
+            ));
+        }
+        Ok(shapes)
+    }
+}
+
 impl<B: Backend> Init<B, LinearConvTranspose2dModel<B>> for LinearConvTranspose2dModelConfig {
     fn init(self, device: &<B as Backend>::Device) -> LinearConvTranspose2dModel<B> {
         LinearConvTranspose2dModel {
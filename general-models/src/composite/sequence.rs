@@ -0,0 +1,68 @@
+use burn::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Init, SimpleInfer, SimpleTrain,
+    error::ShapeError,
+    linear::{LinearModel, LinearModelConfig},
+    recurrent::grus::{GruModel, GruModelConfig},
+    shape::{InferShape, LayerShape, expect_rank, infer_nested},
+};
+
+/// A GRU over a `[batch_size, seq_len, features]` sequence, whose last hidden state goes through a
+/// linear model.
+#[derive(Debug, Module)]
+pub struct GruLinearModel<B: Backend> {
+    pub gru: GruModel<B>,
+    pub linear: LinearModel<B>,
+}
+
+impl<B: Backend> SimpleInfer<B, 3, 2> for GruLinearModel<B> {
+    fn forward(&self, tensor: Tensor<B, 3>) -> Tensor<B, 2> {
+        let tensor: Tensor<B, 2> = self.gru.infer(tensor);
+        self.linear.infer(tensor)
+    }
+}
+
+impl<B: Backend> SimpleTrain<B, 3, 2> for GruLinearModel<B> {
+    fn forward(&self, tensor: Tensor<B, 3>) -> Tensor<B, 2> {
+        let tensor: Tensor<B, 2> = self.gru.train(tensor);
+        self.linear.train(tensor)
+    }
+}
+
+impl<B: Backend> GruLinearModel<B> {
+    pub fn get_input_size(&self) -> usize {
+        self.gru.get_input_size()
+    }
+
+    pub fn get_output_size(&self) -> usize {
+        self.linear.get_output_size()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GruLinearModelConfig {
+    pub gru: GruModelConfig,
+    pub linear: LinearModelConfig,
+}
+
+impl InferShape for GruLinearModelConfig {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        let mut shapes = vec![];
+        let shape = infer_nested(&mut shapes, "gru", &self.gru, input_shape)?;
+        // Only the last hidden state reaches the linear model
+        let [_seq_len, hidden_size] = expect_rank("gru", &shape)?;
+        infer_nested(&mut shapes, "linear", &self.linear, &[hidden_size])?;
+        Ok(shapes)
+    }
+}
+
+impl<B: Backend> Init<B, GruLinearModel<B>> for GruLinearModelConfig {
+    fn init(self, device: &<B as Backend>::Device) -> GruLinearModel<B> {
+        GruLinearModel {
+            gru: self.gru.init(device),
+            linear: self.linear.init(device),
+        }
+    }
+}
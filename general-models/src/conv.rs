@@ -4,6 +4,7 @@ use burn::{
         Dropout, DropoutConfig, PaddingConfig2d,
         activation::Activation,
         conv::{Conv2d, Conv2dConfig, ConvTranspose2d, ConvTranspose2dConfig},
+        pool::{AvgPool2d, AvgPool2dConfig, MaxPool2d, MaxPool2dConfig},
     },
     prelude::*,
 };
@@ -12,17 +13,112 @@ use utils::default_f;
 
 use crate::{
     Init, SimpleInfer, SimpleTrain,
-    common::{ActivationConfig, Either, Norm, NormConfig, Optional, handle_norm_activation},
+    common::{
+        ActivationConfig, Either, InitializerConfig, Norm, NormConfig, Optional,
+        handle_norm_activation, orthogonal_biases,
+    },
+    error::{ExportError, ShapeError, ShapeErrorKind},
+    onnx::{self, OnnxGraph, OnnxValue, ToOnnx},
+    quantize::{ActivationRanges, Quantize, QuantizedConv2d},
+    shape::{
+        InferShape, LayerShape, apply_activation, expect_divisible, expect_rank, expect_size,
+        norm_params, window_output_size,
+    },
 };
 
 #[derive(Debug, Module)]
 pub struct Conv2dModel<B: Backend> {
     input_channels: Ignored<usize>,
     layers: Vec<(Conv2d<B>, Option<Norm<B>>, Option<Activation<B>>)>,
+    /// The skip connection of each layer. `None` if the layer has no skip connection, `Some(None)`
+    /// if the input is added as is, and `Some(Some(projection))` if the input has to go through a
+    /// 1x1 convolution first.
+    residuals: Vec<Option<Option<Conv2d<B>>>>,
+    pools: Vec<Option<Pool2d>>,
     dropout: Dropout,
     dropout_last: bool,
 }
 
+#[derive(Debug, Clone, Module)]
+pub enum Pool2d {
+    Max(MaxPool2d),
+    Avg(AvgPool2d),
+}
+
+impl Pool2d {
+    pub fn forward<B: Backend>(&self, tensor: Tensor<B, 4>) -> Tensor<B, 4> {
+        match self {
+            Pool2d::Max(pool) => pool.forward(tensor),
+            Pool2d::Avg(pool) => pool.forward(tensor),
+        }
+    }
+}
+
+/// How far the skip connection of a layer is shifted along the height and width, so that each
+/// output lines up with the input at the center of its kernel: half the dilated kernel, minus the
+/// padding. It's negative when the padding outgrows the kernel.
+fn skip_offset(kernel_size: [usize; 2], dilation: [usize; 2], padding: [usize; 2]) -> [isize; 2] {
+    [0, 1].map(|i| {
+        (dilation[i] * kernel_size[i].saturating_sub(1) / 2) as isize - padding[i] as isize
+    })
+}
+
+/// The [`skip_offset`] of a layer built by [`Conv2dModelConfig`].
+fn conv_skip_offset<B: Backend>(conv: &Conv2d<B>) -> [isize; 2] {
+    let padding = match *conv.padding {
+        PaddingConfig2d::Explicit(x, y) => [x, y],
+        // The layers are only built with valid or explicit padding
+        PaddingConfig2d::Valid | PaddingConfig2d::Same => [0, 0],
+    };
+    skip_offset(conv.kernel_size, conv.dilation, padding)
+}
+
+/// The height or width of a skip connection with an input of `input` elements, shifted by `offset`
+/// and strided by `stride`.
+fn skip_size(input: usize, offset: isize, stride: usize) -> usize {
+    let shifted = match usize::try_from(offset) {
+        Ok(offset) => input.saturating_sub(offset),
+        Err(_) => input + 2 * offset.unsigned_abs(),
+    };
+    shifted.div_ceil(stride)
+}
+
+/// Shifts `tensor` by `offset` along its height and width, cropping the start for positive offsets
+/// and padding both sides with zeros for negative ones.
+fn shift<B: Backend>(tensor: Tensor<B, 4>, offset: [isize; 2]) -> Tensor<B, 4> {
+    let [top, left] = offset.map(|x| usize::try_from(-x).unwrap_or_default());
+    let tensor = if top > 0 || left > 0 {
+        tensor.pad((left, left, top, top), 0.0)
+    } else {
+        tensor
+    };
+    let [top, left] = offset.map(|x| usize::try_from(x).unwrap_or_default());
+    tensor.slice(s![.., .., top.., left..])
+}
+
+fn skip<B: Backend>(
+    residual: &Option<Option<Conv2d<B>>>,
+    conv: &Conv2d<B>,
+    tensor: &Tensor<B, 4>,
+) -> Option<Tensor<B, 4>> {
+    residual.as_ref().map(|projection| {
+        let tensor = shift(tensor.clone(), conv_skip_offset(conv));
+        match projection {
+            Some(projection) => projection.forward(tensor),
+            None => tensor,
+        }
+    })
+}
+
+/// Adds the skip connection to the output of a layer.
+///
+/// Kernels that aren't fully padded shrink the output, and the skip connection was already
+/// shifted to line up with it, so the rows and columns past the output are cropped.
+fn add_skip<B: Backend>(tensor: Tensor<B, 4>, skip: Tensor<B, 4>) -> Tensor<B, 4> {
+    let [_, _, height, width] = tensor.dims();
+    tensor + skip.slice(s![.., .., 0..height, 0..width])
+}
+
 impl<B: Backend> Conv2dModel<B> {
     pub fn get_input_channels(&self) -> usize {
         self.input_channels.0
@@ -41,18 +137,39 @@ impl<B: Backend> Conv2dModel<B> {
             .map(|(conv, norm, activation)| map(conv, norm, activation))
             .collect();
     }
+
+    /// Maps the learned projections of the skip connections.
+    pub fn iter_residuals(&mut self, mut map: impl FnMut(Conv2d<B>) -> Conv2d<B>) {
+        self.residuals = std::mem::take(&mut self.residuals)
+            .into_iter()
+            .map(|residual| residual.map(|projection| projection.map(&mut map)))
+            .collect();
+    }
 }
 
 impl<B: Backend> SimpleTrain<B, 4, 4> for Conv2dModel<B> {
     fn forward(&self, mut tensor: burn::Tensor<B, 4>) -> burn::Tensor<B, 4> {
-        for (i, (conv, norm, activation)) in self.layers.iter().enumerate() {
+        for (i, (((conv, norm, activation), residual), pool)) in self
+            .layers
+            .iter()
+            .zip(&self.residuals)
+            .zip(&self.pools)
+            .enumerate()
+        {
+            let skip = skip(residual, conv, &tensor);
             tensor = conv.forward(tensor);
             if let Some(norm) = norm {
                 tensor = norm.forward(tensor);
             }
+            if let Some(skip) = skip {
+                tensor = add_skip(tensor, skip);
+            }
             if let Some(activation) = activation {
                 tensor = activation.forward(tensor);
             }
+            if let Some(pool) = pool {
+                tensor = pool.forward(tensor);
+            }
             if i < self.layers.len() - 1 || self.dropout_last {
                 tensor = self.dropout.forward(tensor);
             }
@@ -63,17 +180,151 @@ impl<B: Backend> SimpleTrain<B, 4, 4> for Conv2dModel<B> {
 
 impl<B: Backend> SimpleInfer<B, 4, 4> for Conv2dModel<B> {
     fn forward(&self, mut tensor: burn::Tensor<B, 4>) -> burn::Tensor<B, 4> {
-        for (conv, norm, activation) in self.layers.iter() {
+        for (((conv, norm, activation), residual), pool) in
+            self.layers.iter().zip(&self.residuals).zip(&self.pools)
+        {
+            let skip = skip(residual, conv, &tensor);
+            tensor = conv.forward(tensor);
+            if let Some(norm) = norm {
+                tensor = norm.forward(tensor);
+            }
+            if let Some(skip) = skip {
+                tensor = add_skip(tensor, skip);
+            }
+            if let Some(activation) = activation {
+                tensor = activation.forward(tensor);
+            }
+            if let Some(pool) = pool {
+                tensor = pool.forward(tensor);
+            }
+        }
+        tensor
+    }
+}
+
+impl<B: Backend> ToOnnx for Conv2dModel<B> {
+    fn to_onnx(
+        &self,
+        graph: &mut OnnxGraph,
+        mut input: OnnxValue,
+    ) -> Result<OnnxValue, ExportError> {
+        for (((conv, norm, activation), residual), pool) in
+            self.layers.iter().zip(&self.residuals).zip(&self.pools)
+        {
+            let skip = match residual {
+                Some(projection) => {
+                    let skip = onnx::shift(graph, input.clone(), conv_skip_offset(conv));
+                    Some(projection.to_onnx(graph, skip)?)
+                }
+                None => None,
+            };
+            input = conv.to_onnx(graph, input)?;
+            input = norm.to_onnx(graph, input)?;
+            if let Some(skip) = skip {
+                input = onnx::add_skip(graph, input, skip)?;
+            }
+            input = activation.to_onnx(graph, input)?;
+            input = pool.to_onnx(graph, input)?;
+        }
+        Ok(input)
+    }
+}
+
+/// A [`Conv2dModel`] with int8 weights and activations, see [`crate::quantize`].
+#[derive(Debug, Module)]
+pub struct QuantizedConv2dModel<B: Backend> {
+    input_channels: Ignored<usize>,
+    layers: Vec<(QuantizedConv2d<B>, Option<Norm<B>>, Option<Activation<B>>)>,
+    residuals: Vec<Option<Option<QuantizedConv2d<B>>>>,
+    pools: Vec<Option<Pool2d>>,
+}
+
+impl<B: Backend> QuantizedConv2dModel<B> {
+    pub fn get_input_channels(&self) -> usize {
+        self.input_channels.0
+    }
+}
+
+impl<B: Backend> SimpleInfer<B, 4, 4> for QuantizedConv2dModel<B> {
+    fn forward(&self, mut tensor: Tensor<B, 4>) -> Tensor<B, 4> {
+        for (((conv, norm, activation), residual), pool) in
+            self.layers.iter().zip(&self.residuals).zip(&self.pools)
+        {
+            let skip = residual.as_ref().map(|projection| {
+                let tensor = shift(tensor.clone(), conv_skip_offset(conv.conv()));
+                match projection {
+                    Some(projection) => projection.forward(tensor),
+                    None => tensor,
+                }
+            });
+            tensor = conv.forward(tensor);
+            if let Some(norm) = norm {
+                tensor = norm.forward(tensor);
+            }
+            if let Some(skip) = skip {
+                tensor = add_skip(tensor, skip);
+            }
+            if let Some(activation) = activation {
+                tensor = activation.forward(tensor);
+            }
+            if let Some(pool) = pool {
+                tensor = pool.forward(tensor);
+            }
+        }
+        tensor
+    }
+}
+
+impl<B: Backend> Quantize<B, 4, 4> for Conv2dModel<B> {
+    type Quantized = QuantizedConv2dModel<B>;
+
+    fn observe(&self, mut tensor: Tensor<B, 4>, ranges: &mut ActivationRanges) -> Tensor<B, 4> {
+        for (((conv, norm, activation), residual), pool) in
+            self.layers.iter().zip(&self.residuals).zip(&self.pools)
+        {
+            // The projection of the skip connection shares the input of the layer
+            ranges.observe(&tensor);
+            let skip = skip(residual, conv, &tensor);
             tensor = conv.forward(tensor);
             if let Some(norm) = norm {
                 tensor = norm.forward(tensor);
             }
+            if let Some(skip) = skip {
+                tensor = add_skip(tensor, skip);
+            }
             if let Some(activation) = activation {
                 tensor = activation.forward(tensor);
             }
+            if let Some(pool) = pool {
+                tensor = pool.forward(tensor);
+            }
         }
         tensor
     }
+
+    fn quantize(&self, ranges: &mut ActivationRanges) -> QuantizedConv2dModel<B> {
+        let mut layers = vec![];
+        let mut residuals = vec![];
+        for ((conv, norm, activation), residual) in self.layers.iter().zip(&self.residuals) {
+            let input_scale = ranges.next_scale();
+            layers.push((
+                QuantizedConv2d::new(conv, input_scale),
+                norm.clone(),
+                activation.clone(),
+            ));
+            residuals.push(residual.as_ref().map(|projection| {
+                projection
+                    .as_ref()
+                    .map(|projection| QuantizedConv2d::new(projection, input_scale))
+            }));
+        }
+        QuantizedConv2dModel {
+            input_channels: self.input_channels.clone(),
+            layers,
+            residuals,
+            pools: self.pools.clone(),
+        }
+    }
 }
 
 #[derive(Serialize, Debug, Deserialize, Clone, Copy)]
@@ -86,7 +337,104 @@ pub struct Conv2dLayerConfig {
     pub dilation: [usize; 2],
     #[serde(default = "default_groups")]
     pub groups: usize,
-    pub padding: Option<[usize; 2]>,
+    #[serde(default)]
+    pub padding: Conv2dPadding,
+    /// Pools the output of the layer after the activation.
+    pub pool: Option<Pool2dConfig>,
+    /// Adds the input of the layer to its output before the activation. If the shapes differ, the
+    /// input goes through a learned 1x1 convolution with the same stride first.
+    #[serde(default)]
+    pub residual: bool,
+    /// Overrides the initializer of the weights of the layer.
+    pub initializer: Option<InitializerConfig>,
+}
+
+impl Conv2dLayerConfig {
+    /// Whether the skip connection of this layer needs a 1x1 convolution to match the output,
+    /// given the padding that [`Conv2dPadding::resolve`] returned for it.
+    fn needs_projection(&self, input_channels: usize, padding: [usize; 2]) -> bool {
+        // The output can only be larger than the input when the padding outgrows the kernel
+        let grows = (0..2)
+            .any(|i| padding[i] * 2 > self.dilation[i] * self.kernel_size[i].saturating_sub(1));
+        input_channels != self.output_channels || self.stride != [1, 1] || grows
+    }
+}
+
+/// The padding of a [`Conv2dLayerConfig`], written as `"valid"`, `"same"`, or `[x, y]`.
+#[derive(Serialize, Debug, Deserialize, Clone, Copy, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Conv2dPadding {
+    #[default]
+    Valid,
+    /// Pads just enough for the output to be the same size as the input when the stride is 1.
+    /// The dilated kernel must have an odd size, as the padding would be asymmetric otherwise.
+    Same,
+    #[serde(untagged)]
+    Explicit([usize; 2]),
+}
+
+impl Conv2dPadding {
+    /// Returns the padding along each axis for a kernel with the given size and dilation, or an
+    /// error if the padding is [`Conv2dPadding::Same`] and the dilated kernel has an even size, as
+    /// the padding would have to be asymmetric.
+    pub fn resolve(
+        self,
+        kernel_size: [usize; 2],
+        dilation: [usize; 2],
+    ) -> Result<[usize; 2], ShapeErrorKind> {
+        Ok(match self {
+            Conv2dPadding::Valid => [0, 0],
+            Conv2dPadding::Same => {
+                let total = [0, 1].map(|i| dilation[i] * kernel_size[i].saturating_sub(1));
+                if !total.iter().all(|x| x.is_multiple_of(2)) {
+                    return Err(ShapeErrorKind::EvenSamePadding {
+                        kernel_size,
+                        dilation,
+                    });
+                }
+                total.map(|x| x / 2)
+            }
+            Conv2dPadding::Explicit(padding) => padding,
+        })
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Pool2dConfig {
+    Max {
+        kernel_size: [usize; 2],
+        /// Defaults to the kernel size.
+        stride: Option<[usize; 2]>,
+    },
+    Avg {
+        kernel_size: [usize; 2],
+        /// Defaults to the kernel size.
+        stride: Option<[usize; 2]>,
+    },
+}
+
+impl Pool2dConfig {
+    pub fn init(self) -> Pool2d {
+        match self {
+            Pool2dConfig::Max {
+                kernel_size,
+                stride,
+            } => Pool2d::Max(
+                MaxPool2dConfig::new(kernel_size)
+                    .with_strides(stride.unwrap_or(kernel_size))
+                    .init(),
+            ),
+            Pool2dConfig::Avg {
+                kernel_size,
+                stride,
+            } => Pool2d::Avg(
+                AvgPool2dConfig::new(kernel_size)
+                    .with_strides(stride.unwrap_or(kernel_size))
+                    .init(),
+            ),
+        }
+    }
 }
 
 #[derive(Serialize, Debug, Deserialize, Clone)]
@@ -94,6 +442,9 @@ pub struct Conv2dModelConfig {
     pub input_channels: usize,
     pub default_activation: Option<ActivationConfig>,
     pub default_norm: Option<NormConfig>,
+    /// The initializer of the weights of every layer, instead of picking one from the activation.
+    #[serde(default)]
+    pub default_initializer: Option<InitializerConfig>,
     pub layers: Vec<
         Either<Conv2dLayerConfig, Optional<ActivationConfig>, Optional<NormConfig>, Option<f64>>,
     >,
@@ -103,54 +454,183 @@ pub struct Conv2dModelConfig {
     pub dropout_last: bool,
 }
 
+impl Pool2dConfig {
+    pub(crate) fn output_shape(
+        self,
+        layer: &str,
+        shape: [usize; 3],
+    ) -> Result<[usize; 3], ShapeError> {
+        let (Pool2dConfig::Max {
+            kernel_size,
+            stride,
+        }
+        | Pool2dConfig::Avg {
+            kernel_size,
+            stride,
+        }) = self;
+        let stride = stride.unwrap_or(kernel_size);
+        let [channels, height, width] = shape;
+        Ok([
+            channels,
+            window_output_size(layer, height, kernel_size[0], stride[0], 1, 0)?,
+            window_output_size(layer, width, kernel_size[1], stride[1], 1, 0)?,
+        ])
+    }
+}
+
+impl InferShape for Conv2dModelConfig {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        let [channels, mut height, mut width] = expect_rank("layers.0", input_shape)?;
+        expect_size("layers.0", self.input_channels, channels)?;
+        let mut input_channels = channels;
+        let mut shapes = vec![];
+        for (i, (layer, activation, norm, _)) in self
+            .layers
+            .iter()
+            .cloned()
+            .map(Either::into_tuple)
+            .enumerate()
+        {
+            let name = format!("layers.{i}");
+            expect_divisible(&name, input_channels, layer.groups)?;
+            expect_divisible(&name, layer.output_channels, layer.groups)?;
+            let padding = layer
+                .padding
+                .resolve(layer.kernel_size, layer.dilation)
+                .map_err(|kind| ShapeError::new(&name, kind))?;
+            let input_size = [height, width];
+            height = window_output_size(
+                &name,
+                height,
+                layer.kernel_size[0],
+                layer.stride[0],
+                layer.dilation[0],
+                padding[0],
+            )?;
+            width = window_output_size(
+                &name,
+                width,
+                layer.kernel_size[1],
+                layer.stride[1],
+                layer.dilation[1],
+                padding[1],
+            )?;
+            if layer.residual {
+                let offset = skip_offset(layer.kernel_size, layer.dilation, padding);
+                let skip = [0, 1].map(|i| skip_size(input_size[i], offset[i], layer.stride[i]));
+                if skip[0] < height || skip[1] < width {
+                    return Err(ShapeError::new(
+                        &name,
+                        ShapeErrorKind::SkipTooSmall {
+                            skip,
+                            output: [height, width],
+                        },
+                    ));
+                }
+            }
+            let norm_params = norm_params(&name, &norm, &self.default_norm, layer.output_channels)?;
+            let [kernel_height, kernel_width] = layer.kernel_size;
+            let mut weights = layer.output_channels * input_channels / layer.groups
+                * kernel_height
+                * kernel_width;
+            let mut macs = weights * height * width;
+            if layer.residual && layer.needs_projection(input_channels, padding) {
+                weights += layer.output_channels * input_channels;
+                macs += layer.output_channels * input_channels * height * width;
+            }
+            let bias = if norm_params.is_none() {
+                layer.output_channels
+            } else {
+                0
+            };
+            let mut shape = LayerShape {
+                name,
+                output_shape: vec![layer.output_channels, height, width],
+                params: weights + bias + norm_params.unwrap_or_default(),
+                macs,
+            };
+            apply_activation(&mut shape, &activation, &self.default_activation)?;
+            [input_channels, height, width] = expect_rank(&shape.name, &shape.output_shape)?;
+            shapes.push(shape);
+            if let Some(pool) = layer.pool {
+                let name = format!("layers.{i}.pool");
+                [input_channels, height, width] =
+                    pool.output_shape(&name, [input_channels, height, width])?;
+                shapes.push(LayerShape::new(name, vec![input_channels, height, width]));
+            }
+        }
+        Ok(shapes)
+    }
+}
+
 impl<B: Backend> Init<B, Conv2dModel<B>> for Conv2dModelConfig {
     fn init(self, device: &B::Device) -> Conv2dModel<B> {
         let mut input_channels = self.input_channels;
         let mut layers = vec![];
-        for (
-            Conv2dLayerConfig {
+        let mut residuals = vec![];
+        let mut pools = vec![];
+        for (layer, activation, norm, weights_gain) in
+            self.layers.into_iter().map(Either::into_tuple)
+        {
+            let Conv2dLayerConfig {
                 output_channels,
                 kernel_size,
                 stride,
                 dilation,
                 groups,
                 padding,
-            },
-            activation,
-            norm,
-            weights_gain,
-        ) in self.layers.into_iter().map(Either::into_tuple)
-        {
+                pool,
+                residual,
+                initializer,
+            } = layer;
             let (norm, activation, init) = handle_norm_activation(
                 norm,
                 activation,
+                initializer,
                 &self.default_norm,
                 &self.default_activation,
+                &self.default_initializer,
                 weights_gain,
                 output_channels,
                 device,
             );
-            layers.push((
-                Conv2dConfig::new([input_channels, output_channels], kernel_size)
-                    .with_bias(norm.is_none())
-                    .with_stride(stride)
-                    .with_initializer(init)
-                    .with_dilation(dilation)
-                    .with_groups(groups)
-                    .with_padding(
-                        padding
-                            .map(|[x, y]| PaddingConfig2d::Explicit(x, y))
-                            .unwrap_or(PaddingConfig2d::Valid),
-                    )
-                    .init(device),
-                norm,
-                activation,
-            ));
+            // Even kernels with same padding are rejected by `infer_shapes`, which checks the
+            // config before training
+            let resolved_padding = padding
+                .resolve(kernel_size, dilation)
+                .unwrap_or_else(|e| panic!("Expected a config checked by infer_shapes: {e}"));
+            let [padding_x, padding_y] = resolved_padding;
+            let conv = Conv2dConfig::new([input_channels, output_channels], kernel_size)
+                .with_bias(norm.is_none())
+                .with_stride(stride)
+                .with_initializer(init.clone())
+                .with_dilation(dilation)
+                .with_groups(groups)
+                .with_padding(match padding {
+                    Conv2dPadding::Valid => PaddingConfig2d::Valid,
+                    _ => PaddingConfig2d::Explicit(padding_x, padding_y),
+                })
+                .init(device);
+            layers.push((orthogonal_biases(conv, &init), norm, activation));
+            pools.push(pool.map(Pool2dConfig::init));
+            residuals.push(residual.then(|| {
+                layer
+                    .needs_projection(input_channels, resolved_padding)
+                    .then(|| {
+                        // The input is shifted to the center of the kernel instead of padded
+                        Conv2dConfig::new([input_channels, output_channels], [1, 1])
+                            .with_bias(false)
+                            .with_stride(stride)
+                            .init(device)
+                    })
+            }));
             input_channels = output_channels;
         }
         Conv2dModel {
             input_channels: Ignored(self.input_channels),
             layers,
+            residuals,
+            pools,
             dropout: DropoutConfig::new(self.dropout).init(),
             dropout_last: self.dropout_last,
         }
@@ -218,6 +698,21 @@ impl<B: Backend> SimpleInfer<B, 4, 4> for ConvTranspose2dModel<B> {
     }
 }
 
+impl<B: Backend> ToOnnx for ConvTranspose2dModel<B> {
+    fn to_onnx(
+        &self,
+        graph: &mut OnnxGraph,
+        mut input: OnnxValue,
+    ) -> Result<OnnxValue, ExportError> {
+        for (conv, norm, activation) in self.layers.iter() {
+            input = conv.to_onnx(graph, input)?;
+            input = norm.to_onnx(graph, input)?;
+            input = activation.to_onnx(graph, input)?;
+        }
+        Ok(input)
+    }
+}
+
 #[derive(Serialize, Debug, Deserialize, Clone, Copy)]
 pub struct ConvTranspose2dLayerConfig {
     pub output_channels: usize,
@@ -232,6 +727,8 @@ pub struct ConvTranspose2dLayerConfig {
     pub padding: [usize; 2],
     #[serde(default = "default_padding")]
     pub padding_out: [usize; 2],
+    /// Overrides the initializer of the weights of the layer.
+    pub initializer: Option<InitializerConfig>,
 }
 
 #[derive(Serialize, Debug, Deserialize, Clone)]
@@ -239,6 +736,9 @@ pub struct ConvTranspose2dModelConfig {
     pub input_channels: usize,
     pub default_activation: Option<ActivationConfig>,
     pub default_norm: Option<NormConfig>,
+    /// The initializer of the weights of every layer, instead of picking one from the activation.
+    #[serde(default)]
+    pub default_initializer: Option<InitializerConfig>,
     pub layers: Vec<
         Either<
             ConvTranspose2dLayerConfig,
@@ -253,6 +753,65 @@ pub struct ConvTranspose2dModelConfig {
     pub dropout_last: bool,
 }
 
+impl InferShape for ConvTranspose2dModelConfig {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        let [channels, mut height, mut width] = expect_rank("layers.0", input_shape)?;
+        expect_size("layers.0", self.input_channels, channels)?;
+        let mut input_channels = channels;
+        let mut shapes = vec![];
+        for (i, (layer, activation, norm, _)) in self
+            .layers
+            .iter()
+            .cloned()
+            .map(Either::into_tuple)
+            .enumerate()
+        {
+            let name = format!("layers.{i}");
+            expect_divisible(&name, input_channels, layer.groups)?;
+            expect_divisible(&name, layer.output_channels, layer.groups)?;
+            let output_size = |axis: usize, input: usize| {
+                if layer.kernel_size[axis] == 0 {
+                    return Err(ShapeError::new(&name, ShapeErrorKind::ZeroKernelSize));
+                }
+                if layer.stride[axis] == 0 {
+                    return Err(ShapeError::new(&name, ShapeErrorKind::ZeroStride));
+                }
+                let size = (input - 1) * layer.stride[axis]
+                    + layer.dilation[axis] * (layer.kernel_size[axis] - 1)
+                    + layer.padding_out[axis]
+                    + 1;
+                size.checked_sub(2 * layer.padding[axis])
+                    .filter(|&size| size > 0)
+                    .ok_or_else(|| ShapeError::new(&name, ShapeErrorKind::EmptyOutput))
+            };
+            // Every input element is scattered over the whole kernel
+            let [kernel_height, kernel_width] = layer.kernel_size;
+            let weights = input_channels * layer.output_channels / layer.groups
+                * kernel_height
+                * kernel_width;
+            let macs = weights * height * width;
+            height = output_size(0, height)?;
+            width = output_size(1, width)?;
+            let norm_params = norm_params(&name, &norm, &self.default_norm, layer.output_channels)?;
+            let bias = if norm_params.is_none() {
+                layer.output_channels
+            } else {
+                0
+            };
+            let mut shape = LayerShape {
+                name,
+                output_shape: vec![layer.output_channels, height, width],
+                params: weights + bias + norm_params.unwrap_or_default(),
+                macs,
+            };
+            apply_activation(&mut shape, &activation, &self.default_activation)?;
+            [input_channels, height, width] = expect_rank(&shape.name, &shape.output_shape)?;
+            shapes.push(shape);
+        }
+        Ok(shapes)
+    }
+}
+
 impl<B: Backend> Init<B, ConvTranspose2dModel<B>> for ConvTranspose2dModelConfig {
     fn init(self, device: &B::Device) -> ConvTranspose2dModel<B> {
         let mut input_channels = self.input_channels;
@@ -267,35 +826,52 @@ impl<B: Backend> Init<B, ConvTranspose2dModel<B>> for ConvTranspose2dModelConfig
                 padding,
                 padding_out,
                 groups,
+                initializer,
             },
             activation,
             norm,
             weights_gain,
         ) in self.layers.into_iter().map(Either::into_tuple)
         {
-            let (norm, activation, _init) = handle_norm_activation(
+            let explicit_initializer = initializer.is_some() || self.default_initializer.is_some();
+            let (norm, activation, init) = handle_norm_activation(
                 norm,
                 activation,
+                initializer,
                 &self.default_norm,
                 &self.default_activation,
+                &self.default_initializer,
                 weights_gain,
                 output_channels,
                 device,
             );
-            layers.push((
+            let mut conv =
                 ConvTranspose2dConfig::new([input_channels, output_channels], kernel_size)
                     .with_bias(norm.is_none())
                     .with_stride(stride)
-                    // Bug in Burn when using Xavier
-                    // .with_initializer(init)
                     .with_dilation(dilation)
                     .with_padding(padding)
                     .with_padding_out(padding_out)
                     .with_groups(groups)
-                    .init(device),
-                norm,
-                activation,
-            ));
+                    .init(device);
+            // Burn only gives the fan in to the initializer of transposed convolutions, which
+            // Xavier needs the fan out for, so initializers set in the config are applied here
+            // instead. Without one, the layer keeps the default initializer of Burn.
+            if explicit_initializer {
+                let kernel_elements = kernel_size.iter().product::<usize>();
+                let fan_in = output_channels / groups * kernel_elements;
+                let fan_out = input_channels / groups * kernel_elements;
+                conv.weight = init.init_with(
+                    conv.weight.lazy_shape(),
+                    Some(fan_in),
+                    Some(fan_out),
+                    device,
+                );
+                conv.bias = conv.bias.map(|bias| {
+                    init.init_with(bias.lazy_shape(), Some(fan_in), Some(fan_out), device)
+                });
+            }
+            layers.push((orthogonal_biases(conv, &init), norm, activation));
             input_channels = output_channels;
         }
 
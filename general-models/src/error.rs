@@ -1,4 +1,5 @@
 use burn::record::RecorderError;
+use burn_store::{PytorchStoreError, SafetensorsStoreError};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -7,4 +8,122 @@ pub enum LoadModelError {
     ConfigError(#[from] serde_json::Error),
     #[error("Error loading model weights: {0}")]
     WeightsError(#[from] RecorderError),
+    #[error("Error loading model weights: {0}")]
+    StoreError(#[from] StoreError),
+    #[error("Error reading model: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Not a model bundle: {0}")]
+    InvalidBundle(String),
+    /// The bundle was written by an incompatible version of [`crate::bundle`].
+    #[error("Expected a model bundle of format version {expected}, got {found}")]
+    VersionMismatch { expected: u32, found: u32 },
+    /// The weights don't fit the model built from the config, such as when the config was edited
+    /// or its defaults changed.
+    #[error("Expected {tensor} to have shape {expected:?} from the config, got {found:?}")]
+    ShapeMismatch {
+        tensor: String,
+        expected: Vec<usize>,
+        found: Vec<usize>,
+    },
+    /// Tensors of the submodule loaded by [`crate::transfer::load_submodule`] that weren't in the
+    /// checkpoint.
+    #[error("Expected the checkpoint to have weights for {0:?}")]
+    MissingTensors(Vec<String>),
+    #[error("{0}")]
+    UnmatchedPath(#[from] UnmatchedPathError),
+}
+
+/// A module path of [`crate::transfer`] that doesn't match any tensor of the model.
+#[derive(Error, Debug, Clone)]
+#[error("No tensors of the model match {0}")]
+pub struct UnmatchedPathError(pub String);
+
+/// A shape mismatch found by [`crate::shape::InferShape`], along with the layer it happened in.
+#[derive(Error, Debug, Clone)]
+#[error("Shape mismatch in {layer}: {kind}")]
+pub struct ShapeError {
+    pub layer: String,
+    pub kind: ShapeErrorKind,
+}
+
+impl ShapeError {
+    pub fn new(layer: impl Into<String>, kind: ShapeErrorKind) -> Self {
+        Self {
+            layer: layer.into(),
+            kind,
+        }
+    }
+
+    /// Prefixes the layer name with the name of the parent model.
+    pub fn nested(mut self, prefix: &str) -> Self {
+        self.layer = format!("{prefix}.{}", self.layer);
+        self
+    }
+}
+
+#[derive(Error, Debug, Clone)]
+pub enum ShapeErrorKind {
+    #[error("expected an input of rank {expected}, got {actual:?}")]
+    RankMismatch { expected: usize, actual: Vec<usize> },
+    #[error("expected an input of shape {expected:?}, got {actual:?}")]
+    ShapeMismatch {
+        expected: Vec<usize>,
+        actual: Vec<usize>,
+    },
+    #[error("expected an input size of {expected}, got {actual}")]
+    SizeMismatch { expected: usize, actual: usize },
+    #[error("{size} is not divisible by {divisor}")]
+    NotDivisible { size: usize, divisor: usize },
+    #[error(
+        "an input of {input} with a padding of {padding} is smaller than the window of {window}"
+    )]
+    TooSmall {
+        input: usize,
+        padding: usize,
+        window: usize,
+    },
+    #[error("the output would be empty")]
+    EmptyOutput,
+    #[error(
+        "same padding requires an odd dilated kernel size, got kernel size {kernel_size:?} and dilation {dilation:?}"
+    )]
+    EvenSamePadding {
+        kernel_size: [usize; 2],
+        dilation: [usize; 2],
+    },
+    #[error("the skip connection of {skip:?} is smaller than the output of {output:?}")]
+    SkipTooSmall {
+        skip: [usize; 2],
+        output: [usize; 2],
+    },
+    #[error("the kernel size must be at least 1")]
+    ZeroKernelSize,
+    #[error("the stride must be at least 1")]
+    ZeroStride,
+    #[error("expected at least one layer")]
+    NoLayers,
+    #[error("expected at least one member")]
+    NoMembers,
+}
+
+/// An error exporting a model with [`crate::onnx::ToOnnx`].
+#[derive(Error, Debug, Clone)]
+pub enum ExportError {
+    #[error("{0} can't be exported to ONNX")]
+    Unsupported(String),
+    #[error("Unexpected input shape {shape:?} in {layer}")]
+    ShapeMismatch { layer: String, shape: Vec<usize> },
+}
+
+/// An error saving or loading weights with [`crate::weights`].
+#[derive(Error, Debug)]
+pub enum StoreError {
+    #[error("Error reading or writing safetensors: {0}")]
+    Safetensors(#[from] SafetensorsStoreError),
+    #[error("Error loading PyTorch weights: {0}")]
+    Pytorch(#[from] PytorchStoreError),
+    #[error("Invalid name mapping: {0}")]
+    InvalidMapping(String),
+    #[error("Error writing weights: {0}")]
+    Io(#[from] std::io::Error),
 }
@@ -1,12 +1,21 @@
 use burn::{Tensor, prelude::Backend};
 
+pub mod bundle;
 pub mod common;
 pub mod composite;
 pub mod conv;
 pub mod error;
 pub mod linear;
 pub mod recurrent;
+pub mod sequential;
 pub mod loss;
+pub mod metrics;
+pub mod onnx;
+pub mod quantize;
+pub mod shape;
+pub mod summary;
+pub mod transfer;
+pub mod weights;
 
 pub trait Init<B: Backend, T> {
     fn init(self, device: &B::Device) -> T;
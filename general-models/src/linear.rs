@@ -8,23 +8,50 @@ use utils::default_f;
 
 use crate::{
     Init, SimpleInfer, SimpleTrain,
-    common::{ActivationConfig, Either, Norm, NormConfig, Optional, handle_norm_activation},
+    common::{
+        ActivationConfig, Either, InitializerConfig, Norm, NormConfig, Optional,
+        handle_norm_activation, orthogonal_biases,
+    },
+    error::{ExportError, ShapeError, ShapeErrorKind},
+    onnx::{OnnxGraph, OnnxValue, ToOnnx},
+    quantize::{ActivationRanges, Quantize, QuantizedLinear},
+    shape::{InferShape, LayerShape, apply_activation, expect_size, norm_params, positions},
 };
 
 #[derive(Debug, Module)]
 pub struct LinearModel<B: Backend> {
     layers: Vec<(Linear<B>, Option<Norm<B>>, Option<Activation<B>>)>,
+    /// The skip connection of each layer. `None` if the layer has no skip connection, `Some(None)`
+    /// if the input is added as is, and `Some(Some(projection))` if the input has to be projected
+    /// to the output size first.
+    residuals: Vec<Option<Option<Linear<B>>>>,
     dropout: Dropout,
     dropout_last: bool,
 }
 
+fn skip<B: Backend>(
+    residual: &Option<Option<Linear<B>>>,
+    tensor: &Tensor<B, 2>,
+) -> Option<Tensor<B, 2>> {
+    residual.as_ref().map(|projection| match projection {
+        Some(projection) => projection.forward(tensor.clone()),
+        None => tensor.clone(),
+    })
+}
+
 impl<B: Backend> SimpleTrain<B, 2, 2> for LinearModel<B> {
     fn forward(&self, mut tensor: burn::Tensor<B, 2>) -> burn::Tensor<B, 2> {
-        for (i, (linear, norm, activation)) in self.layers.iter().enumerate() {
+        for (i, ((linear, norm, activation), residual)) in
+            self.layers.iter().zip(&self.residuals).enumerate()
+        {
+            let skip = skip(residual, &tensor);
             tensor = linear.forward(tensor);
             if let Some(norm) = norm {
                 tensor = norm.forward(tensor);
             }
+            if let Some(skip) = skip {
+                tensor = tensor + skip;
+            }
             if let Some(activation) = activation {
                 tensor = activation.forward(tensor);
             }
@@ -38,11 +65,15 @@ impl<B: Backend> SimpleTrain<B, 2, 2> for LinearModel<B> {
 
 impl<B: Backend> SimpleInfer<B, 2, 2> for LinearModel<B> {
     fn forward(&self, mut tensor: burn::Tensor<B, 2>) -> burn::Tensor<B, 2> {
-        for (linear, norm, activation) in self.layers.iter() {
+        for ((linear, norm, activation), residual) in self.layers.iter().zip(&self.residuals) {
+            let skip = skip(residual, &tensor);
             tensor = linear.forward(tensor);
             if let Some(norm) = norm {
                 tensor = norm.forward(tensor);
             }
+            if let Some(skip) = skip {
+                tensor = tensor + skip;
+            }
             if let Some(activation) = activation {
                 tensor = activation.forward(tensor);
             }
@@ -50,6 +81,29 @@ impl<B: Backend> SimpleInfer<B, 2, 2> for LinearModel<B> {
         tensor
     }
 }
+
+impl<B: Backend> ToOnnx for LinearModel<B> {
+    fn to_onnx(
+        &self,
+        graph: &mut OnnxGraph,
+        mut input: OnnxValue,
+    ) -> Result<OnnxValue, ExportError> {
+        for ((linear, norm, activation), residual) in self.layers.iter().zip(&self.residuals) {
+            let skip = match residual {
+                Some(projection) => Some(projection.to_onnx(graph, input.clone())?),
+                None => None,
+            };
+            input = linear.to_onnx(graph, input)?;
+            input = norm.to_onnx(graph, input)?;
+            if let Some(skip) = skip {
+                input = graph.binary("Add", &input, &skip.name);
+            }
+            input = activation.to_onnx(graph, input)?;
+        }
+        Ok(input)
+    }
+}
+
 impl<B: Backend> LinearModel<B> {
     pub fn iter_layers(
         &mut self,
@@ -65,12 +119,146 @@ impl<B: Backend> LinearModel<B> {
             .collect();
     }
 
+    /// Maps the learned projections of the skip connections.
+    pub fn iter_residuals(&mut self, mut map: impl FnMut(Linear<B>) -> Linear<B>) {
+        self.residuals = std::mem::take(&mut self.residuals)
+            .into_iter()
+            .map(|residual| residual.map(|projection| projection.map(&mut map)))
+            .collect();
+    }
+
     pub fn get_input_size(&self) -> usize {
-        self.layers[0].0.weight.dims()[1]
+        self.layers
+            .first()
+            .expect("Expected a linear model checked by infer_shapes to have at least one layer")
+            .0
+            .weight
+            .dims()[0]
     }
 
     pub fn get_output_size(&self) -> usize {
-        self.layers.last().unwrap().0.weight.dims()[1]
+        self.layers
+            .last()
+            .expect("Expected a linear model checked by infer_shapes to have at least one layer")
+            .0
+            .weight
+            .dims()[1]
+    }
+}
+
+/// A [`LinearModel`] with int8 weights and activations, see [`crate::quantize`].
+#[derive(Debug, Module)]
+pub struct QuantizedLinearModel<B: Backend> {
+    layers: Vec<(QuantizedLinear<B>, Option<Norm<B>>, Option<Activation<B>>)>,
+    residuals: Vec<Option<Option<QuantizedLinear<B>>>>,
+}
+
+impl<B: Backend> SimpleInfer<B, 2, 2> for QuantizedLinearModel<B> {
+    fn forward(&self, mut tensor: Tensor<B, 2>) -> Tensor<B, 2> {
+        for ((linear, norm, activation), residual) in self.layers.iter().zip(&self.residuals) {
+            let skip = residual.as_ref().map(|projection| match projection {
+                Some(projection) => projection.forward(tensor.clone()),
+                None => tensor.clone(),
+            });
+            tensor = linear.forward(tensor);
+            if let Some(norm) = norm {
+                tensor = norm.forward(tensor);
+            }
+            if let Some(skip) = skip {
+                tensor = tensor + skip;
+            }
+            if let Some(activation) = activation {
+                tensor = activation.forward(tensor);
+            }
+        }
+        tensor
+    }
+}
+
+impl<B: Backend> Quantize<B, 2, 2> for LinearModel<B> {
+    type Quantized = QuantizedLinearModel<B>;
+
+    fn observe(&self, mut tensor: Tensor<B, 2>, ranges: &mut ActivationRanges) -> Tensor<B, 2> {
+        for ((linear, norm, activation), residual) in self.layers.iter().zip(&self.residuals) {
+            // The projection of the skip connection shares the input of the layer
+            ranges.observe(&tensor);
+            let skip = skip(residual, &tensor);
+            tensor = linear.forward(tensor);
+            if let Some(norm) = norm {
+                tensor = norm.forward(tensor);
+            }
+            if let Some(skip) = skip {
+                tensor = tensor + skip;
+            }
+            if let Some(activation) = activation {
+                tensor = activation.forward(tensor);
+            }
+        }
+        tensor
+    }
+
+    fn quantize(&self, ranges: &mut ActivationRanges) -> QuantizedLinearModel<B> {
+        let mut layers = vec![];
+        let mut residuals = vec![];
+        for ((linear, norm, activation), residual) in self.layers.iter().zip(&self.residuals) {
+            let input_scale = ranges.next_scale();
+            layers.push((
+                QuantizedLinear::new(linear, input_scale),
+                norm.clone(),
+                activation.clone(),
+            ));
+            residuals.push(residual.as_ref().map(|projection| {
+                projection
+                    .as_ref()
+                    .map(|projection| QuantizedLinear::new(projection, input_scale))
+            }));
+        }
+        QuantizedLinearModel { layers, residuals }
+    }
+}
+
+/// A layer of a [`LinearModel`], written either as just the output size, or as an object.
+#[derive(Serialize, Debug, Deserialize, Clone, Copy)]
+#[serde(untagged)]
+pub enum LinearLayerConfig {
+    OutputSize(usize),
+    Layer {
+        output_size: usize,
+        /// Adds the input of the layer to its output before the activation. If the input and
+        /// output sizes differ, the input goes through a learned projection first.
+        #[serde(default)]
+        residual: bool,
+        /// Overrides the initializer of the weights of the layer.
+        initializer: Option<InitializerConfig>,
+    },
+}
+
+impl LinearLayerConfig {
+    pub fn output_size(&self) -> usize {
+        match *self {
+            LinearLayerConfig::OutputSize(output_size) => output_size,
+            LinearLayerConfig::Layer { output_size, .. } => output_size,
+        }
+    }
+
+    pub fn residual(&self) -> bool {
+        match *self {
+            LinearLayerConfig::OutputSize(_) => false,
+            LinearLayerConfig::Layer { residual, .. } => residual,
+        }
+    }
+
+    pub fn initializer(&self) -> Option<InitializerConfig> {
+        match *self {
+            LinearLayerConfig::OutputSize(_) => None,
+            LinearLayerConfig::Layer { initializer, .. } => initializer,
+        }
+    }
+}
+
+impl From<usize> for LinearLayerConfig {
+    fn from(output_size: usize) -> Self {
+        LinearLayerConfig::OutputSize(output_size)
     }
 }
 
@@ -79,7 +267,12 @@ pub struct LinearModelConfig {
     pub input_size: usize,
     pub default_activation: Option<ActivationConfig>,
     pub default_norm: Option<NormConfig>,
-    pub layers: Vec<Either<usize, Optional<ActivationConfig>, Optional<NormConfig>, Option<f64>>>,
+    /// The initializer of the weights of every layer, instead of picking one from the activation.
+    #[serde(default)]
+    pub default_initializer: Option<InitializerConfig>,
+    pub layers: Vec<
+        Either<LinearLayerConfig, Optional<ActivationConfig>, Optional<NormConfig>, Option<f64>>,
+    >,
     #[serde(default = "default_dropout")]
     pub dropout: f64,
     #[serde(default = "default_dropout_last")]
@@ -90,35 +283,99 @@ impl<B: Backend> Init<B, LinearModel<B>> for LinearModelConfig {
     fn init(self, device: &B::Device) -> LinearModel<B> {
         let mut input_size = self.input_size;
         let mut layers = vec![];
-        for (output_size, activation, norm, weights_gain) in
+        let mut residuals = vec![];
+        for (layer, activation, norm, weights_gain) in
             self.layers.into_iter().map(Either::into_tuple)
         {
+            let output_size = layer.output_size();
             let (norm, activation, init) = handle_norm_activation(
                 norm,
                 activation,
+                layer.initializer(),
                 &self.default_norm,
                 &self.default_activation,
+                &self.default_initializer,
                 weights_gain,
                 output_size,
                 device,
             );
-            layers.push((
-                LinearConfig::new(input_size, output_size)
-                    .with_bias(norm.is_none())
-                    .with_initializer(init)
-                    .init(device),
-                norm,
-                activation,
-            ));
+            let linear = LinearConfig::new(input_size, output_size)
+                .with_bias(norm.is_none())
+                .with_initializer(init.clone())
+                .init(device);
+            layers.push((orthogonal_biases(linear, &init), norm, activation));
+            residuals.push(layer.residual().then(|| {
+                (input_size != output_size).then(|| {
+                    LinearConfig::new(input_size, output_size)
+                        .with_bias(false)
+                        .init(device)
+                })
+            }));
             input_size = output_size;
         }
         LinearModel {
             layers,
+            residuals,
             dropout: DropoutConfig::new(self.dropout).init(),
             dropout_last: self.dropout_last,
         }
     }
 }
 
+impl InferShape for LinearModelConfig {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        if self.layers.is_empty() {
+            return Err(ShapeError::new("layers", ShapeErrorKind::NoLayers));
+        }
+        if input_shape.is_empty() {
+            return Err(ShapeError::new(
+                "layers.0",
+                ShapeErrorKind::RankMismatch {
+                    expected: 1,
+                    actual: vec![],
+                },
+            ));
+        }
+        let mut input_size = self.input_size;
+        let mut shape = input_shape.to_vec();
+        let mut shapes = vec![];
+        for (i, (layer, activation, norm, _)) in self
+            .layers
+            .iter()
+            .cloned()
+            .map(Either::into_tuple)
+            .enumerate()
+        {
+            let name = format!("layers.{i}");
+            expect_size(&name, input_size, *shape.last().unwrap())?;
+            let output_size = layer.output_size();
+            let norm_params = norm_params(&name, &norm, &self.default_norm, output_size)?;
+            let mut weights = input_size * output_size;
+            if layer.residual() && input_size != output_size {
+                // The projection of the skip connection
+                weights *= 2;
+            }
+            let bias = if norm_params.is_none() {
+                output_size
+            } else {
+                0
+            };
+            let macs = weights * positions(&shape);
+            *shape.last_mut().unwrap() = output_size;
+            let mut layer = LayerShape {
+                name,
+                output_shape: shape,
+                params: weights + bias + norm_params.unwrap_or_default(),
+                macs,
+            };
+            apply_activation(&mut layer, &activation, &self.default_activation)?;
+            input_size = output_size;
+            shape = layer.output_shape.clone();
+            shapes.push(layer);
+        }
+        Ok(shapes)
+    }
+}
+
 default_f!(default_dropout, f64, 0.0);
 default_f!(default_dropout_last, bool, true);
@@ -1,8 +1,13 @@
 use burn::{
     Tensor,
-    nn::loss::{MseLoss, Reduction},
-    prelude::Backend,
+    nn::loss::{CrossEntropyLossConfig, HuberLossConfig, MseLoss, Reduction},
+    prelude::{Backend, Int},
+    tensor::activation::{log_softmax, softmax},
 };
+use serde::{Deserialize, Deserializer, Serialize, de::Error};
+use utils::default_f;
+
+use crate::metrics::{as_images, ms_ssim, ssim};
 
 const EPSILON: f64 = 1e-7;
 
@@ -22,3 +27,204 @@ pub fn mse<B: Backend, const D: usize>(
 ) -> Tensor<B, 1> {
     MseLoss::new().forward(actual, expected, Reduction::Mean)
 }
+
+pub fn l1<B: Backend, const D: usize>(
+    expected: Tensor<B, D>,
+    actual: Tensor<B, D>,
+) -> Tensor<B, 1> {
+    (actual - expected).abs().mean()
+}
+
+/// Quadratic for errors below `delta` and linear above it, which makes it less sensitive to
+/// outliers than [`mse`].
+pub fn huber<B: Backend, const D: usize>(
+    expected: Tensor<B, D>,
+    actual: Tensor<B, D>,
+    delta: f32,
+) -> Tensor<B, 1> {
+    HuberLossConfig::new(delta)
+        .init()
+        .forward(actual, expected, Reduction::Mean)
+}
+
+/// The cross-entropy between the softmax of `logits`, `[batch_size, num_classes]`, and the class
+/// `labels`, `[batch_size]`.
+pub fn cross_entropy_loss<B: Backend>(
+    labels: Tensor<B, 1, Int>,
+    logits: Tensor<B, 2>,
+) -> Tensor<B, 1> {
+    CrossEntropyLossConfig::new()
+        .init(&logits.device())
+        .forward(logits, labels)
+}
+
+/// The KL divergence from the probabilities of `teacher_logits` to those of `student_logits`, both
+/// `[batch_size, num_classes]`, for knowledge distillation.
+///
+/// Both are softened by `temperature` first, so that a temperature above 1 also teaches the
+/// student how the teacher ranks the wrong classes. The loss is scaled by the square of the
+/// temperature, which keeps its gradients the same size as the temperature changes.
+pub fn distillation_loss<B: Backend>(
+    teacher_logits: Tensor<B, 2>,
+    student_logits: Tensor<B, 2>,
+    temperature: f32,
+) -> Tensor<B, 1> {
+    let teacher = log_softmax(teacher_logits.detach().div_scalar(temperature), 1);
+    let student = log_softmax(student_logits.div_scalar(temperature), 1);
+    let divergence = (teacher.clone().exp() * (teacher - student)).sum_dim(1);
+    divergence.mean().mul_scalar(temperature * temperature)
+}
+
+/// Like [`huber`], but divided by `beta`, so that the linear part has a slope of 1 like [`l1`].
+pub fn smooth_l1<B: Backend, const D: usize>(
+    expected: Tensor<B, D>,
+    actual: Tensor<B, D>,
+    beta: f32,
+) -> Tensor<B, 1> {
+    huber(expected, actual, beta).div_scalar(beta)
+}
+
+/// A differentiable [`l1`], `sqrt(error^2 + epsilon^2)`, which is smooth around 0.
+pub fn charbonnier<B: Backend, const D: usize>(
+    expected: Tensor<B, D>,
+    actual: Tensor<B, D>,
+    epsilon: f32,
+) -> Tensor<B, 1> {
+    (actual - expected)
+        .square()
+        .add_scalar(epsilon * epsilon)
+        .sqrt()
+        .mean()
+}
+
+/// The cross-entropy between the softmax of `logits` over dim 1 and the probabilities
+/// `expected`, such as one-hot labels. Confidently right predictions are down-weighted by
+/// `(1 - p)^gamma`, which focuses training on the hard ones. A `gamma` of 0 is the plain
+/// cross-entropy.
+pub fn focal_loss<B: Backend, const D: usize>(
+    expected: Tensor<B, D>,
+    logits: Tensor<B, D>,
+    gamma: f32,
+) -> Tensor<B, 1> {
+    let log_probs = log_softmax(logits, 1);
+    let mut loss = expected.detach() * log_probs.clone();
+    if gamma != 0.0 {
+        loss = loss * (-log_probs.exp() + 1.0).powf_scalar(gamma);
+    }
+    -loss.sum_dim(1).mean()
+}
+
+/// A loss between the output of a model and what it was expected to output, selected in
+/// training.json.
+///
+/// Classifiers are given one-hot labels as the expected output. `cross_entropy` and `focal` are
+/// given their logits as the actual output, and every other loss the softmax of the logits.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum LossConfig {
+    /// The binary cross-entropy, for outputs in [0, 1].
+    Bce,
+    Mse,
+    L1,
+    Huber {
+        #[serde(default = "default_huber_delta")]
+        delta: f32,
+    },
+    SmoothL1 {
+        #[serde(default = "default_smooth_l1_beta")]
+        beta: f32,
+    },
+    Charbonnier {
+        #[serde(default = "default_charbonnier_epsilon")]
+        epsilon: f32,
+    },
+    /// `1 - SSIM`, for images with values in [0, 1].
+    Ssim,
+    /// `1 - MS-SSIM`, for images with values in [0, 1].
+    MsSsim,
+    /// The cross-entropy of logits over dim 1.
+    CrossEntropy,
+    /// The focal loss of logits over dim 1.
+    Focal {
+        #[serde(default = "default_focal_gamma")]
+        gamma: f32,
+    },
+    /// A weighted sum of several losses, such as
+    /// `{ "weighted": [{ "loss": "bce", "weight": 0.8 }, { "loss": "ssim", "weight": 0.2 }] }`.
+    Weighted(#[serde(deserialize_with = "non_empty")] Vec<WeightedLossConfig>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WeightedLossConfig {
+    pub loss: LossConfig,
+    #[serde(default = "default_loss_weight")]
+    pub weight: f32,
+}
+
+impl LossConfig {
+    pub fn forward<B: Backend, const D: usize>(
+        &self,
+        expected: Tensor<B, D>,
+        actual: Tensor<B, D>,
+    ) -> Tensor<B, 1> {
+        match self {
+            LossConfig::Bce => bce_float_loss(expected, actual),
+            LossConfig::Mse => mse(expected, actual),
+            LossConfig::L1 => l1(expected, actual),
+            LossConfig::Huber { delta } => huber(expected, actual, *delta),
+            LossConfig::SmoothL1 { beta } => smooth_l1(expected, actual, *beta),
+            LossConfig::Charbonnier { epsilon } => charbonnier(expected, actual, *epsilon),
+            LossConfig::Ssim => -ssim(as_images(expected), as_images(actual)) + 1.0,
+            LossConfig::MsSsim => -ms_ssim(as_images(expected), as_images(actual)) + 1.0,
+            LossConfig::CrossEntropy => focal_loss(expected, actual, 0.0),
+            LossConfig::Focal { gamma } => focal_loss(expected, actual, *gamma),
+            LossConfig::Weighted(losses) => losses
+                .iter()
+                .map(|x| x.loss.forward(expected.clone(), actual.clone()) * x.weight)
+                .reduce(|a, b| a + b)
+                .expect("Expected a weighted loss to have at least one loss"),
+        }
+    }
+
+    /// The loss between the `logits` of a classifier, `[batch_size, num_classes]`, and the class
+    /// `labels`, `[batch_size]`.
+    pub fn forward_classes<B: Backend>(
+        &self,
+        labels: Tensor<B, 1, Int>,
+        logits: Tensor<B, 2>,
+    ) -> Tensor<B, 1> {
+        match self {
+            LossConfig::CrossEntropy => cross_entropy_loss(labels, logits),
+            LossConfig::Focal { gamma } => {
+                let [_, num_classes] = logits.dims();
+                focal_loss(labels.one_hot::<2>(num_classes).float(), logits, *gamma)
+            }
+            LossConfig::Weighted(losses) => losses
+                .iter()
+                .map(|x| x.loss.forward_classes(labels.clone(), logits.clone()) * x.weight)
+                .reduce(|a, b| a + b)
+                .expect("Expected a weighted loss to have at least one loss"),
+            _ => {
+                let [_, num_classes] = logits.dims();
+                self.forward(labels.one_hot::<2>(num_classes).float(), softmax(logits, 1))
+            }
+        }
+    }
+}
+
+/// Rejects an empty list of weighted losses when the config is parsed, rather than in training.
+fn non_empty<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<WeightedLossConfig>, D::Error> {
+    let losses = Vec::deserialize(deserializer)?;
+    if losses.is_empty() {
+        return Err(D::Error::invalid_length(0, &"at least one weighted loss"));
+    }
+    Ok(losses)
+}
+
+default_f!(default_huber_delta, f32, 1.0);
+default_f!(default_smooth_l1_beta, f32, 1.0);
+default_f!(default_charbonnier_epsilon, f32, 1e-3);
+default_f!(default_focal_gamma, f32, 2.0);
+default_f!(default_loss_weight, f32, 1.0);
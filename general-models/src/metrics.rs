@@ -0,0 +1,539 @@
+//! Metrics that measure how well a model does, apart from its loss, and accumulators that
+//! aggregate them over the batches of an epoch.
+
+use std::fmt::Display;
+
+use burn::{
+    nn::{
+        loss::{MseLoss, Reduction},
+        pool::AvgPool2dConfig,
+    },
+    prelude::*,
+    tensor::{module::conv2d, ops::ConvOptions},
+};
+use serde::{Deserialize, Serialize};
+
+/// Peak Signal-to-Noise Ratio between two float tensors whose values are in [0, 1].
+///
+/// - \>40 dB: Excellent quality, differences barely perceptible
+/// - 30-40 dB: Good quality, acceptable for most applications
+/// - 20-30 dB: Fair quality, noticeable differences
+/// - <20 dB: Poor quality, significant degradation
+pub fn psnr<B: Backend, const N: usize>(a: Tensor<B, N>, b: Tensor<B, N>) -> f32 {
+    let mse = MseLoss::new().forward(a, b, Reduction::Mean);
+    10.0 * (1.0 / mse.into_scalar().elem::<f32>()).log10()
+}
+
+/// Peak Signal-to-Noise Ratio between two batched float tensors whose values are in [0, 1]. It is
+/// assumed that the first axis is the batch axis.
+///
+/// - \>40 dB: Excellent quality, differences barely perceptible
+/// - 30-40 dB: Good quality, acceptable for most applications
+/// - 20-30 dB: Fair quality, noticeable differences
+/// - <20 dB: Poor quality, significant degradation
+pub fn psnr_batched<B: Backend, const N: usize>(a: Tensor<B, N>, b: Tensor<B, N>) -> Tensor<B, 1> {
+    assert_eq!(a.shape(), b.shape(), "The shapes of a and b are different");
+    let batch_size = a.dims()[0];
+    let mse = a
+        .sub(b)
+        .square()
+        .mean_dims(&(1..N).collect::<Vec<_>>())
+        .reshape([batch_size]);
+    mse.recip().log().div_scalar(10f32.ln()).mul_scalar(10.0)
+}
+
+pub fn psnr_mask<B: Backend>(psnr_level: Tensor<B, 1>, min_level: f32) -> Tensor<B, 1, Bool> {
+    psnr_level.greater_elem(min_level)
+}
+
+/// Mean absolute error of each item of two batched float tensors, `[batch_size]`.
+pub fn mae_batched<B: Backend, const N: usize>(a: Tensor<B, N>, b: Tensor<B, N>) -> Tensor<B, 1> {
+    assert_eq!(a.shape(), b.shape(), "The shapes of a and b are different");
+    let batch_size = a.dims()[0];
+    a.sub(b)
+        .abs()
+        .mean_dims(&(1..N).collect::<Vec<_>>())
+        .reshape([batch_size])
+}
+
+/// The side of the gaussian window of [`ssim`], which is shrunk for smaller images.
+const SSIM_WINDOW_SIZE: usize = 11;
+const SSIM_SIGMA: f32 = 1.5;
+/// The stabilizing constants of [`ssim`], for values in [0, 1].
+const SSIM_C1: f32 = 0.01 * 0.01;
+const SSIM_C2: f32 = 0.03 * 0.03;
+/// The weights of the 5 scales of [`ms_ssim`], from the finest to the coarsest.
+const MS_SSIM_WEIGHTS: [f32; 5] = [0.0448, 0.2856, 0.3001, 0.2363, 0.1333];
+
+/// A normalized gaussian window for each channel, `[channels, 1, size, size]`, to convolve each
+/// channel separately.
+fn gaussian_window<B: Backend>(channels: usize, size: usize, device: &B::Device) -> Tensor<B, 4> {
+    let center = (size as f32 - 1.0) / 2.0;
+    let gaussian: Vec<f32> = (0..size)
+        .map(|i| (-(i as f32 - center).powi(2) / (2.0 * SSIM_SIGMA * SSIM_SIGMA)).exp())
+        .collect();
+    let sum: f32 = gaussian.iter().sum();
+    let window: Vec<f32> = gaussian
+        .iter()
+        .flat_map(|y| gaussian.iter().map(move |x| x * y / (sum * sum)))
+        .collect();
+    Tensor::<B, 1>::from_floats(window.as_slice(), device)
+        .reshape([1, 1, size, size])
+        .repeat_dim(0, channels)
+}
+
+/// The mean SSIM and contrast-structure terms of each image and channel, `[batch_size, channels]`.
+fn ssim_terms<B: Backend>(
+    expected: Tensor<B, 4>,
+    actual: Tensor<B, 4>,
+) -> (Tensor<B, 2>, Tensor<B, 2>) {
+    let [batch_size, channels, height, width] = actual.dims();
+    let size = SSIM_WINDOW_SIZE.min(height).min(width);
+    let window = gaussian_window::<B>(channels, size, &actual.device());
+    let blur = |x: Tensor<B, 4>| {
+        conv2d(
+            x,
+            window.clone(),
+            None,
+            ConvOptions::new([1, 1], [0, 0], [1, 1], channels),
+        )
+    };
+    let expected = expected.detach();
+    let mu_x = blur(expected.clone());
+    let mu_y = blur(actual.clone());
+    let mu_xx = mu_x.clone().square();
+    let mu_yy = mu_y.clone().square();
+    let mu_xy = mu_x * mu_y;
+    let sigma_xx = blur(expected.clone().square()) - mu_xx.clone();
+    let sigma_yy = blur(actual.clone().square()) - mu_yy.clone();
+    let sigma_xy = blur(expected * actual) - mu_xy.clone();
+
+    let cs = (sigma_xy * 2.0 + SSIM_C2) / (sigma_xx + sigma_yy + SSIM_C2);
+    let luminance = (mu_xy * 2.0 + SSIM_C1) / (mu_xx + mu_yy + SSIM_C1);
+    let mean = |x: Tensor<B, 4>| {
+        x.flatten::<3>(2, 3)
+            .mean_dim(2)
+            .reshape([batch_size, channels])
+    };
+    (mean(luminance * cs.clone()), mean(cs))
+}
+
+/// The mean structural similarity between two batches of images, `[batch_size, channels, height,
+/// width]` with values in [0, 1]. It is 1 for identical images.
+pub fn ssim<B: Backend>(expected: Tensor<B, 4>, actual: Tensor<B, 4>) -> Tensor<B, 1> {
+    ssim_terms(expected, actual).0.mean()
+}
+
+/// The [`ssim`] of each image of two batches, averaged over the channels, `[batch_size]`.
+pub fn ssim_batched<B: Backend>(expected: Tensor<B, 4>, actual: Tensor<B, 4>) -> Tensor<B, 1> {
+    let batch_size = actual.dims()[0];
+    ssim_terms(expected, actual)
+        .0
+        .mean_dim(1)
+        .reshape([batch_size])
+}
+
+/// The multi-scale [`ssim`], which compares the structure of the images at up to 5 scales, each
+/// half the size of the previous one. Small images use fewer scales, as long as they are at least
+/// as large as the window.
+pub fn ms_ssim<B: Backend>(mut expected: Tensor<B, 4>, mut actual: Tensor<B, 4>) -> Tensor<B, 1> {
+    let [_, _, height, width] = actual.dims();
+    let mut scales = 1;
+    while scales < MS_SSIM_WEIGHTS.len()
+        && height.min(width) >> scales >= SSIM_WINDOW_SIZE.min(height).min(width)
+    {
+        scales += 1;
+    }
+    let weights = &MS_SSIM_WEIGHTS[..scales];
+    let total: f32 = weights.iter().sum();
+    let downsample = AvgPool2dConfig::new([2, 2]).with_strides([2, 2]).init();
+
+    let mut similarity = None;
+    for (i, weight) in weights.iter().enumerate() {
+        let (ssim, cs) = ssim_terms(expected.clone(), actual.clone());
+        // The coarsest scale compares the luminance too
+        let term = if i == scales - 1 { ssim } else { cs };
+        // Negative similarities can't be raised to fractional powers
+        let term = term.clamp_min(0.0).powf_scalar(weight / total);
+        similarity = Some(match similarity {
+            Some(similarity) => similarity * term,
+            None => term,
+        });
+        expected = downsample.forward(expected);
+        actual = downsample.forward(actual);
+    }
+    similarity.unwrap().mean()
+}
+
+/// Reshapes a batch of images to rank 4, for the losses and metrics that need images.
+pub(crate) fn as_images<B: Backend, const D: usize>(tensor: Tensor<B, D>) -> Tensor<B, 4> {
+    let shape: [usize; 4] = tensor.dims().as_slice().try_into().unwrap_or_else(|_| {
+        panic!("Expected images of [batch_size, channels, height, width] for SSIM")
+    });
+    tensor.reshape(shape)
+}
+
+/// Aggregates a stream of values, such as the metric of every item of the batches of an epoch.
+#[derive(Debug, Clone, Default)]
+pub struct RunningStats {
+    /// Every value, to compute percentiles.
+    values: Vec<f64>,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl RunningStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if self.values.is_empty() {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.sum += value;
+        self.values.push(value);
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn count(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    pub fn mean(&self) -> f64 {
+        self.sum / self.count().max(1) as f64
+    }
+
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    /// The value below which `p` percent of the values fall, interpolating between the two
+    /// closest values.
+    pub fn percentile(&self, p: f64) -> f64 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+        let mut sorted = self.values.clone();
+        sorted.sort_unstable_by(f64::total_cmp);
+        let rank = p.clamp(0.0, 100.0) / 100.0 * (sorted.len() - 1) as f64;
+        let (lower, upper) = (sorted[rank.floor() as usize], sorted[rank.ceil() as usize]);
+        lower + (upper - lower) * rank.fract()
+    }
+}
+
+impl Display for RunningStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:.4} (Min: {:.4}; P5: {:.4}; P50: {:.4}; P95: {:.4}; Max: {:.4})",
+            self.mean(),
+            self.min,
+            self.percentile(5.0),
+            self.percentile(50.0),
+            self.percentile(95.0),
+            self.max
+        )
+    }
+}
+
+/// Accumulates the accuracy, top-k accuracy, F1 score and confusion matrix of a classifier over
+/// batches.
+#[derive(Debug, Clone)]
+pub struct ClassificationStats {
+    num_classes: usize,
+    top_k: usize,
+    /// `confusion[actual * num_classes + predicted]`
+    confusion: Vec<usize>,
+    top_k_correct: usize,
+}
+
+impl ClassificationStats {
+    pub fn new(num_classes: usize, top_k: usize) -> Self {
+        Self {
+            num_classes,
+            top_k,
+            confusion: vec![0; num_classes * num_classes],
+            top_k_correct: 0,
+        }
+    }
+
+    /// Adds a batch of class scores, `[batch_size, num_classes]`, and their labels,
+    /// `[batch_size]`. The scores can be either logits or probabilities.
+    pub fn update<B: Backend>(&mut self, scores: Tensor<B, 2>, labels: Tensor<B, 1, Int>) {
+        let scores = scores
+            .into_data()
+            .convert::<f32>()
+            .into_vec::<f32>()
+            .unwrap();
+        let labels = labels
+            .into_data()
+            .convert::<i64>()
+            .into_vec::<i64>()
+            .unwrap();
+        for (scores, &label) in scores.chunks(self.num_classes).zip(&labels) {
+            let label = label as usize;
+            let predicted = scores
+                .iter()
+                .enumerate()
+                .max_by(|(_, a), (_, b)| a.total_cmp(b))
+                .map(|(i, _)| i)
+                .unwrap();
+            self.confusion[label * self.num_classes + predicted] += 1;
+            // The label is within the top k if fewer than k classes scored higher
+            let rank = scores.iter().filter(|&&x| x > scores[label]).count();
+            if rank < self.top_k {
+                self.top_k_correct += 1;
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.confusion.fill(0);
+        self.top_k_correct = 0;
+    }
+
+    pub fn get_top_k(&self) -> usize {
+        self.top_k
+    }
+
+    pub fn total(&self) -> usize {
+        self.confusion.iter().sum()
+    }
+
+    pub fn accuracy(&self) -> f64 {
+        let correct: usize = (0..self.num_classes)
+            .map(|i| self.confusion[i * self.num_classes + i])
+            .sum();
+        correct as f64 / self.total().max(1) as f64
+    }
+
+    pub fn top_k_accuracy(&self) -> f64 {
+        self.top_k_correct as f64 / self.total().max(1) as f64
+    }
+
+    /// The F1 score averaged over the classes that were either seen or predicted.
+    pub fn f1(&self) -> f64 {
+        let n = self.num_classes;
+        let scores: Vec<f64> = (0..n)
+            .filter_map(|class| {
+                let true_positives = self.confusion[class * n + class];
+                let actual: usize = self.confusion[class * n..(class + 1) * n].iter().sum();
+                let predicted: usize = (0..n).map(|i| self.confusion[i * n + class]).sum();
+                (actual + predicted > 0)
+                    .then(|| 2.0 * true_positives as f64 / (actual + predicted) as f64)
+            })
+            .collect();
+        scores.iter().sum::<f64>() / scores.len().max(1) as f64
+    }
+
+    /// The rows are the actual classes and the columns are the predicted classes.
+    pub fn confusion_matrix(&self) -> Vec<Vec<usize>> {
+        self.confusion
+            .chunks(self.num_classes)
+            .map(<[usize]>::to_vec)
+            .collect()
+    }
+}
+
+impl Display for ClassificationStats {
+    /// Formats the confusion matrix as a table, with the actual classes as rows.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .confusion
+            .iter()
+            .max()
+            .map(|x| x.to_string().len())
+            .unwrap_or(1)
+            .max(self.num_classes.to_string().len());
+        write!(f, "{:>width$}", "")?;
+        for predicted in 0..self.num_classes {
+            write!(f, " {predicted:>width$}")?;
+        }
+        writeln!(f)?;
+        for (actual, row) in self.confusion.chunks(self.num_classes).enumerate() {
+            write!(f, "{actual:>width$}")?;
+            for count in row {
+                write!(f, " {count:>width$}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// A metric to report during validation, selected in training.json.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricConfig {
+    /// The PSNR of each output, for outputs in [0, 1].
+    Psnr,
+    /// The SSIM of each image, for images with values in [0, 1].
+    Ssim,
+    /// The mean absolute error of each output.
+    Mae,
+    /// The accuracy of a classifier.
+    Accuracy,
+    /// The F1 score of a classifier, averaged over the classes.
+    F1,
+}
+
+impl MetricConfig {
+    /// Whether the metric is computed from the predicted classes of a classifier, rather than
+    /// from each output.
+    pub fn is_classification(self) -> bool {
+        matches!(self, MetricConfig::Accuracy | MetricConfig::F1)
+    }
+
+    /// Whether higher values of the metric are better, as for PSNR but not for MAE.
+    pub fn higher_is_better(self) -> bool {
+        !matches!(self, MetricConfig::Mae)
+    }
+}
+
+impl Display for MetricConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            MetricConfig::Psnr => "PSNR",
+            MetricConfig::Ssim => "SSIM",
+            MetricConfig::Mae => "MAE",
+            MetricConfig::Accuracy => "Accuracy",
+            MetricConfig::F1 => "F1",
+        })
+    }
+}
+
+/// Accumulates the configured metrics over the batches of an epoch.
+///
+/// The metrics of each output are aggregated into [`RunningStats`], while the metrics of a
+/// classifier are computed from its confusion matrix.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    /// The metrics along with the values of each output, which are left empty for the metrics
+    /// of a classifier.
+    metrics: Vec<(MetricConfig, RunningStats)>,
+    classification: Option<ClassificationStats>,
+}
+
+impl Metrics {
+    pub fn new(metrics: &[MetricConfig]) -> Self {
+        Self {
+            metrics: metrics.iter().map(|&x| (x, RunningStats::new())).collect(),
+            classification: None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.metrics.is_empty()
+    }
+
+    /// Adds a batch of outputs of a model and what it was expected to output, `[batch_size,
+    /// ...]`. SSIM needs images, `[batch_size, channels, height, width]`. The metrics of a
+    /// classifier are left out, see [`Metrics::update_classes`].
+    pub fn update<B: Backend, const D: usize>(
+        &mut self,
+        expected: Tensor<B, D>,
+        actual: Tensor<B, D>,
+    ) {
+        for (metric, stats) in &mut self.metrics {
+            let values = match metric {
+                MetricConfig::Psnr => psnr_batched(expected.clone(), actual.clone()),
+                MetricConfig::Ssim => {
+                    ssim_batched(as_images(expected.clone()), as_images(actual.clone()))
+                }
+                MetricConfig::Mae => mae_batched(expected.clone(), actual.clone()),
+                MetricConfig::Accuracy | MetricConfig::F1 => continue,
+            };
+            let values = values
+                .into_data()
+                .convert::<f64>()
+                .into_vec::<f64>()
+                .unwrap();
+            for value in values {
+                stats.push(value);
+            }
+        }
+    }
+
+    /// Adds a batch of class scores of a classifier, `[batch_size, num_classes]`, and their
+    /// labels, `[batch_size]`, for the metrics of a classifier.
+    pub fn update_classes<B: Backend>(&mut self, scores: Tensor<B, 2>, labels: Tensor<B, 1, Int>) {
+        if !self.metrics.iter().any(|(x, _)| x.is_classification()) {
+            return;
+        }
+        let [_, num_classes] = scores.dims();
+        self.classification
+            .get_or_insert_with(|| ClassificationStats::new(num_classes, 1))
+            .update(scores, labels);
+    }
+
+    /// Clears the values of every metric, before the next epoch.
+    pub fn reset(&mut self) {
+        for (_, stats) in &mut self.metrics {
+            stats.reset();
+        }
+        self.classification = None;
+    }
+
+    /// The values of a metric of each output, if it was configured.
+    pub fn get_stats(&self, metric: MetricConfig) -> Option<&RunningStats> {
+        self.metrics
+            .iter()
+            .find(|(x, _)| *x == metric)
+            .map(|(_, stats)| stats)
+    }
+
+    /// The value of a metric over the epoch, or the mean value of the metrics of each output. It
+    /// is `None` if the metric wasn't configured or nothing was added yet.
+    pub fn get(&self, metric: MetricConfig) -> Option<f64> {
+        if metric.is_classification() {
+            self.get_stats(metric)?;
+            let classification = self.classification.as_ref()?;
+            Some(match metric {
+                MetricConfig::Accuracy => classification.accuracy(),
+                _ => classification.f1(),
+            })
+        } else {
+            let stats = self.get_stats(metric)?;
+            (!stats.is_empty()).then(|| stats.mean())
+        }
+    }
+}
+
+impl Display for Metrics {
+    /// Formats the metrics that have values, such as `PSNR: 24.3115 (Min: ...); Accuracy: 0.9120`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut first = true;
+        for (metric, stats) in &self.metrics {
+            if metric.is_classification() {
+                let Some(value) = self.get(*metric) else {
+                    continue;
+                };
+                write!(f, "{}{metric}: {value:.4}", if first { "" } else { "; " })?;
+            } else {
+                if stats.is_empty() {
+                    continue;
+                }
+                write!(f, "{}{metric}: {stats}", if first { "" } else { "; " })?;
+            }
+            first = false;
+        }
+        Ok(())
+    }
+}
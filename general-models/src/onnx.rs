@@ -0,0 +1,1266 @@
+//! Export of models to [ONNX](https://onnx.ai).
+//!
+//! There is no protobuf dependency, so the few messages that make up an ONNX model are encoded by
+//! hand. Shapes are tracked statically while the graph is built, except for the batch axis, which
+//! stays dynamic.
+
+use burn::{
+    module::{Content, DisplaySettings, ModuleDisplay},
+    nn::{
+        Linear, PaddingConfig2d,
+        activation::Activation,
+        conv::{Conv2d, ConvTranspose2d},
+        interpolate::{Interpolate2d, InterpolateMode},
+        pool::AdaptiveAvgPool2d,
+    },
+    prelude::*,
+};
+
+use crate::{common::Norm, conv::Pool2d, error::ExportError};
+
+/// The opset that exported models import. 17 is the first to have `LayerNormalization`.
+const OPSET_VERSION: i64 = 17;
+/// The IR version that goes with [`OPSET_VERSION`].
+const IR_VERSION: i64 = 8;
+
+const FLOAT: i64 = 1;
+const INT64: i64 = 7;
+
+/// A protobuf message, built field by field.
+#[derive(Debug, Default, Clone)]
+struct Message(Vec<u8>);
+
+impl Message {
+    fn varint(&mut self, mut value: u64) {
+        while value >= 0x80 {
+            self.0.push(value as u8 | 0x80);
+            value >>= 7;
+        }
+        self.0.push(value as u8);
+    }
+
+    fn tag(&mut self, field: u64, wire_type: u64) {
+        self.varint((field << 3) | wire_type);
+    }
+
+    fn int(mut self, field: u64, value: i64) -> Self {
+        self.tag(field, 0);
+        self.varint(value as u64);
+        self
+    }
+
+    fn float(mut self, field: u64, value: f32) -> Self {
+        self.tag(field, 5);
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn bytes(mut self, field: u64, bytes: &[u8]) -> Self {
+        self.tag(field, 2);
+        self.varint(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+        self
+    }
+
+    fn string(self, field: u64, string: &str) -> Self {
+        self.bytes(field, string.as_bytes())
+    }
+
+    fn message(self, field: u64, message: Message) -> Self {
+        self.bytes(field, &message.0)
+    }
+}
+
+/// The value of an attribute of a node.
+#[derive(Debug, Clone)]
+pub enum Attribute {
+    Int(i64),
+    Float(f32),
+    Ints(Vec<i64>),
+    String(&'static str),
+}
+
+impl Attribute {
+    fn encode(&self, name: &str) -> Message {
+        let message = Message::default().string(1, name);
+        // The field of the value, then the `AttributeType`
+        match self {
+            Attribute::Float(value) => message.float(2, *value).int(20, 1),
+            Attribute::Int(value) => message.int(3, *value).int(20, 2),
+            Attribute::String(value) => message.string(4, value).int(20, 3),
+            Attribute::Ints(values) => values
+                .iter()
+                .fold(message, |message, value| message.int(8, *value))
+                .int(20, 7),
+        }
+    }
+}
+
+/// A tensor flowing through an [`OnnxGraph`].
+#[derive(Debug, Clone)]
+pub struct OnnxValue {
+    pub name: String,
+    /// The shape without the batch axis.
+    pub shape: Vec<usize>,
+}
+
+impl OnnxValue {
+    pub fn new(name: String, shape: Vec<usize>) -> Self {
+        OnnxValue { name, shape }
+    }
+}
+
+/// An ONNX graph under construction, with a dynamic batch axis on every input and output.
+#[derive(Debug, Default)]
+pub struct OnnxGraph {
+    nodes: Vec<Message>,
+    initializers: Vec<Message>,
+    inputs: Vec<Message>,
+    next_id: usize,
+}
+
+fn value_info(name: &str, shape: &[usize]) -> Message {
+    let shape = shape.iter().fold(
+        Message::default().message(1, Message::default().string(2, "batch_size")),
+        |message, &dim| message.message(1, Message::default().int(1, dim as i64)),
+    );
+    let tensor_type = Message::default().int(1, FLOAT).message(2, shape);
+    Message::default()
+        .string(1, name)
+        .message(2, Message::default().message(1, tensor_type))
+}
+
+fn ints(values: &[usize]) -> Attribute {
+    Attribute::Ints(values.iter().map(|&x| x as i64).collect())
+}
+
+impl OnnxGraph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn fresh_name(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{prefix}_{}", self.next_id)
+    }
+
+    /// Adds an input to the graph. `shape` excludes the batch axis.
+    pub fn input(&mut self, name: &str, shape: &[usize]) -> OnnxValue {
+        self.inputs.push(value_info(name, shape));
+        OnnxValue {
+            name: name.to_string(),
+            shape: shape.to_vec(),
+        }
+    }
+
+    fn initializer(&mut self, data_type: i64, shape: &[usize], raw_data: Vec<u8>) -> String {
+        let name = self.fresh_name("init");
+        let tensor = shape
+            .iter()
+            .fold(Message::default(), |message, &dim| {
+                message.int(1, dim as i64)
+            })
+            .int(2, data_type)
+            .string(8, &name)
+            .bytes(9, &raw_data);
+        self.initializers.push(tensor);
+        name
+    }
+
+    /// Adds a constant float tensor of the given shape.
+    pub fn floats(&mut self, shape: &[usize], values: &[f32]) -> String {
+        let raw_data = values.iter().flat_map(|x| x.to_le_bytes()).collect();
+        self.initializer(FLOAT, shape, raw_data)
+    }
+
+    /// Adds a constant float scalar.
+    pub fn scalar(&mut self, value: f32) -> String {
+        self.floats(&[], &[value])
+    }
+
+    /// Adds a constant 1D int64 tensor, as used for shapes and axes.
+    pub fn ints(&mut self, values: &[i64]) -> String {
+        let raw_data = values.iter().flat_map(|x| x.to_le_bytes()).collect();
+        self.initializer(INT64, &[values.len()], raw_data)
+    }
+
+    /// Adds the weights of a module as a constant.
+    pub fn tensor<B: Backend, const D: usize>(&mut self, tensor: Tensor<B, D>) -> String {
+        let shape = tensor.dims();
+        let values = tensor
+            .into_data()
+            .convert::<f32>()
+            .into_vec::<f32>()
+            .unwrap();
+        self.floats(&shape, &values)
+    }
+
+    /// Adds a node with a single output and returns the name of the output.
+    pub fn node(
+        &mut self,
+        op_type: &str,
+        inputs: &[&str],
+        attributes: &[(&str, Attribute)],
+    ) -> String {
+        let output = self.fresh_name(&op_type.to_lowercase());
+        let node = inputs
+            .iter()
+            .fold(Message::default(), |message, input| {
+                message.string(1, input)
+            })
+            .string(2, &output)
+            .string(3, &output)
+            .string(4, op_type);
+        let node = attributes.iter().fold(node, |message, (name, value)| {
+            message.message(5, value.encode(name))
+        });
+        self.nodes.push(node);
+        output
+    }
+
+    /// Adds a node that keeps the shape of its input.
+    pub fn unary(
+        &mut self,
+        op_type: &str,
+        input: &OnnxValue,
+        attributes: &[(&str, Attribute)],
+    ) -> OnnxValue {
+        let name = self.node(op_type, &[&input.name], attributes);
+        OnnxValue::new(name, input.shape.clone())
+    }
+
+    /// Applies a binary op between `input` and a constant or another value broadcastable to it.
+    pub fn binary(&mut self, op_type: &str, input: &OnnxValue, other: &str) -> OnnxValue {
+        let name = self.node(op_type, &[&input.name, other], &[]);
+        OnnxValue::new(name, input.shape.clone())
+    }
+
+    /// Reshapes everything but the batch axis.
+    pub fn reshape(&mut self, input: &OnnxValue, shape: &[usize]) -> OnnxValue {
+        let mut dims = vec![0];
+        dims.extend(shape.iter().map(|&x| x as i64));
+        let dims = self.ints(&dims);
+        let name = self.node("Reshape", &[&input.name, &dims], &[]);
+        OnnxValue::new(name, shape.to_vec())
+    }
+
+    /// Adds a per-channel constant, reshaped to broadcast along axis 1 of `input`.
+    fn channel_tensor<B: Backend>(&mut self, input: &OnnxValue, tensor: Tensor<B, 1>) -> String {
+        let mut shape = tensor.dims().to_vec();
+        shape.extend(std::iter::repeat_n(1, input.shape.len().saturating_sub(1)));
+        let values = tensor
+            .into_data()
+            .convert::<f32>()
+            .into_vec::<f32>()
+            .unwrap();
+        self.floats(&shape, &values)
+    }
+
+    /// Encodes the graph as a serialized `ModelProto`, with `outputs` renamed to their given
+    /// names.
+    pub fn finish(mut self, outputs: &[(&str, OnnxValue)]) -> Vec<u8> {
+        let mut output_infos = vec![];
+        for (name, value) in outputs {
+            let node = Message::default()
+                .string(1, &value.name)
+                .string(2, name)
+                .string(3, name)
+                .string(4, "Identity");
+            self.nodes.push(node);
+            output_infos.push(value_info(name, &value.shape));
+        }
+
+        let graph = self
+            .nodes
+            .into_iter()
+            .fold(Message::default(), |message, node| message.message(1, node))
+            .string(2, "main");
+        let graph = self
+            .initializers
+            .into_iter()
+            .fold(graph, |message, tensor| message.message(5, tensor));
+        let graph = self
+            .inputs
+            .into_iter()
+            .fold(graph, |message, input| message.message(11, input));
+        let graph = output_infos
+            .into_iter()
+            .fold(graph, |message, output| message.message(12, output));
+
+        Message::default()
+            .int(1, IR_VERSION)
+            .string(2, env!("CARGO_PKG_NAME"))
+            .string(3, env!("CARGO_PKG_VERSION"))
+            .message(7, graph)
+            .message(8, Message::default().int(2, OPSET_VERSION))
+            .0
+    }
+}
+
+/// A model that can be exported to ONNX for inference.
+pub trait ToOnnx {
+    /// Adds the nodes of the model to `graph`, returning the output.
+    fn to_onnx(&self, graph: &mut OnnxGraph, input: OnnxValue) -> Result<OnnxValue, ExportError>;
+}
+
+impl<T: ToOnnx> ToOnnx for Option<T> {
+    fn to_onnx(&self, graph: &mut OnnxGraph, input: OnnxValue) -> Result<OnnxValue, ExportError> {
+        match self {
+            Some(model) => model.to_onnx(graph, input),
+            None => Ok(input),
+        }
+    }
+}
+
+/// Exports a model with a single input and output to a serialized ONNX model. `input_shape`
+/// excludes the batch axis.
+pub fn export_onnx(model: &impl ToOnnx, input_shape: &[usize]) -> Result<Vec<u8>, ExportError> {
+    let mut graph = OnnxGraph::new();
+    let input = graph.input("input", input_shape);
+    let output = model.to_onnx(&mut graph, input)?;
+    Ok(graph.finish(&[("output", output)]))
+}
+
+/// Shifts `input` by `offset` along its height and width, the same way as the skip connections
+/// of the models: the start is cropped for positive offsets, and both sides are padded with zeros
+/// for negative ones.
+pub(crate) fn shift(graph: &mut OnnxGraph, mut input: OnnxValue, offset: [isize; 2]) -> OnnxValue {
+    let pad = offset.map(|x| usize::try_from(-x).unwrap_or_default());
+    if pad != [0, 0] {
+        let [top, left] = pad.map(|x| x as i64);
+        let pads = graph.ints(&[0, 0, top, left, 0, 0, top, left]);
+        let name = graph.node("Pad", &[&input.name, &pads], &[]);
+        let mut shape = input.shape.clone();
+        shape[1] += 2 * pad[0];
+        shape[2] += 2 * pad[1];
+        input = OnnxValue::new(name, shape);
+    }
+    let crop = offset.map(|x| usize::try_from(x).unwrap_or_default());
+    if crop == [0, 0] {
+        return input;
+    }
+    let starts = graph.ints(&[crop[0] as i64, crop[1] as i64]);
+    let ends = graph.ints(&[input.shape[1] as i64, input.shape[2] as i64]);
+    let axes = graph.ints(&[2, 3]);
+    let name = graph.node("Slice", &[&input.name, &starts, &ends, &axes], &[]);
+    let mut shape = input.shape;
+    shape[1] = shape[1].saturating_sub(crop[0]);
+    shape[2] = shape[2].saturating_sub(crop[1]);
+    OnnxValue::new(name, shape)
+}
+
+/// Adds `skip`, which [`shift`] lined up with `input`, to `input`, cropping it the same way as the
+/// residual connections of the models.
+pub(crate) fn add_skip(
+    graph: &mut OnnxGraph,
+    input: OnnxValue,
+    skip: OnnxValue,
+) -> Result<OnnxValue, ExportError> {
+    if skip.shape == input.shape {
+        return Ok(graph.binary("Add", &input, &skip.name));
+    }
+    let (&[_, height, width], &[_, skip_height, skip_width]) = (&input.shape[..], &skip.shape[..])
+    else {
+        return Err(ExportError::ShapeMismatch {
+            layer: "residual".into(),
+            shape: skip.shape,
+        });
+    };
+    if skip_height < height || skip_width < width {
+        return Err(ExportError::ShapeMismatch {
+            layer: "residual".into(),
+            shape: skip.shape,
+        });
+    }
+    let starts = graph.ints(&[0, 0]);
+    let ends = graph.ints(&[height as i64, width as i64]);
+    let axes = graph.ints(&[2, 3]);
+    let cropped = graph.node("Slice", &[&skip.name, &starts, &ends, &axes], &[]);
+    Ok(graph.binary("Add", &input, &cropped))
+}
+
+impl<B: Backend> ToOnnx for Linear<B> {
+    fn to_onnx(&self, graph: &mut OnnxGraph, input: OnnxValue) -> Result<OnnxValue, ExportError> {
+        let [_, d_output] = self.weight.dims();
+        let weight = graph.tensor(self.weight.val());
+        let name = graph.node("MatMul", &[&input.name, &weight], &[]);
+        let mut shape = input.shape.clone();
+        *shape.last_mut().unwrap() = d_output;
+        let mut output = OnnxValue::new(name, shape);
+        if let Some(bias) = &self.bias {
+            let bias = graph.tensor(bias.val());
+            output = graph.binary("Add", &output, &bias);
+        }
+        Ok(output)
+    }
+}
+
+fn explicit_padding(layer: &str, padding: &PaddingConfig2d) -> Result<[usize; 2], ExportError> {
+    match padding {
+        PaddingConfig2d::Valid => Ok([0, 0]),
+        PaddingConfig2d::Explicit(x, y) => Ok([*x, *y]),
+        PaddingConfig2d::Same => Err(ExportError::Unsupported(format!(
+            "{layer} with same padding"
+        ))),
+    }
+}
+
+fn expect_image(layer: &str, input: &OnnxValue) -> Result<[usize; 3], ExportError> {
+    input
+        .shape
+        .as_slice()
+        .try_into()
+        .map_err(|_| ExportError::ShapeMismatch {
+            layer: layer.into(),
+            shape: input.shape.clone(),
+        })
+}
+
+/// The `pads` attribute of a window padded by the same amount on both sides.
+fn pads(padding: [usize; 2]) -> Attribute {
+    ints(&[padding[0], padding[1], padding[0], padding[1]])
+}
+
+/// The output size of a convolution or pooling window.
+fn window_output_size(
+    size: [usize; 2],
+    kernel_size: [usize; 2],
+    stride: [usize; 2],
+    dilation: [usize; 2],
+    padding: [usize; 2],
+) -> [usize; 2] {
+    [0, 1].map(|i| {
+        (size[i] + 2 * padding[i] - dilation[i] * (kernel_size[i] - 1) - 1) / stride[i] + 1
+    })
+}
+
+impl<B: Backend> ToOnnx for Conv2d<B> {
+    fn to_onnx(&self, graph: &mut OnnxGraph, input: OnnxValue) -> Result<OnnxValue, ExportError> {
+        let [_, height, width] = expect_image("conv", &input)?;
+        let [channels_out, ..] = self.weight.dims();
+        let padding = explicit_padding("conv", &self.padding)?;
+        let weight = graph.tensor(self.weight.val());
+        let mut inputs = vec![input.name.as_str(), &weight];
+        let bias = self.bias.as_ref().map(|bias| graph.tensor(bias.val()));
+        inputs.extend(bias.as_deref());
+        let name = graph.node(
+            "Conv",
+            &inputs,
+            &[
+                ("kernel_shape", ints(&self.kernel_size)),
+                ("strides", ints(&self.stride)),
+                ("dilations", ints(&self.dilation)),
+                ("pads", pads(padding)),
+                ("group", Attribute::Int(self.groups as i64)),
+            ],
+        );
+        let [height, width] = window_output_size(
+            [height, width],
+            self.kernel_size,
+            self.stride,
+            self.dilation,
+            padding,
+        );
+        Ok(OnnxValue::new(name, vec![channels_out, height, width]))
+    }
+}
+
+impl<B: Backend> ToOnnx for ConvTranspose2d<B> {
+    fn to_onnx(&self, graph: &mut OnnxGraph, input: OnnxValue) -> Result<OnnxValue, ExportError> {
+        let [_, height, width] = expect_image("conv_transpose", &input)?;
+        let [_, channels_per_group, ..] = self.weight.dims();
+        let weight = graph.tensor(self.weight.val());
+        let mut inputs = vec![input.name.as_str(), &weight];
+        let bias = self.bias.as_ref().map(|bias| graph.tensor(bias.val()));
+        inputs.extend(bias.as_deref());
+        let padding = self.padding;
+        let name = graph.node(
+            "ConvTranspose",
+            &inputs,
+            &[
+                ("kernel_shape", ints(&self.kernel_size)),
+                ("strides", ints(&self.stride)),
+                ("dilations", ints(&self.dilation)),
+                ("pads", pads(padding)),
+                ("output_padding", ints(&self.padding_out)),
+                ("group", Attribute::Int(self.groups as i64)),
+            ],
+        );
+        let size = [height, width];
+        let [height, width] = [0, 1].map(|i| {
+            (size[i] - 1) * self.stride[i]
+                + self.dilation[i] * (self.kernel_size[i] - 1)
+                + 1
+                + self.padding_out[i]
+                - 2 * padding[i]
+        });
+        Ok(OnnxValue::new(
+            name,
+            vec![channels_per_group * self.groups, height, width],
+        ))
+    }
+}
+
+/// Normalizes over everything but the batch and channel axes, like `InstanceNormalization` with
+/// a unit scale and no bias.
+fn instance_norm(graph: &mut OnnxGraph, input: &OnnxValue, epsilon: f64) -> OnnxValue {
+    let channels = input.shape[0];
+    let scale = graph.floats(&[channels], &vec![1.0; channels]);
+    let bias = graph.floats(&[channels], &vec![0.0; channels]);
+    let name = graph.node(
+        "InstanceNormalization",
+        &[&input.name, &scale, &bias],
+        &[("epsilon", Attribute::Float(epsilon as f32))],
+    );
+    OnnxValue::new(name, input.shape.clone())
+}
+
+impl<B: Backend> ToOnnx for Norm<B> {
+    fn to_onnx(&self, graph: &mut OnnxGraph, input: OnnxValue) -> Result<OnnxValue, ExportError> {
+        Ok(match self {
+            Norm::BatchNorm(norm) => {
+                let inputs = [
+                    norm.gamma.val(),
+                    norm.beta.val(),
+                    norm.running_mean.value(),
+                    norm.running_var.value(),
+                ]
+                .map(|tensor| graph.tensor(tensor));
+                let name = graph.node(
+                    "BatchNormalization",
+                    &[&input.name, &inputs[0], &inputs[1], &inputs[2], &inputs[3]],
+                    &[("epsilon", Attribute::Float(norm.epsilon as f32))],
+                );
+                OnnxValue::new(name, input.shape.clone())
+            }
+            Norm::LayerNorm(norm) => {
+                let [d_model] = norm.gamma.dims();
+                // The bias is optional in some versions of burn, and the epsilon is private, so
+                // both are recovered from the public behavior of the module
+                let beta = norm.forward(Tensor::<B, 2>::zeros([1, d_model], &norm.gamma.device()));
+                let epsilon = norm
+                    .custom_content(Content::new(DisplaySettings::new()))
+                    .and_then(|content| {
+                        content
+                            .attributes
+                            .into_iter()
+                            .find(|attribute| attribute.name == "epsilon")
+                    })
+                    .and_then(|attribute| attribute.value.parse::<f32>().ok())
+                    .ok_or_else(|| {
+                        ExportError::Unsupported("layer norm with an unknown epsilon".into())
+                    })?;
+                let gamma = graph.tensor(norm.gamma.val());
+                let beta = graph.tensor(beta.reshape([d_model]));
+                let name = graph.node(
+                    "LayerNormalization",
+                    &[&input.name, &gamma, &beta],
+                    &[
+                        ("axis", Attribute::Int(-1)),
+                        ("epsilon", Attribute::Float(epsilon)),
+                    ],
+                );
+                OnnxValue::new(name, input.shape.clone())
+            }
+            Norm::RmsNorm(norm) => {
+                // x / sqrt(mean(x^2) + epsilon) * gamma, over the last axis
+                let squared = graph.binary("Mul", &input, &input.name);
+                let mean = graph.unary(
+                    "ReduceMean",
+                    &squared,
+                    &[
+                        ("axes", Attribute::Ints(vec![-1])),
+                        ("keepdims", Attribute::Int(1)),
+                    ],
+                );
+                let epsilon = graph.scalar(norm.epsilon as f32);
+                let variance = graph.binary("Add", &mean, &epsilon);
+                let rms = graph.unary("Sqrt", &variance, &[]);
+                let normalized = graph.binary("Div", &input, &rms.name);
+                let gamma = graph.tensor(norm.gamma.val());
+                graph.binary("Mul", &normalized, &gamma)
+            }
+            Norm::InstanceNorm(norm) => {
+                let channels = norm.num_channels;
+                let scale = match &norm.gamma {
+                    Some(gamma) => graph.tensor(gamma.val()),
+                    None => graph.floats(&[channels], &vec![1.0; channels]),
+                };
+                let bias = match &norm.beta {
+                    Some(beta) => graph.tensor(beta.val()),
+                    None => graph.floats(&[channels], &vec![0.0; channels]),
+                };
+                let name = graph.node(
+                    "InstanceNormalization",
+                    &[&input.name, &scale, &bias],
+                    &[("epsilon", Attribute::Float(norm.epsilon as f32))],
+                );
+                OnnxValue::new(name, input.shape.clone())
+            }
+            Norm::GroupNorm(norm) => {
+                // Each group is normalized as a single instance norm channel
+                let size: usize = input.shape.iter().product();
+                let grouped = graph.reshape(&input, &[norm.num_groups, size / norm.num_groups]);
+                let normalized = instance_norm(graph, &grouped, norm.epsilon);
+                let mut output = graph.reshape(&normalized, &input.shape);
+                if let Some(gamma) = &norm.gamma {
+                    let gamma = graph.channel_tensor(&input, gamma.val());
+                    output = graph.binary("Mul", &output, &gamma);
+                }
+                if let Some(beta) = &norm.beta {
+                    let beta = graph.channel_tensor(&input, beta.val());
+                    output = graph.binary("Add", &output, &beta);
+                }
+                output
+            }
+        })
+    }
+}
+
+impl<B: Backend> ToOnnx for Activation<B> {
+    fn to_onnx(&self, graph: &mut OnnxGraph, input: OnnxValue) -> Result<OnnxValue, ExportError> {
+        Ok(match self {
+            Activation::Relu(_) => graph.unary("Relu", &input, &[]),
+            Activation::Sigmoid(_) => graph.unary("Sigmoid", &input, &[]),
+            Activation::Tanh(_) => graph.unary("Tanh", &input, &[]),
+            Activation::LeakyRelu(activation) => graph.unary(
+                "LeakyRelu",
+                &input,
+                &[("alpha", Attribute::Float(activation.negative_slope as f32))],
+            ),
+            Activation::HardSigmoid(activation) => graph.unary(
+                "HardSigmoid",
+                &input,
+                &[
+                    ("alpha", Attribute::Float(activation.alpha as f32)),
+                    ("beta", Attribute::Float(activation.beta as f32)),
+                ],
+            ),
+            Activation::Gelu(_) => {
+                // The exact GELU, 0.5 * x * (1 + erf(x / sqrt(2))), as `Gelu` needs opset 20
+                let sqrt_2 = graph.scalar(std::f32::consts::SQRT_2);
+                let scaled = graph.binary("Div", &input, &sqrt_2);
+                let erf = graph.unary("Erf", &scaled, &[]);
+                let one = graph.scalar(1.0);
+                let erf = graph.binary("Add", &erf, &one);
+                let output = graph.binary("Mul", &erf, &input.name);
+                let half = graph.scalar(0.5);
+                graph.binary("Mul", &output, &half)
+            }
+            Activation::PRelu(activation) => {
+                let slope = if activation.alpha.dims()[0] == 1 {
+                    graph.tensor(activation.alpha.val())
+                } else {
+                    graph.channel_tensor(&input, activation.alpha.val())
+                };
+                graph.binary("PRelu", &input, &slope)
+            }
+            Activation::SwiGlu(activation) => {
+                // silu(inner(x)) * outer(x)
+                let inner = activation.linear_inner.to_onnx(graph, input.clone())?;
+                let sigmoid = graph.unary("Sigmoid", &inner, &[]);
+                let silu = graph.binary("Mul", &inner, &sigmoid.name);
+                let outer = activation.linear_outer.to_onnx(graph, input)?;
+                graph.binary("Mul", &silu, &outer.name)
+            }
+            activation => {
+                return Err(ExportError::Unsupported(format!(
+                    "the activation {activation:?}"
+                )));
+            }
+        })
+    }
+}
+
+impl ToOnnx for Pool2d {
+    fn to_onnx(&self, graph: &mut OnnxGraph, input: OnnxValue) -> Result<OnnxValue, ExportError> {
+        let [channels, height, width] = expect_image("pool", &input)?;
+        let (op_type, kernel_size, stride, dilation, padding, attribute) = match self {
+            Pool2d::Max(pool) => (
+                "MaxPool",
+                pool.kernel_size,
+                pool.stride,
+                pool.dilation,
+                explicit_padding("max pool", &pool.padding)?,
+                ("dilations", ints(&pool.dilation)),
+            ),
+            Pool2d::Avg(pool) => (
+                "AveragePool",
+                pool.kernel_size,
+                pool.stride,
+                [1, 1],
+                explicit_padding("avg pool", &pool.padding)?,
+                (
+                    "count_include_pad",
+                    Attribute::Int(pool.count_include_pad as i64),
+                ),
+            ),
+        };
+        let name = graph.node(
+            op_type,
+            &[&input.name],
+            &[
+                ("kernel_shape", ints(&kernel_size)),
+                ("strides", ints(&stride)),
+                ("pads", pads(padding)),
+                attribute,
+            ],
+        );
+        let [height, width] =
+            window_output_size([height, width], kernel_size, stride, dilation, padding);
+        Ok(OnnxValue::new(name, vec![channels, height, width]))
+    }
+}
+
+impl ToOnnx for AdaptiveAvgPool2d {
+    fn to_onnx(&self, graph: &mut OnnxGraph, input: OnnxValue) -> Result<OnnxValue, ExportError> {
+        let [channels, height, width] = expect_image("adaptive_avg_pool", &input)?;
+        let [output_height, output_width] = self.output_size;
+        if height % output_height == 0 && width % output_width == 0 {
+            let window = [height / output_height, width / output_width];
+            let name = graph.node(
+                "AveragePool",
+                &[&input.name],
+                &[("kernel_shape", ints(&window)), ("strides", ints(&window))],
+            );
+            return Ok(OnnxValue::new(
+                name,
+                vec![channels, output_height, output_width],
+            ));
+        }
+
+        // The bins overlap, so each one is averaged separately, one axis at a time
+        let mut output = input;
+        for (axis, output_size) in [(2, output_height), (3, output_width)] {
+            let input_size = output.shape[axis - 1];
+            let bins: Vec<_> = (0..output_size)
+                .map(|i| {
+                    let start = i * input_size / output_size;
+                    let end = ((i + 1) * input_size).div_ceil(output_size);
+                    let starts = graph.ints(&[start as i64]);
+                    let ends = graph.ints(&[end as i64]);
+                    let axes = graph.ints(&[axis as i64]);
+                    let bin = graph.node("Slice", &[&output.name, &starts, &ends, &axes], &[]);
+                    graph.node(
+                        "ReduceMean",
+                        &[&bin],
+                        &[
+                            ("axes", Attribute::Ints(vec![axis as i64])),
+                            ("keepdims", Attribute::Int(1)),
+                        ],
+                    )
+                })
+                .collect();
+            let bins: Vec<_> = bins.iter().map(String::as_str).collect();
+            let name = graph.node("Concat", &bins, &[("axis", Attribute::Int(axis as i64))]);
+            let mut shape = output.shape.clone();
+            shape[axis - 1] = output_size;
+            output = OnnxValue::new(name, shape);
+        }
+        Ok(output)
+    }
+}
+
+impl ToOnnx for Interpolate2d {
+    fn to_onnx(&self, graph: &mut OnnxGraph, input: OnnxValue) -> Result<OnnxValue, ExportError> {
+        let [channels, height, width] = expect_image("interpolate", &input)?;
+        let [height, width] = match (self.output_size, self.scale_factor) {
+            (Some(output_size), _) => output_size,
+            (None, Some([scale_height, scale_width])) => [
+                (height as f64 * scale_height as f64) as usize,
+                (width as f64 * scale_width as f64) as usize,
+            ],
+            (None, None) => [height, width],
+        };
+        // Matches the sampling of burn, which aligns the corners except with nearest neighbors
+        let attributes = match &self.mode.0 {
+            InterpolateMode::Nearest => vec![
+                ("mode", Attribute::String("nearest")),
+                (
+                    "coordinate_transformation_mode",
+                    Attribute::String("asymmetric"),
+                ),
+                ("nearest_mode", Attribute::String("floor")),
+            ],
+            InterpolateMode::Linear => vec![
+                ("mode", Attribute::String("linear")),
+                (
+                    "coordinate_transformation_mode",
+                    Attribute::String("align_corners"),
+                ),
+            ],
+            InterpolateMode::Cubic => vec![
+                ("mode", Attribute::String("cubic")),
+                (
+                    "coordinate_transformation_mode",
+                    Attribute::String("align_corners"),
+                ),
+            ],
+        };
+        // The sizes include the dynamic batch axis, so they are taken from the input
+        let input_shape = graph.node("Shape", &[&input.name], &[]);
+        let starts = graph.ints(&[0]);
+        let ends = graph.ints(&[2]);
+        let batch_channels = graph.node("Slice", &[&input_shape, &starts, &ends], &[]);
+        let size = graph.ints(&[height as i64, width as i64]);
+        let sizes = graph.node(
+            "Concat",
+            &[&batch_channels, &size],
+            &[("axis", Attribute::Int(0))],
+        );
+        let name = graph.node("Resize", &[&input.name, "", "", &sizes], &attributes);
+        Ok(OnnxValue::new(name, vec![channels, height, width]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    //! Checks exported models by decoding them and evaluating them with a small reference
+    //! implementation of the ONNX ops they use, since there is no ONNX runtime among the
+    //! dependencies.
+
+    use std::collections::HashMap;
+
+    use burn::prelude::*;
+
+    use super::*;
+    use crate::{
+        Init, SimpleInfer,
+        composite::image::Conv2dLinearModelConfig,
+        cpu::{NdArrayBackend, get_device},
+        shape::InferShape,
+    };
+
+    /// A decoded protobuf field.
+    #[derive(Debug, Clone, Copy)]
+    enum Field<'a> {
+        Varint(u64),
+        /// A float, such as an epsilon, which the ops of the test don't use.
+        Fixed32,
+        Bytes(&'a [u8]),
+    }
+
+    fn read_varint(bytes: &mut &[u8]) -> u64 {
+        let mut value = 0;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = bytes.split_first().expect("Expected a complete varint");
+            *bytes = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte < 0x80 {
+                return value;
+            }
+        }
+        panic!("Expected a varint of at most 64 bits");
+    }
+
+    fn decode(mut bytes: &[u8]) -> Vec<(u64, Field<'_>)> {
+        let mut fields = vec![];
+        while !bytes.is_empty() {
+            let tag = read_varint(&mut bytes);
+            let field = match tag & 7 {
+                0 => Field::Varint(read_varint(&mut bytes)),
+                2 => {
+                    let len = read_varint(&mut bytes) as usize;
+                    let (value, rest) = bytes.split_at(len);
+                    bytes = rest;
+                    Field::Bytes(value)
+                }
+                5 => {
+                    bytes = &bytes[4..];
+                    Field::Fixed32
+                }
+                wire_type => panic!("Expected no wire type {wire_type}"),
+            };
+            fields.push((tag >> 3, field));
+        }
+        fields
+    }
+
+    fn messages<'a>(fields: &[(u64, Field<'a>)], number: u64) -> Vec<&'a [u8]> {
+        fields
+            .iter()
+            .filter(|(n, _)| *n == number)
+            .map(|(_, field)| match field {
+                Field::Bytes(bytes) => *bytes,
+                field => panic!("Expected field {number} to be bytes, not {field:?}"),
+            })
+            .collect()
+    }
+
+    fn strings(fields: &[(u64, Field)], number: u64) -> Vec<String> {
+        messages(fields, number)
+            .into_iter()
+            .map(|bytes| String::from_utf8(bytes.to_vec()).unwrap())
+            .collect()
+    }
+
+    fn varints(fields: &[(u64, Field)], number: u64) -> Vec<i64> {
+        fields
+            .iter()
+            .filter_map(|(n, field)| match field {
+                Field::Varint(value) if *n == number => Some(*value as i64),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// A tensor with its batch axis, as evaluated by [`run`].
+    #[derive(Debug, Clone)]
+    struct Value {
+        shape: Vec<usize>,
+        data: Vec<f32>,
+    }
+
+    impl Value {
+        fn strides(&self) -> Vec<usize> {
+            let mut strides = vec![1; self.shape.len()];
+            for i in (0..self.shape.len().saturating_sub(1)).rev() {
+                strides[i] = strides[i + 1] * self.shape[i + 1];
+            }
+            strides
+        }
+
+        fn ints(&self) -> Vec<i64> {
+            self.data.iter().map(|&x| x as i64).collect()
+        }
+    }
+
+    fn initializer(bytes: &[u8]) -> (String, Value) {
+        let fields = decode(bytes);
+        let shape: Vec<_> = varints(&fields, 1).iter().map(|&x| x as usize).collect();
+        let raw_data = messages(&fields, 9)[0];
+        let data: Vec<_> = match varints(&fields, 2)[..] {
+            [FLOAT] => raw_data
+                .chunks_exact(4)
+                .map(|x| f32::from_le_bytes(x.try_into().unwrap()))
+                .collect(),
+            [INT64] => raw_data
+                .chunks_exact(8)
+                .map(|x| i64::from_le_bytes(x.try_into().unwrap()) as f32)
+                .collect(),
+            ref data_type => panic!("Expected no data type {data_type:?}"),
+        };
+        assert_eq!(data.len(), shape.iter().product::<usize>());
+        (strings(&fields, 8).remove(0), Value { shape, data })
+    }
+
+    /// The dims of a `ValueInfoProto`, with `None` for the symbolic ones.
+    fn value_info_shape(bytes: &[u8]) -> (String, Vec<Option<usize>>) {
+        let fields = decode(bytes);
+        let tensor_type = decode(messages(&decode(messages(&fields, 2)[0]), 1)[0]);
+        assert_eq!(varints(&tensor_type, 1), [FLOAT]);
+        let shape = messages(&decode(messages(&tensor_type, 2)[0]), 1)
+            .into_iter()
+            .map(|dim| varints(&decode(dim), 1).first().map(|&x| x as usize))
+            .collect();
+        (strings(&fields, 1).remove(0), shape)
+    }
+
+    fn attributes(fields: &[(u64, Field)]) -> HashMap<String, Vec<i64>> {
+        messages(fields, 5)
+            .into_iter()
+            .map(|bytes| {
+                let fields = decode(bytes);
+                let values = match varints(&fields, 20)[..] {
+                    [2] => varints(&fields, 3),
+                    [7] => varints(&fields, 8),
+                    ref attribute_type => panic!("Expected no attribute type {attribute_type:?}"),
+                };
+                (strings(&fields, 1).remove(0), values)
+            })
+            .collect()
+    }
+
+    fn conv(
+        input: &Value,
+        weight: &Value,
+        bias: Option<&Value>,
+        attributes: &HashMap<String, Vec<i64>>,
+    ) -> Value {
+        let attribute =
+            |name: &str| -> Vec<usize> { attributes[name].iter().map(|&x| x as usize).collect() };
+        let (stride, dilation, pads) = (
+            attribute("strides"),
+            attribute("dilations"),
+            attribute("pads"),
+        );
+        assert_eq!(attribute("group"), [1]);
+        assert_eq!(attribute("kernel_shape"), weight.shape[2..]);
+        let &[batch_size, channels_in, height, width] = &input.shape[..] else {
+            panic!("Expected an image");
+        };
+        let &[channels_out, _, kernel_height, kernel_width] = &weight.shape[..] else {
+            panic!("Expected a 2D kernel");
+        };
+        let output_size = |size: usize, kernel: usize, i: usize| {
+            (size + pads[i] + pads[i + 2] - dilation[i] * (kernel - 1) - 1) / stride[i] + 1
+        };
+        let shape = vec![
+            batch_size,
+            channels_out,
+            output_size(height, kernel_height, 0),
+            output_size(width, kernel_width, 1),
+        ];
+        let mut data = vec![];
+        for b in 0..batch_size {
+            for o in 0..channels_out {
+                for y in 0..shape[2] {
+                    for x in 0..shape[3] {
+                        let mut sum = bias.map_or(0.0, |bias| bias.data[o]);
+                        for c in 0..channels_in {
+                            for ky in 0..kernel_height {
+                                for kx in 0..kernel_width {
+                                    let iy =
+                                        (y * stride[0] + ky * dilation[0]).checked_sub(pads[0]);
+                                    let ix =
+                                        (x * stride[1] + kx * dilation[1]).checked_sub(pads[1]);
+                                    let (Some(iy), Some(ix)) = (iy, ix) else {
+                                        continue;
+                                    };
+                                    if iy >= height || ix >= width {
+                                        continue;
+                                    }
+                                    sum += input.data
+                                        [((b * channels_in + c) * height + iy) * width + ix]
+                                        * weight.data[((o * channels_in + c) * kernel_height + ky)
+                                            * kernel_width
+                                            + kx];
+                                }
+                            }
+                        }
+                        data.push(sum);
+                    }
+                }
+            }
+        }
+        Value { shape, data }
+    }
+
+    /// Adds `b`, which must broadcast to the shape of `a`.
+    fn add(a: &Value, b: &Value) -> Value {
+        let offset = a.shape.len() - b.shape.len();
+        let (a_strides, b_strides) = (a.strides(), b.strides());
+        let data = (0..a.data.len())
+            .map(|i| {
+                let b_index: usize = (0..b.shape.len())
+                    .map(|axis| {
+                        assert!(b.shape[axis] == 1 || b.shape[axis] == a.shape[axis + offset]);
+                        let index = i / a_strides[axis + offset] % a.shape[axis + offset];
+                        index % b.shape[axis] * b_strides[axis]
+                    })
+                    .sum();
+                a.data[i] + b.data[b_index]
+            })
+            .collect();
+        Value {
+            shape: a.shape.clone(),
+            data,
+        }
+    }
+
+    fn matmul(a: &Value, b: &Value) -> Value {
+        let (&[rows, inner], &[inner_b, columns]) = (&a.shape[..], &b.shape[..]) else {
+            panic!("Expected 2D matrices");
+        };
+        assert_eq!(inner, inner_b);
+        let data = (0..rows * columns)
+            .map(|i| {
+                let (row, column) = (i / columns, i % columns);
+                (0..inner)
+                    .map(|k| a.data[row * inner + k] * b.data[k * columns + column])
+                    .sum()
+            })
+            .collect();
+        Value {
+            shape: vec![rows, columns],
+            data,
+        }
+    }
+
+    /// Copies the elements of `input` whose index along each axis is in `ranges`, where the ranges
+    /// may extend past `input` to pad it with zeros.
+    fn window(input: &Value, ranges: &[(isize, isize)]) -> Value {
+        let shape: Vec<_> = ranges
+            .iter()
+            .map(|(start, end)| (end - start) as usize)
+            .collect();
+        let strides = input.strides();
+        let output_len: usize = shape.iter().product();
+        let data = (0..output_len)
+            .map(|i| {
+                let mut rest = i;
+                let mut index = 0;
+                for axis in (0..shape.len()).rev() {
+                    let position = (rest % shape[axis]) as isize + ranges[axis].0;
+                    rest /= shape[axis];
+                    if position < 0 || position >= input.shape[axis] as isize {
+                        return 0.0;
+                    }
+                    index += position as usize * strides[axis];
+                }
+                input.data[index]
+            })
+            .collect();
+        Value { shape, data }
+    }
+
+    /// Evaluates the exported `model` on `input`, checking that every node only uses values that
+    /// were defined before it, and that the output has the declared shape.
+    fn run(model: &[u8], input: Value) -> Value {
+        let model = decode(model);
+        assert_eq!(varints(&model, 1), [IR_VERSION]);
+        let opset = decode(messages(&model, 8)[0]);
+        assert_eq!(varints(&opset, 2), [OPSET_VERSION]);
+        let graph = decode(messages(&model, 7)[0]);
+
+        let mut values: HashMap<_, _> = messages(&graph, 5).into_iter().map(initializer).collect();
+        let [graph_input] = messages(&graph, 11)[..] else {
+            panic!("Expected a single input");
+        };
+        let (name, shape) = value_info_shape(graph_input);
+        assert_eq!(shape[0], None);
+        assert!(
+            shape[1..]
+                .iter()
+                .copied()
+                .eq(input.shape[1..].iter().copied().map(Some))
+        );
+        values.insert(name, input);
+
+        for node in messages(&graph, 1) {
+            let node = decode(node);
+            let inputs: Vec<_> = strings(&node, 1)
+                .iter()
+                .map(|name| {
+                    values
+                        .get(name)
+                        .unwrap_or_else(|| panic!("Expected {name} to be defined before its use"))
+                })
+                .collect();
+            let attributes = attributes(&node);
+            let op_type = strings(&node, 4).remove(0);
+            let output = match (op_type.as_str(), &inputs[..]) {
+                ("Conv", [input, weight, bias @ ..]) => {
+                    conv(input, weight, bias.first().copied(), &attributes)
+                }
+                ("Relu", [input]) => Value {
+                    shape: input.shape.clone(),
+                    data: input.data.iter().map(|x| x.max(0.0)).collect(),
+                },
+                ("Add", [a, b]) => add(a, b),
+                ("MatMul", [a, b]) => matmul(a, b),
+                ("Flatten", [input]) => {
+                    assert_eq!(attributes["axis"], [1]);
+                    Value {
+                        shape: vec![input.shape[0], input.shape[1..].iter().product()],
+                        data: input.data.clone(),
+                    }
+                }
+                ("Slice", [input, starts, ends, axes]) => {
+                    let mut ranges: Vec<_> = input.shape.iter().map(|&x| (0, x as isize)).collect();
+                    for ((start, end), axis) in
+                        starts.ints().into_iter().zip(ends.ints()).zip(axes.ints())
+                    {
+                        let size = input.shape[axis as usize] as isize;
+                        ranges[axis as usize] = (start as isize, (end as isize).min(size));
+                    }
+                    window(input, &ranges)
+                }
+                ("Pad", [input, pads]) => {
+                    let pads = pads.ints();
+                    let rank = input.shape.len();
+                    let ranges: Vec<_> = (0..rank)
+                        .map(|axis| {
+                            let start = -pads[axis] as isize;
+                            (
+                                start,
+                                input.shape[axis] as isize + pads[axis + rank] as isize,
+                            )
+                        })
+                        .collect();
+                    window(input, &ranges)
+                }
+                ("Identity", [input]) => (*input).clone(),
+                (op_type, inputs) => {
+                    panic!("Expected no {op_type} with {} inputs", inputs.len())
+                }
+            };
+            let [name] = &strings(&node, 2)[..] else {
+                panic!("Expected a single output");
+            };
+            assert!(values.insert(name.clone(), output).is_none());
+        }
+
+        let [graph_output] = messages(&graph, 12)[..] else {
+            panic!("Expected a single output");
+        };
+        let (name, shape) = value_info_shape(graph_output);
+        let output = values
+            .remove(&name)
+            .expect("Expected the output to be defined");
+        assert_eq!(shape[0], None);
+        assert!(
+            shape[1..]
+                .iter()
+                .copied()
+                .eq(output.shape[1..].iter().copied().map(Some))
+        );
+        output
+    }
+
+    #[test]
+    fn conv2d_linear_model_matches_burn() {
+        let config: Conv2dLinearModelConfig = serde_json::from_value(serde_json::json!({
+            "conv": {
+                "input_channels": 2,
+                "default_activation": "relu",
+                "default_norm": null,
+                "layers": [
+                    { "output_channels": 4, "kernel_size": [3, 3], "padding": [1, 1] },
+                    {
+                        "output_channels": 3,
+                        "kernel_size": [3, 3],
+                        "stride": [2, 2],
+                        "residual": true
+                    }
+                ]
+            },
+            "adaptive_avg_pooling": null,
+            "linear": {
+                "input_size": 27,
+                "default_activation": "relu",
+                "default_norm": null,
+                "layers": [5, [3, "none", "none"]]
+            }
+        }))
+        .unwrap();
+        let input_shape = [2, 7, 7];
+        config.infer_shapes(&input_shape).unwrap();
+        let device = get_device();
+        let model = Init::<NdArrayBackend, _>::init(config, device);
+
+        let input = Tensor::<NdArrayBackend, 4>::random(
+            [3, 2, 7, 7],
+            burn::tensor::Distribution::Normal(0.0, 1.0),
+            device,
+        );
+        let expected = model.infer(input.clone());
+        let onnx = export_onnx(&model, &input_shape).unwrap();
+        let actual = run(
+            &onnx,
+            Value {
+                shape: input.dims().to_vec(),
+                data: input.into_data().into_vec().unwrap(),
+            },
+        );
+
+        assert_eq!(actual.shape, expected.dims());
+        let expected = expected.into_data().into_vec::<f32>().unwrap();
+        for (actual, expected) in actual.data.iter().zip(&expected) {
+            assert!(
+                (actual - expected).abs() <= 1e-4 * expected.abs().max(1.0),
+                "{actual} != {expected}"
+            );
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! Post-training int8 quantization of inference models.
+//!
+//! The weights of linear and convolutional layers are quantized symmetrically per output
+//! feature, and their inputs per tensor, with ranges calibrated by running the f32 model on
+//! sample inputs. Everything in between, like norms, activations and skip connections, stays in
+//! f32.
+//!
+//! The quantized weights and inputs are int8 tensors of the backend, built with
+//! [`Tensor::quantize`], so that matrix products run on its int8 kernels where it has them. The
+//! f32 model is only run to calibrate, by [`Quantize::observe`].
+
+use burn::{
+    module::{Ignored, Param},
+    nn::{Linear, conv::Conv2d},
+    prelude::*,
+    tensor::{
+        ops::QuantizedTensor,
+        quantization::{
+            QTensorPrimitive, QuantLevel, QuantParam, QuantScheme, QuantValue,
+            QuantizationParameters,
+        },
+    },
+};
+
+use crate::SimpleInfer;
+
+/// The largest magnitude of a symmetric int8 value. -128 is left out so that the range is
+/// symmetric.
+const QUANTIZED_MAX: f32 = 127.0;
+
+/// The largest absolute values seen at the inputs of the quantized layers of a model, in the
+/// order the layers run.
+#[derive(Debug, Clone, Default)]
+pub struct ActivationRanges {
+    max_abs: Vec<f32>,
+    next: usize,
+}
+
+impl ActivationRanges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Goes back to the first layer, before observing another batch or quantizing.
+    pub fn rewind(&mut self) {
+        self.next = 0;
+    }
+
+    /// Widens the range of the input of the next layer to cover `tensor`.
+    pub fn observe<B: Backend, const D: usize>(&mut self, tensor: &Tensor<B, D>) {
+        let max_abs = tensor.clone().abs().max().into_scalar().elem::<f32>();
+        match self.max_abs.get_mut(self.next) {
+            Some(range) => *range = range.max(max_abs),
+            None => self.max_abs.push(max_abs),
+        }
+        self.next += 1;
+    }
+
+    /// The scale that maps the range of the input of the next layer to int8.
+    pub fn next_scale(&mut self) -> f32 {
+        let max_abs = *self
+            .max_abs
+            .get(self.next)
+            .expect("Expected every quantized layer to be observed");
+        self.next += 1;
+        max_abs.max(f32::EPSILON) / QUANTIZED_MAX
+    }
+}
+
+/// A model that can be quantized after training.
+pub trait Quantize<B: Backend, const N_I: usize, const N_O: usize>:
+    SimpleInfer<B, N_I, N_O>
+{
+    type Quantized: SimpleInfer<B, N_I, N_O>;
+
+    /// Runs the model like [`SimpleInfer::infer`], recording the range of the input of every layer
+    /// that gets quantized.
+    fn observe(&self, tensor: Tensor<B, N_I>, ranges: &mut ActivationRanges) -> Tensor<B, N_O>;
+
+    /// Quantizes the weights of the model, and its activations with the ranges of `ranges`.
+    fn quantize(&self, ranges: &mut ActivationRanges) -> Self::Quantized;
+}
+
+/// Calibrates the activation ranges of `model` on `batches`, then quantizes it.
+pub fn quantize<B, M, const N_I: usize, const N_O: usize>(
+    model: &M,
+    batches: impl IntoIterator<Item = Tensor<B, N_I>>,
+) -> M::Quantized
+where
+    B: Backend,
+    M: Quantize<B, N_I, N_O>,
+{
+    let mut ranges = ActivationRanges::new();
+    for batch in batches {
+        ranges.rewind();
+        model.observe(batch, &mut ranges);
+    }
+    ranges.rewind();
+    model.quantize(&mut ranges)
+}
+
+/// Symmetric int8 with a single f32 scale per tensor, stored the way the backend prefers.
+fn int8_scheme<B: Backend>() -> QuantScheme {
+    <QuantizedTensor<B> as QTensorPrimitive>::default_scheme()
+        .with_value(QuantValue::Q8S)
+        .with_level(QuantLevel::Tensor)
+        .with_param(QuantParam::F32)
+}
+
+/// Quantizes `tensor` to int8 values that are multiplied by `scale`.
+fn quantize_int8<B: Backend, const D: usize>(tensor: Tensor<B, D>, scale: f32) -> Tensor<B, D> {
+    let scales = Tensor::from_floats([scale], &tensor.device());
+    tensor.quantize(&int8_scheme::<B>(), QuantizationParameters { scales })
+}
+
+/// A [`Linear`] layer with int8 weights and inputs.
+#[derive(Module, Debug)]
+pub struct QuantizedLinear<B: Backend> {
+    /// Holds the int8 weights, without the bias.
+    linear: Linear<B>,
+    input_scale: Ignored<f32>,
+    /// The scale of the weights of each output feature, which the output is multiplied by.
+    weight_scale: Param<Tensor<B, 1>>,
+    bias: Option<Param<Tensor<B, 1>>>,
+}
+
+impl<B: Backend> QuantizedLinear<B> {
+    pub fn new(linear: &Linear<B>, input_scale: f32) -> Self {
+        let weight = linear.weight.val();
+        // [1, d_output]
+        let weight_scale = weight
+            .clone()
+            .abs()
+            .max_dim(0)
+            .clamp_min(f32::EPSILON)
+            .div_scalar(QUANTIZED_MAX);
+        let mut quantized = linear.clone();
+        quantized.weight = Param::from_tensor(quantize_int8(weight / weight_scale.clone(), 1.0));
+        quantized.bias = None;
+        Self {
+            linear: quantized,
+            input_scale: Ignored(input_scale),
+            weight_scale: Param::from_tensor(weight_scale.flatten(0, 1)),
+            bias: linear.bias.clone(),
+        }
+    }
+
+    pub fn forward(&self, tensor: Tensor<B, 2>) -> Tensor<B, 2> {
+        let input = quantize_int8(tensor, *self.input_scale);
+        let output = self.linear.forward(input).dequantize() * self.weight_scale.val().unsqueeze();
+        match &self.bias {
+            Some(bias) => output + bias.val().unsqueeze(),
+            None => output,
+        }
+    }
+}
+
+/// A [`Conv2d`] layer with int8 weights and inputs.
+#[derive(Module, Debug)]
+pub struct QuantizedConv2d<B: Backend> {
+    /// Holds the int8 weights, without the bias.
+    conv: Conv2d<B>,
+    input_scale: Ignored<f32>,
+    /// The scale of the weights of each output channel, which the output is multiplied by.
+    weight_scale: Param<Tensor<B, 1>>,
+    bias: Option<Param<Tensor<B, 1>>>,
+}
+
+impl<B: Backend> QuantizedConv2d<B> {
+    pub fn new(conv: &Conv2d<B>, input_scale: f32) -> Self {
+        let weight = conv.weight.val();
+        let [channels, ..] = weight.dims();
+        // [channels, 1, 1, 1]
+        let weight_scale = weight
+            .clone()
+            .abs()
+            .flatten::<2>(1, 3)
+            .max_dim(1)
+            .clamp_min(f32::EPSILON)
+            .div_scalar(QUANTIZED_MAX)
+            .reshape([channels, 1, 1, 1]);
+        let mut quantized = conv.clone();
+        quantized.weight = Param::from_tensor(quantize_int8(weight / weight_scale.clone(), 1.0));
+        quantized.bias = None;
+        Self {
+            conv: quantized,
+            input_scale: Ignored(input_scale),
+            weight_scale: Param::from_tensor(weight_scale.flatten(0, 3)),
+            bias: conv.bias.clone(),
+        }
+    }
+
+    /// The convolution with the int8 weights, which has the geometry of the original.
+    pub fn conv(&self) -> &Conv2d<B> {
+        &self.conv
+    }
+
+    pub fn forward(&self, tensor: Tensor<B, 4>) -> Tensor<B, 4> {
+        let input = quantize_int8(tensor, *self.input_scale);
+        let channels = |x: Tensor<B, 1>| x.reshape([1, -1, 1, 1]);
+        let output = self.conv.forward(input).dequantize() * channels(self.weight_scale.val());
+        match &self.bias {
+            Some(bias) => output + channels(bias.val()),
+            None => output,
+        }
+    }
+}
@@ -1 +1,76 @@
-pub mod grus;
\ No newline at end of file
+use burn::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    SimpleInfer,
+    common::{InitializerConfig, Norm},
+};
+
+pub mod grus;
+pub mod lstms;
+
+/// A layer of a recurrent model, written either as just the hidden size, or as an object.
+#[derive(Serialize, Debug, Deserialize, Clone, Copy)]
+#[serde(untagged)]
+pub enum RecurrentLayerConfig {
+    HiddenSize(usize),
+    Layer {
+        hidden_size: usize,
+        /// Overrides the initializer of the weights of the layer.
+        initializer: Option<InitializerConfig>,
+    },
+}
+
+impl RecurrentLayerConfig {
+    pub fn hidden_size(&self) -> usize {
+        match *self {
+            RecurrentLayerConfig::HiddenSize(hidden_size) => hidden_size,
+            RecurrentLayerConfig::Layer { hidden_size, .. } => hidden_size,
+        }
+    }
+
+    pub fn initializer(&self) -> Option<InitializerConfig> {
+        match *self {
+            RecurrentLayerConfig::HiddenSize(_) => None,
+            RecurrentLayerConfig::Layer { initializer, .. } => initializer,
+        }
+    }
+}
+
+impl From<usize> for RecurrentLayerConfig {
+    fn from(hidden_size: usize) -> Self {
+        RecurrentLayerConfig::HiddenSize(hidden_size)
+    }
+}
+
+/// Applies `norm` to a `[batch_size, seq_len, features]` tensor.
+///
+/// Layer and RMS norms normalize the last axis, while the other norms expect the features on the
+/// second axis, so the sequence is transposed around them.
+pub(crate) fn norm_sequence<B: Backend>(norm: &Norm<B>, tensor: Tensor<B, 3>) -> Tensor<B, 3> {
+    match norm {
+        Norm::LayerNorm(_) | Norm::RmsNorm(_) => norm.forward(tensor),
+        _ => norm.forward(tensor.swap_dims(1, 2)).swap_dims(1, 2),
+    }
+}
+
+/// Picks the final hidden state out of a `[batch_size, seq_len, hidden_size]` output sequence.
+///
+/// For bidirectional layers the second half of the features comes from the reversed pass, whose
+/// final hidden state sits at the first step of the sequence.
+pub(crate) fn last_hidden_state<B: Backend>(
+    tensor: Tensor<B, 3>,
+    bidirectional: bool,
+) -> Tensor<B, 2> {
+    let [batch_size, _seq_len, hidden_size] = tensor.dims();
+    if bidirectional {
+        let half = hidden_size / 2;
+        let forward = tensor.clone().slice(s![.., -1, 0..half]);
+        let reverse = tensor.slice(s![.., 0, half..hidden_size]);
+        Tensor::cat(vec![forward, reverse], 2).reshape([batch_size, hidden_size])
+    } else {
+        tensor
+            .slice(s![.., -1, ..])
+            .reshape([batch_size, hidden_size])
+    }
+}
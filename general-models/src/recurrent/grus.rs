@@ -1,6 +1,10 @@
 use burn::{
     module::Module,
-    nn::{Dropout, DropoutConfig, activation::Activation, gru::{Gru, GruConfig}},
+    nn::{
+        Dropout, DropoutConfig,
+        activation::Activation,
+        gru::{Gru, GruConfig},
+    },
     prelude::*,
 };
 use serde::{Deserialize, Serialize};
@@ -8,22 +12,51 @@ use utils::default_f;
 
 use crate::{
     Init, SimpleInfer, SimpleTrain,
-    common::{ActivationConfig, Either, Norm, NormConfig, Optional, handle_norm_activation},
+    common::{
+        ActivationConfig, Either, InitializerConfig, Norm, NormConfig, Optional,
+        handle_norm_activation, orthogonal_biases,
+    },
+    error::{ShapeError, ShapeErrorKind},
+    recurrent::{RecurrentLayerConfig, last_hidden_state, norm_sequence},
+    shape::{InferShape, LayerShape, apply_activation, expect_rank, expect_size, norm_params},
 };
 
+/// A stack of GRU layers.
+///
+/// The model outputs the whole `[batch_size, seq_len, hidden_size]` sequence when used as a
+/// 3 to 3 dimensional model, and only the last hidden state when used as a 3 to 2 dimensional model.
 #[derive(Debug, Module)]
 pub struct GruModel<B: Backend> {
-    layers: Vec<(Gru<B>, Option<Norm<B>>, Option<Activation<B>>)>,
+    layers: Vec<(
+        Gru<B>,
+        Option<Gru<B>>,
+        Option<Norm<B>>,
+        Option<Activation<B>>,
+    )>,
     dropout: Dropout,
     dropout_last: bool,
 }
 
-impl<B: Backend> SimpleTrain<B, 3, 2> for GruModel<B> {
-    fn forward(&self, mut tensor: burn::Tensor<B, 3>) -> burn::Tensor<B, 2> {
-        for (i, (gru, norm, activation)) in self.layers.iter().enumerate() {
-            tensor = gru.forward(tensor, None);
+fn forward_gru<B: Backend>(
+    gru: &Gru<B>,
+    reverse: &Option<Gru<B>>,
+    tensor: Tensor<B, 3>,
+) -> Tensor<B, 3> {
+    match reverse {
+        Some(reverse) => {
+            let reversed = reverse.forward(tensor.clone().flip([1]), None).flip([1]);
+            Tensor::cat(vec![gru.forward(tensor, None), reversed], 2)
+        }
+        None => gru.forward(tensor, None),
+    }
+}
+
+impl<B: Backend> SimpleTrain<B, 3, 3> for GruModel<B> {
+    fn forward(&self, mut tensor: burn::Tensor<B, 3>) -> burn::Tensor<B, 3> {
+        for (i, (gru, reverse, norm, activation)) in self.layers.iter().enumerate() {
+            tensor = forward_gru(gru, reverse, tensor);
             if let Some(norm) = norm {
-                tensor = norm.forward(tensor);
+                tensor = norm_sequence(norm, tensor);
             }
             if let Some(activation) = activation {
                 tensor = activation.forward(tensor);
@@ -32,47 +65,89 @@ impl<B: Backend> SimpleTrain<B, 3, 2> for GruModel<B> {
                 tensor = self.dropout.forward(tensor);
             }
         }
-        let [batch_size, _seq_len, hidden_size] = tensor.dims();
-        tensor.slice(s![.., -1, ..]).reshape([batch_size, hidden_size])
+        tensor
     }
 }
 
-impl<B: Backend> SimpleInfer<B, 3, 2> for GruModel<B> {
-    fn forward(&self, mut tensor: burn::Tensor<B, 3>) -> burn::Tensor<B, 2> {
-        for (gru, norm, activation) in self.layers.iter() {
-            tensor = gru.forward(tensor, None);
+impl<B: Backend> SimpleTrain<B, 3, 2> for GruModel<B> {
+    fn forward(&self, tensor: burn::Tensor<B, 3>) -> burn::Tensor<B, 2> {
+        let tensor: Tensor<B, 3> = self.train(tensor);
+        last_hidden_state(tensor, self.is_bidirectional())
+    }
+}
+
+impl<B: Backend> SimpleInfer<B, 3, 3> for GruModel<B> {
+    fn forward(&self, mut tensor: burn::Tensor<B, 3>) -> burn::Tensor<B, 3> {
+        for (gru, reverse, norm, activation) in self.layers.iter() {
+            tensor = forward_gru(gru, reverse, tensor);
             if let Some(norm) = norm {
-                tensor = norm.forward(tensor);
+                tensor = norm_sequence(norm, tensor);
             }
             if let Some(activation) = activation {
                 tensor = activation.forward(tensor);
             }
         }
-        let [batch_size, _seq_len, hidden_size] = tensor.dims();
-        tensor.slice(s![.., -1, ..]).reshape([batch_size, hidden_size])
+        tensor
     }
 }
+
+impl<B: Backend> SimpleInfer<B, 3, 2> for GruModel<B> {
+    fn forward(&self, tensor: burn::Tensor<B, 3>) -> burn::Tensor<B, 2> {
+        let tensor: Tensor<B, 3> = self.infer(tensor);
+        last_hidden_state(tensor, self.is_bidirectional())
+    }
+}
+
 impl<B: Backend> GruModel<B> {
     pub fn iter_layers(
         &mut self,
         mut map: impl FnMut(
             Gru<B>,
+            Option<Gru<B>>,
             Option<Norm<B>>,
             Option<Activation<B>>,
-        ) -> (Gru<B>, Option<Norm<B>>, Option<Activation<B>>),
+        ) -> (
+            Gru<B>,
+            Option<Gru<B>>,
+            Option<Norm<B>>,
+            Option<Activation<B>>,
+        ),
     ) {
         self.layers = std::mem::take(&mut self.layers)
             .into_iter()
-            .map(|(gru, norm, activation)| map(gru, norm, activation))
+            .map(|(gru, reverse, norm, activation)| map(gru, reverse, norm, activation))
             .collect();
     }
 
+    pub fn is_bidirectional(&self) -> bool {
+        self.layers
+            .last()
+            .expect("Expected a GRU model checked by infer_shapes to have at least one layer")
+            .1
+            .is_some()
+    }
+
     pub fn get_input_size(&self) -> usize {
-        self.layers[0].0.new_gate.input_transform.weight.dims()[1]
+        self.layers
+            .first()
+            .expect("Expected a GRU model checked by infer_shapes to have at least one layer")
+            .0
+            .new_gate
+            .input_transform
+            .weight
+            .dims()[0]
     }
 
     pub fn get_output_size(&self) -> usize {
-        self.layers.last().unwrap().0.d_hidden
+        let (gru, reverse, _, _) = self
+            .layers
+            .last()
+            .expect("Expected a GRU model checked by infer_shapes to have at least one layer");
+        if reverse.is_some() {
+            gru.d_hidden * 2
+        } else {
+            gru.d_hidden
+        }
     }
 }
 
@@ -81,7 +156,16 @@ pub struct GruModelConfig {
     pub input_size: usize,
     pub default_activation: Option<ActivationConfig>,
     pub default_norm: Option<NormConfig>,
-    pub layers: Vec<Either<usize, Optional<ActivationConfig>, Optional<NormConfig>, Option<f64>>>,
+    /// The initializer of the weights of every layer, instead of picking one from the activation.
+    #[serde(default)]
+    pub default_initializer: Option<InitializerConfig>,
+    pub layers: Vec<
+        Either<RecurrentLayerConfig, Optional<ActivationConfig>, Optional<NormConfig>, Option<f64>>,
+    >,
+    /// Runs every layer over the sequence in both directions and concatenates the hidden states,
+    /// doubling the output size of each layer.
+    #[serde(default)]
+    pub bidirectional: bool,
     #[serde(default = "default_dropout")]
     pub dropout: f64,
     #[serde(default = "default_dropout_last")]
@@ -90,24 +174,31 @@ pub struct GruModelConfig {
 
 impl<B: Backend> Init<B, GruModel<B>> for GruModelConfig {
     fn init(self, device: &B::Device) -> GruModel<B> {
+        let directions = if self.bidirectional { 2 } else { 1 };
         let mut input_size = self.input_size;
         let mut layers = vec![];
-        for (output_size, activation, norm, weights_gain) in
+        for (layer, activation, norm, weights_gain) in
             self.layers.into_iter().map(Either::into_tuple)
         {
+            let hidden_size = layer.hidden_size();
+            let output_size = hidden_size * directions;
             let (norm, activation, init) = handle_norm_activation(
                 norm,
                 activation,
+                layer.initializer(),
                 &self.default_norm,
                 &self.default_activation,
+                &self.default_initializer,
                 weights_gain,
                 output_size,
                 device,
             );
+            let config = GruConfig::new(input_size, hidden_size, norm.is_none())
+                .with_initializer(init.clone());
+            let init_layer = || orthogonal_biases(config.init(device), &init);
             layers.push((
-                GruConfig::new(input_size, output_size, norm.is_none())
-                    .with_initializer(init)
-                    .init(device),
+                init_layer(),
+                self.bidirectional.then(init_layer),
                 norm,
                 activation,
             ));
@@ -121,5 +212,48 @@ impl<B: Backend> Init<B, GruModel<B>> for GruModelConfig {
     }
 }
 
+impl InferShape for GruModelConfig {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        if self.layers.is_empty() {
+            return Err(ShapeError::new("layers", ShapeErrorKind::NoLayers));
+        }
+        let [seq_len, mut features] = expect_rank("layers.0", input_shape)?;
+        let directions = if self.bidirectional { 2 } else { 1 };
+        let mut input_size = self.input_size;
+        let mut shapes = vec![];
+        for (i, (layer, activation, norm, _)) in self
+            .layers
+            .iter()
+            .cloned()
+            .map(Either::into_tuple)
+            .enumerate()
+        {
+            let name = format!("layers.{i}");
+            expect_size(&name, input_size, features)?;
+            let hidden_size = layer.hidden_size();
+            let output_size = hidden_size * directions;
+            let norm_params = norm_params(&name, &norm, &self.default_norm, output_size)?;
+            // Every GRU gate has an input and a hidden transform
+            let weights = directions * 3 * (input_size + hidden_size) * hidden_size;
+            let bias = if norm_params.is_none() {
+                directions * 3 * 2 * hidden_size
+            } else {
+                0
+            };
+            let mut layer = LayerShape {
+                name,
+                output_shape: vec![seq_len, output_size],
+                params: weights + bias + norm_params.unwrap_or_default(),
+                macs: weights * seq_len,
+            };
+            apply_activation(&mut layer, &activation, &self.default_activation)?;
+            input_size = output_size;
+            features = layer.output_shape[1];
+            shapes.push(layer);
+        }
+        Ok(shapes)
+    }
+}
+
 default_f!(default_dropout, f64, 0.0);
 default_f!(default_dropout_last, bool, true);
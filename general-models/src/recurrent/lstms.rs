@@ -0,0 +1,243 @@
+use burn::{
+    module::Module,
+    nn::{
+        Dropout, DropoutConfig,
+        activation::Activation,
+        lstm::{Lstm, LstmConfig},
+    },
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+use utils::default_f;
+
+use crate::{
+    Init, SimpleInfer, SimpleTrain,
+    common::{
+        ActivationConfig, Either, InitializerConfig, Norm, NormConfig, Optional,
+        handle_norm_activation, orthogonal_biases,
+    },
+    error::ShapeError,
+    recurrent::{RecurrentLayerConfig, last_hidden_state, norm_sequence},
+    shape::{InferShape, LayerShape, apply_activation, expect_rank, expect_size, norm_params},
+};
+
+/// A stack of LSTM layers.
+///
+/// The model outputs the whole `[batch_size, seq_len, hidden_size]` sequence when used as a
+/// 3 to 3 dimensional model, and only the last hidden state when used as a 3 to 2 dimensional model.
+#[derive(Debug, Module)]
+pub struct LstmModel<B: Backend> {
+    layers: Vec<(
+        Lstm<B>,
+        Option<Lstm<B>>,
+        Option<Norm<B>>,
+        Option<Activation<B>>,
+    )>,
+    dropout: Dropout,
+    dropout_last: bool,
+}
+
+fn forward_lstm<B: Backend>(
+    lstm: &Lstm<B>,
+    reverse: &Option<Lstm<B>>,
+    tensor: Tensor<B, 3>,
+) -> Tensor<B, 3> {
+    match reverse {
+        Some(reverse) => {
+            let (reversed, _) = reverse.forward(tensor.clone().flip([1]), None);
+            let (output, _) = lstm.forward(tensor, None);
+            Tensor::cat(vec![output, reversed.flip([1])], 2)
+        }
+        None => lstm.forward(tensor, None).0,
+    }
+}
+
+impl<B: Backend> SimpleTrain<B, 3, 3> for LstmModel<B> {
+    fn forward(&self, mut tensor: burn::Tensor<B, 3>) -> burn::Tensor<B, 3> {
+        for (i, (lstm, reverse, norm, activation)) in self.layers.iter().enumerate() {
+            tensor = forward_lstm(lstm, reverse, tensor);
+            if let Some(norm) = norm {
+                tensor = norm_sequence(norm, tensor);
+            }
+            if let Some(activation) = activation {
+                tensor = activation.forward(tensor);
+            }
+            if i < self.layers.len() - 1 || self.dropout_last {
+                tensor = self.dropout.forward(tensor);
+            }
+        }
+        tensor
+    }
+}
+
+impl<B: Backend> SimpleTrain<B, 3, 2> for LstmModel<B> {
+    fn forward(&self, tensor: burn::Tensor<B, 3>) -> burn::Tensor<B, 2> {
+        let tensor: Tensor<B, 3> = self.train(tensor);
+        last_hidden_state(tensor, self.is_bidirectional())
+    }
+}
+
+impl<B: Backend> SimpleInfer<B, 3, 3> for LstmModel<B> {
+    fn forward(&self, mut tensor: burn::Tensor<B, 3>) -> burn::Tensor<B, 3> {
+        for (lstm, reverse, norm, activation) in self.layers.iter() {
+            tensor = forward_lstm(lstm, reverse, tensor);
+            if let Some(norm) = norm {
+                tensor = norm_sequence(norm, tensor);
+            }
+            if let Some(activation) = activation {
+                tensor = activation.forward(tensor);
+            }
+        }
+        tensor
+    }
+}
+
+impl<B: Backend> SimpleInfer<B, 3, 2> for LstmModel<B> {
+    fn forward(&self, tensor: burn::Tensor<B, 3>) -> burn::Tensor<B, 2> {
+        let tensor: Tensor<B, 3> = self.infer(tensor);
+        last_hidden_state(tensor, self.is_bidirectional())
+    }
+}
+
+impl<B: Backend> LstmModel<B> {
+    pub fn iter_layers(
+        &mut self,
+        mut map: impl FnMut(
+            Lstm<B>,
+            Option<Lstm<B>>,
+            Option<Norm<B>>,
+            Option<Activation<B>>,
+        ) -> (
+            Lstm<B>,
+            Option<Lstm<B>>,
+            Option<Norm<B>>,
+            Option<Activation<B>>,
+        ),
+    ) {
+        self.layers = std::mem::take(&mut self.layers)
+            .into_iter()
+            .map(|(lstm, reverse, norm, activation)| map(lstm, reverse, norm, activation))
+            .collect();
+    }
+
+    pub fn is_bidirectional(&self) -> bool {
+        self.layers.last().unwrap().1.is_some()
+    }
+
+    pub fn get_input_size(&self) -> usize {
+        self.layers[0].0.input_gate.input_transform.weight.dims()[0]
+    }
+
+    pub fn get_output_size(&self) -> usize {
+        let (lstm, reverse, _, _) = self.layers.last().unwrap();
+        if reverse.is_some() {
+            lstm.d_hidden * 2
+        } else {
+            lstm.d_hidden
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Deserialize, Clone)]
+pub struct LstmModelConfig {
+    pub input_size: usize,
+    pub default_activation: Option<ActivationConfig>,
+    pub default_norm: Option<NormConfig>,
+    /// The initializer of the weights of every layer, instead of picking one from the activation.
+    #[serde(default)]
+    pub default_initializer: Option<InitializerConfig>,
+    pub layers: Vec<
+        Either<RecurrentLayerConfig, Optional<ActivationConfig>, Optional<NormConfig>, Option<f64>>,
+    >,
+    /// Runs every layer over the sequence in both directions and concatenates the hidden states,
+    /// doubling the output size of each layer.
+    #[serde(default)]
+    pub bidirectional: bool,
+    #[serde(default = "default_dropout")]
+    pub dropout: f64,
+    #[serde(default = "default_dropout_last")]
+    pub dropout_last: bool,
+}
+
+impl<B: Backend> Init<B, LstmModel<B>> for LstmModelConfig {
+    fn init(self, device: &B::Device) -> LstmModel<B> {
+        let directions = if self.bidirectional { 2 } else { 1 };
+        let mut input_size = self.input_size;
+        let mut layers = vec![];
+        for (layer, activation, norm, weights_gain) in
+            self.layers.into_iter().map(Either::into_tuple)
+        {
+            let hidden_size = layer.hidden_size();
+            let output_size = hidden_size * directions;
+            let (norm, activation, init) = handle_norm_activation(
+                norm,
+                activation,
+                layer.initializer(),
+                &self.default_norm,
+                &self.default_activation,
+                &self.default_initializer,
+                weights_gain,
+                output_size,
+                device,
+            );
+            let config = LstmConfig::new(input_size, hidden_size, norm.is_none())
+                .with_initializer(init.clone());
+            let init_layer = || orthogonal_biases(config.init(device), &init);
+            layers.push((
+                init_layer(),
+                self.bidirectional.then(init_layer),
+                norm,
+                activation,
+            ));
+            input_size = output_size;
+        }
+        LstmModel {
+            layers,
+            dropout: DropoutConfig::new(self.dropout).init(),
+            dropout_last: self.dropout_last,
+        }
+    }
+}
+
+impl InferShape for LstmModelConfig {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        let [seq_len, mut features] = expect_rank("layers.0", input_shape)?;
+        let directions = if self.bidirectional { 2 } else { 1 };
+        let mut input_size = self.input_size;
+        let mut shapes = vec![];
+        for (i, (layer, activation, norm, _)) in self
+            .layers
+            .iter()
+            .cloned()
+            .map(Either::into_tuple)
+            .enumerate()
+        {
+            let name = format!("layers.{i}");
+            expect_size(&name, input_size, features)?;
+            let hidden_size = layer.hidden_size();
+            let output_size = hidden_size * directions;
+            let norm_params = norm_params(&name, &norm, &self.default_norm, output_size)?;
+            // Every LSTM gate has an input and a hidden transform
+            let weights = directions * 4 * (input_size + hidden_size) * hidden_size;
+            let bias = if norm_params.is_none() {
+                directions * 4 * 2 * hidden_size
+            } else {
+                0
+            };
+            let mut layer = LayerShape {
+                name,
+                output_shape: vec![seq_len, output_size],
+                params: weights + bias + norm_params.unwrap_or_default(),
+                macs: weights * seq_len,
+            };
+            apply_activation(&mut layer, &activation, &self.default_activation)?;
+            input_size = output_size;
+            features = layer.output_shape[1];
+            shapes.push(layer);
+        }
+        Ok(shapes)
+    }
+}
+
+default_f!(default_dropout, f64, 0.0);
+default_f!(default_dropout_last, bool, true);
@@ -0,0 +1,394 @@
+use burn::{
+    module::{Ignored, Module},
+    nn::{
+        Dropout, DropoutConfig,
+        activation::Activation,
+        interpolate::{Interpolate2d, Interpolate2dConfig},
+        pool::{AdaptiveAvgPool2d, AdaptiveAvgPool2dConfig},
+    },
+    prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Init, SimpleInfer, SimpleTrain,
+    common::{ActivationConfig, Norm, NormConfig, Optional},
+    conv::{
+        Conv2dModel, Conv2dModelConfig, ConvTranspose2dModel, ConvTranspose2dModelConfig, Pool2d,
+        Pool2dConfig,
+    },
+    error::{ExportError, ShapeError, ShapeErrorKind},
+    linear::{LinearModel, LinearModelConfig},
+    onnx::{OnnxGraph, OnnxValue, ToOnnx},
+    shape::{
+        InferShape, LayerShape, apply_activation, expect_rank, expect_size, infer_nested,
+        interpolate_size, norm_params,
+    },
+};
+
+#[derive(Debug, Module)]
+pub enum Block<B: Backend> {
+    Conv(Conv2dModel<B>),
+    ConvTranspose(ConvTranspose2dModel<B>),
+    Linear(LinearModel<B>),
+    /// Reshapes everything but the batch axis.
+    Reshape(Ignored<Vec<usize>>),
+    Pool(Pool2d),
+    AdaptiveAvgPool(AdaptiveAvgPool2d),
+    Interpolate(Interpolate2d),
+    Norm(Norm<B>),
+    Activation(Activation<B>),
+    Dropout(Dropout),
+}
+
+/// A chain of blocks where each block may change the rank of the tensor.
+///
+/// The ranks of the input and output are only known at runtime, so using the model with the wrong
+/// ranks panics.
+#[derive(Debug, Module)]
+pub struct SequentialModel<B: Backend> {
+    input_shape: Ignored<Vec<usize>>,
+    blocks: Vec<Block<B>>,
+}
+
+/// A tensor whose rank is only known at runtime.
+enum RankedTensor<B: Backend> {
+    Two(Tensor<B, 2>),
+    Three(Tensor<B, 3>),
+    Four(Tensor<B, 4>),
+}
+
+macro_rules! map_ranked {
+    ($tensor: expr, |$inner: ident| $body: expr) => {
+        match $tensor {
+            RankedTensor::Two($inner) => RankedTensor::Two($body),
+            RankedTensor::Three($inner) => RankedTensor::Three($body),
+            RankedTensor::Four($inner) => RankedTensor::Four($body),
+        }
+    };
+}
+
+fn cast<B: Backend, const D1: usize, const D2: usize>(tensor: Tensor<B, D1>) -> Tensor<B, D2> {
+    assert_eq!(D1, D2, "Expected a tensor of rank {D2}, got rank {D1}");
+    let dims = tensor.dims();
+    tensor.reshape(std::array::from_fn::<usize, D2, _>(|i| dims[i]))
+}
+
+impl<B: Backend> RankedTensor<B> {
+    fn new<const D: usize>(tensor: Tensor<B, D>) -> Self {
+        match D {
+            2 => RankedTensor::Two(cast(tensor)),
+            3 => RankedTensor::Three(cast(tensor)),
+            4 => RankedTensor::Four(cast(tensor)),
+            _ => panic!("Sequential models only support tensors of rank 2 to 4, got rank {D}"),
+        }
+    }
+
+    fn into_tensor<const D: usize>(self) -> Tensor<B, D> {
+        match self {
+            RankedTensor::Two(tensor) => cast(tensor),
+            RankedTensor::Three(tensor) => cast(tensor),
+            RankedTensor::Four(tensor) => cast(tensor),
+        }
+    }
+
+    fn reshape(self, shape: &[usize]) -> Self {
+        let batch_size = match &self {
+            RankedTensor::Two(tensor) => tensor.dims()[0],
+            RankedTensor::Three(tensor) => tensor.dims()[0],
+            RankedTensor::Four(tensor) => tensor.dims()[0],
+        };
+        let mut dims = vec![batch_size];
+        dims.extend_from_slice(shape);
+        match self {
+            RankedTensor::Two(tensor) => Self::reshape_from(tensor, &dims),
+            RankedTensor::Three(tensor) => Self::reshape_from(tensor, &dims),
+            RankedTensor::Four(tensor) => Self::reshape_from(tensor, &dims),
+        }
+    }
+
+    fn reshape_from<const D: usize>(tensor: Tensor<B, D>, dims: &[usize]) -> Self {
+        match *dims {
+            [a, b] => RankedTensor::Two(tensor.reshape([a, b])),
+            [a, b, c] => RankedTensor::Three(tensor.reshape([a, b, c])),
+            [a, b, c, d] => RankedTensor::Four(tensor.reshape([a, b, c, d])),
+            _ => panic!("Cannot reshape to {dims:?}"),
+        }
+    }
+}
+
+impl<B: Backend> SequentialModel<B> {
+    fn forward_blocks(&self, mut tensor: RankedTensor<B>, train: bool) -> RankedTensor<B> {
+        for block in &self.blocks {
+            tensor = match (block, tensor) {
+                (Block::Conv(conv), RankedTensor::Four(tensor)) => RankedTensor::Four(if train {
+                    conv.train(tensor)
+                } else {
+                    conv.infer(tensor)
+                }),
+                (Block::ConvTranspose(conv), RankedTensor::Four(tensor)) => {
+                    RankedTensor::Four(if train {
+                        conv.train(tensor)
+                    } else {
+                        conv.infer(tensor)
+                    })
+                }
+                (Block::Linear(linear), RankedTensor::Two(tensor)) => RankedTensor::Two(if train {
+                    linear.train(tensor)
+                } else {
+                    linear.infer(tensor)
+                }),
+                (Block::Reshape(shape), tensor) => tensor.reshape(shape),
+                (Block::Pool(pool), RankedTensor::Four(tensor)) => {
+                    RankedTensor::Four(pool.forward(tensor))
+                }
+                (Block::AdaptiveAvgPool(pool), RankedTensor::Four(tensor)) => {
+                    RankedTensor::Four(pool.forward(tensor))
+                }
+                (Block::Interpolate(interpolate), RankedTensor::Four(tensor)) => {
+                    RankedTensor::Four(interpolate.forward(tensor))
+                }
+                (Block::Norm(norm), tensor) => map_ranked!(tensor, |tensor| norm.forward(tensor)),
+                (Block::Activation(activation), tensor) => {
+                    map_ranked!(tensor, |tensor| activation.forward(tensor))
+                }
+                (Block::Dropout(dropout), tensor) => {
+                    if train {
+                        map_ranked!(tensor, |tensor| dropout.forward(tensor))
+                    } else {
+                        tensor
+                    }
+                }
+                (block, _) => panic!("Incorrect tensor rank for {block:?}"),
+            };
+        }
+        tensor
+    }
+
+    pub fn iter_blocks(&mut self, map: impl FnMut(Block<B>) -> Block<B>) {
+        self.blocks = std::mem::take(&mut self.blocks)
+            .into_iter()
+            .map(map)
+            .collect();
+    }
+
+    /// The shape of a single input, without the batch axis.
+    pub fn get_input_shape(&self) -> &[usize] {
+        &self.input_shape
+    }
+}
+
+impl<B: Backend, const N_I: usize, const N_O: usize> SimpleTrain<B, N_I, N_O>
+    for SequentialModel<B>
+{
+    fn forward(&self, tensor: Tensor<B, N_I>) -> Tensor<B, N_O> {
+        self.forward_blocks(RankedTensor::new(tensor), true)
+            .into_tensor()
+    }
+}
+
+impl<B: Backend, const N_I: usize, const N_O: usize> SimpleInfer<B, N_I, N_O>
+    for SequentialModel<B>
+{
+    fn forward(&self, tensor: Tensor<B, N_I>) -> Tensor<B, N_O> {
+        self.forward_blocks(RankedTensor::new(tensor), false)
+            .into_tensor()
+    }
+}
+
+impl<B: Backend> ToOnnx for SequentialModel<B> {
+    fn to_onnx(
+        &self,
+        graph: &mut OnnxGraph,
+        mut input: OnnxValue,
+    ) -> Result<OnnxValue, ExportError> {
+        for block in &self.blocks {
+            input = match block {
+                Block::Conv(conv) => conv.to_onnx(graph, input)?,
+                Block::ConvTranspose(conv) => conv.to_onnx(graph, input)?,
+                Block::Linear(linear) => linear.to_onnx(graph, input)?,
+                Block::Reshape(shape) => graph.reshape(&input, shape),
+                Block::Pool(pool) => pool.to_onnx(graph, input)?,
+                Block::AdaptiveAvgPool(pool) => pool.to_onnx(graph, input)?,
+                Block::Interpolate(interpolate) => interpolate.to_onnx(graph, input)?,
+                Block::Norm(norm) => norm.to_onnx(graph, input)?,
+                Block::Activation(activation) => activation.to_onnx(graph, input)?,
+                Block::Dropout(_) => input,
+            };
+        }
+        Ok(input)
+    }
+}
+
+/// One block of a [`SequentialModelConfig`].
+///
+/// Shapes never include the batch axis, so images are `[channels, height, width]` and feature
+/// vectors are `[features]`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockConfig {
+    Conv(Conv2dModelConfig),
+    ConvTranspose(ConvTranspose2dModelConfig),
+    Linear(LinearModelConfig),
+    /// Flattens everything but the batch axis.
+    Flatten,
+    Reshape(Vec<usize>),
+    Pool(Pool2dConfig),
+    AdaptiveAvgPool([usize; 2]),
+    Interpolate(Interpolate2dConfig),
+    /// Normalizes the channels, or the last axis for layer and RMS norms.
+    Norm(NormConfig),
+    Activation(ActivationConfig),
+    Dropout(f64),
+}
+
+/// Returns the number of features `norm` normalizes over in an input of `shape`.
+fn norm_features(norm: &NormConfig, shape: &[usize]) -> usize {
+    match norm {
+        NormConfig::LayerNorm { .. } | NormConfig::RmsNorm { .. } => *shape.last().unwrap(),
+        _ => shape[0],
+    }
+}
+
+impl BlockConfig {
+    /// Appends the layers of this block to `shapes` and returns the output shape.
+    fn infer_shapes(
+        &self,
+        name: &str,
+        input_shape: &[usize],
+        shapes: &mut Vec<LayerShape>,
+    ) -> Result<Vec<usize>, ShapeError> {
+        let output_shape = match self {
+            BlockConfig::Conv(config) => {
+                return infer_nested(shapes, name, config, input_shape);
+            }
+            BlockConfig::ConvTranspose(config) => {
+                return infer_nested(shapes, name, config, input_shape);
+            }
+            BlockConfig::Linear(config) => {
+                expect_rank::<1>(name, input_shape)?;
+                return infer_nested(shapes, name, config, input_shape);
+            }
+            BlockConfig::Flatten => vec![input_shape.iter().product()],
+            BlockConfig::Reshape(shape) => {
+                if shape.is_empty() || shape.len() > 3 {
+                    return Err(ShapeError::new(
+                        name,
+                        ShapeErrorKind::RankMismatch {
+                            expected: input_shape.len(),
+                            actual: shape.clone(),
+                        },
+                    ));
+                }
+                expect_size(name, shape.iter().product(), input_shape.iter().product())?;
+                shape.clone()
+            }
+            BlockConfig::Pool(config) => config
+                .output_shape(name, expect_rank(name, input_shape)?)?
+                .to_vec(),
+            BlockConfig::AdaptiveAvgPool([height, width]) => {
+                let [channels, _, _] = expect_rank(name, input_shape)?;
+                vec![channels, *height, *width]
+            }
+            BlockConfig::Interpolate(config) => {
+                let [channels, height, width] = expect_rank(name, input_shape)?;
+                let [height, width] = interpolate_size(config, [height, width]);
+                vec![channels, height, width]
+            }
+            BlockConfig::Norm(config) => {
+                let features = norm_features(config, input_shape);
+                let params = norm_params(name, &Optional::Inner(config.clone()), &None, features)?;
+                shapes.push(LayerShape {
+                    params: params.unwrap_or_default(),
+                    ..LayerShape::new(name, input_shape.to_vec())
+                });
+                return Ok(input_shape.to_vec());
+            }
+            BlockConfig::Activation(config) => {
+                let mut layer = LayerShape::new(name, input_shape.to_vec());
+                apply_activation(&mut layer, &Optional::Inner(config.clone()), &None)?;
+                let output_shape = layer.output_shape.clone();
+                shapes.push(layer);
+                return Ok(output_shape);
+            }
+            BlockConfig::Dropout(_) => input_shape.to_vec(),
+        };
+        shapes.push(LayerShape::new(name, output_shape.clone()));
+        Ok(output_shape)
+    }
+
+    /// Creates the block for an input of `input_shape` and output of `output_shape`.
+    ///
+    /// Returns `None` if the block does nothing.
+    fn init<B: Backend>(
+        self,
+        input_shape: &[usize],
+        output_shape: &[usize],
+        device: &B::Device,
+    ) -> Option<Block<B>> {
+        Some(match self {
+            BlockConfig::Conv(config) => Block::Conv(config.init(device)),
+            BlockConfig::ConvTranspose(config) => Block::ConvTranspose(config.init(device)),
+            BlockConfig::Linear(config) => Block::Linear(config.init(device)),
+            BlockConfig::Flatten | BlockConfig::Reshape(_) => {
+                Block::Reshape(Ignored(output_shape.to_vec()))
+            }
+            BlockConfig::Pool(config) => Block::Pool(config.init()),
+            BlockConfig::AdaptiveAvgPool(output_size) => {
+                Block::AdaptiveAvgPool(AdaptiveAvgPool2dConfig::new(output_size).init())
+            }
+            BlockConfig::Interpolate(config) => Block::Interpolate(config.init()),
+            BlockConfig::Norm(config) => {
+                let features = norm_features(&config, input_shape);
+                Block::Norm(config.init(device, features)?)
+            }
+            BlockConfig::Activation(config) => Block::Activation(config.init(device)),
+            BlockConfig::Dropout(prob) => Block::Dropout(DropoutConfig::new(prob).init()),
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SequentialModelConfig {
+    /// The shape of a single input, without the batch axis.
+    pub input_shape: Vec<usize>,
+    pub blocks: Vec<BlockConfig>,
+}
+
+impl InferShape for SequentialModelConfig {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        if input_shape != self.input_shape {
+            return Err(ShapeError::new(
+                "input_shape",
+                ShapeErrorKind::ShapeMismatch {
+                    expected: self.input_shape.clone(),
+                    actual: input_shape.to_vec(),
+                },
+            ));
+        }
+        let mut shapes = vec![];
+        let mut shape = input_shape.to_vec();
+        for (i, block) in self.blocks.iter().enumerate() {
+            shape = block.infer_shapes(&format!("blocks.{i}"), &shape, &mut shapes)?;
+        }
+        Ok(shapes)
+    }
+}
+
+impl<B: Backend> Init<B, SequentialModel<B>> for SequentialModelConfig {
+    fn init(self, device: &B::Device) -> SequentialModel<B> {
+        let mut shape = self.input_shape.clone();
+        let mut blocks = vec![];
+        for (i, block) in self.blocks.into_iter().enumerate() {
+            let output_shape = block
+                .infer_shapes(&format!("blocks.{i}"), &shape, &mut vec![])
+                .unwrap_or_else(|e| panic!("Invalid sequential model config: {e}"));
+            blocks.extend(block.init(&shape, &output_shape, device));
+            shape = output_shape;
+        }
+        SequentialModel {
+            input_shape: Ignored(self.input_shape),
+            blocks,
+        }
+    }
+}
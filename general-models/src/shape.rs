@@ -0,0 +1,226 @@
+//! Shape inference over model configs, without initializing any weights.
+//!
+//! Shapes never include the batch dimension, so an image is `[channels, height, width]`, a
+//! feature vector is `[features]`, and a sequence is `[seq_len, features]`.
+
+use burn::nn::interpolate::Interpolate2dConfig;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{ActivationConfig, NormConfig, Optional},
+    error::{ShapeError, ShapeErrorKind},
+};
+
+/// The shape coming out of one named step of a model, along with what the step costs.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct LayerShape {
+    /// The path of the layer, following the field names of the model, eg. `encoder.conv.layers.1`.
+    pub name: String,
+    pub output_shape: Vec<usize>,
+    /// The number of trainable parameters in the layer, including its norm, activation and skip
+    /// connection. Running statistics of batch norms are not counted.
+    #[serde(default)]
+    pub params: usize,
+    /// An estimate of the multiply-accumulates the layer performs on a single input.
+    ///
+    /// Only weighted operations are counted, so norms, pooling and interpolation are free.
+    #[serde(default)]
+    pub macs: usize,
+}
+
+impl LayerShape {
+    /// Creates a layer without any parameters or multiply-accumulates.
+    pub fn new(name: impl Into<String>, output_shape: Vec<usize>) -> Self {
+        Self {
+            name: name.into(),
+            output_shape,
+            params: 0,
+            macs: 0,
+        }
+    }
+}
+
+pub trait InferShape {
+    /// Returns the output shape of every layer the given input flows through, in order.
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError>;
+
+    /// Returns the shape of the final output of the model.
+    fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, ShapeError> {
+        let shapes = self.infer_shapes(input_shape)?;
+        Ok(last_shape(&shapes, input_shape))
+    }
+}
+
+impl<T: InferShape> InferShape for &T {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        T::infer_shapes(self, input_shape)
+    }
+
+    fn output_shape(&self, input_shape: &[usize]) -> Result<Vec<usize>, ShapeError> {
+        T::output_shape(self, input_shape)
+    }
+}
+
+pub(crate) fn last_shape(shapes: &[LayerShape], input_shape: &[usize]) -> Vec<usize> {
+    shapes
+        .last()
+        .map(|layer| layer.output_shape.clone())
+        .unwrap_or_else(|| input_shape.to_vec())
+}
+
+/// Runs `model` on `input_shape` and appends its layers to `shapes` under `prefix`.
+///
+/// Returns the output shape of `model`.
+pub(crate) fn infer_nested(
+    shapes: &mut Vec<LayerShape>,
+    prefix: &str,
+    model: &impl InferShape,
+    input_shape: &[usize],
+) -> Result<Vec<usize>, ShapeError> {
+    let nested = model
+        .infer_shapes(input_shape)
+        .map_err(|error| error.nested(prefix))?;
+    let output_shape = last_shape(&nested, input_shape);
+    shapes.extend(nested.into_iter().map(|layer| LayerShape {
+        name: format!("{prefix}.{}", layer.name),
+        ..layer
+    }));
+    Ok(output_shape)
+}
+
+pub(crate) fn expect_rank<const N: usize>(
+    layer: &str,
+    shape: &[usize],
+) -> Result<[usize; N], ShapeError> {
+    shape.try_into().map_err(|_| {
+        ShapeError::new(
+            layer,
+            ShapeErrorKind::RankMismatch {
+                expected: N,
+                actual: shape.to_vec(),
+            },
+        )
+    })
+}
+
+pub(crate) fn expect_size(layer: &str, expected: usize, actual: usize) -> Result<(), ShapeError> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(ShapeError::new(
+            layer,
+            ShapeErrorKind::SizeMismatch { expected, actual },
+        ))
+    }
+}
+
+pub(crate) fn expect_divisible(layer: &str, size: usize, divisor: usize) -> Result<(), ShapeError> {
+    if divisor != 0 && size.is_multiple_of(divisor) {
+        Ok(())
+    } else {
+        Err(ShapeError::new(
+            layer,
+            ShapeErrorKind::NotDivisible { size, divisor },
+        ))
+    }
+}
+
+/// Resolves the norm of a layer the same way `Init` does and checks it against `features`.
+///
+/// Returns the number of parameters in the norm, or `None` if there is no norm, in which case
+/// the layer has a bias instead.
+pub(crate) fn norm_params(
+    layer: &str,
+    norm: &Optional<NormConfig>,
+    default_norm: &Option<NormConfig>,
+    features: usize,
+) -> Result<Option<usize>, ShapeError> {
+    Ok(match norm.clone().resolve(|| default_norm.clone()) {
+        Some(NormConfig::BatchNorm { .. } | NormConfig::LayerNorm { .. }) => Some(2 * features),
+        Some(NormConfig::RmsNorm { .. }) => Some(features),
+        Some(NormConfig::GroupNorm {
+            num_groups, affine, ..
+        }) => {
+            expect_divisible(layer, features, num_groups)?;
+            Some(if affine { 2 * features } else { 0 })
+        }
+        Some(NormConfig::InstanceNorm { affine, .. }) => {
+            Some(if affine { 2 * features } else { 0 })
+        }
+        Some(NormConfig::None) | None => None,
+    })
+}
+
+/// Resolves the activation of a layer the same way `Init` does and applies it to `layer`.
+///
+/// Only SwiGLU changes the shape, mapping the last axis from `d_input` to `d_output`.
+pub(crate) fn apply_activation(
+    layer: &mut LayerShape,
+    activation: &Optional<ActivationConfig>,
+    default_activation: &Option<ActivationConfig>,
+) -> Result<(), ShapeError> {
+    match activation.clone().resolve(|| default_activation.clone()) {
+        Some(ActivationConfig::PRelu(config)) => layer.params += config.num_parameters,
+        Some(ActivationConfig::SwiGlu(config)) => {
+            let last = layer
+                .output_shape
+                .last_mut()
+                .expect("Shapes should never be empty");
+            expect_size(&layer.name, config.d_input, *last)?;
+            *last = config.d_output;
+            // SwiGLU is made of two linear layers
+            let weights = config.d_input * config.d_output;
+            let bias = if config.bias { config.d_output } else { 0 };
+            layer.params += 2 * (weights + bias);
+            layer.macs += 2 * weights * positions(&layer.output_shape);
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// Returns the number of positions a layer acting on the last axis of `shape` is applied at.
+pub(crate) fn positions(shape: &[usize]) -> usize {
+    shape[..shape.len() - 1].iter().product()
+}
+
+/// Returns the output size of a sliding window over an axis of `input` elements.
+pub(crate) fn window_output_size(
+    layer: &str,
+    input: usize,
+    kernel_size: usize,
+    stride: usize,
+    dilation: usize,
+    padding: usize,
+) -> Result<usize, ShapeError> {
+    if kernel_size == 0 {
+        return Err(ShapeError::new(layer, ShapeErrorKind::ZeroKernelSize));
+    }
+    if stride == 0 {
+        return Err(ShapeError::new(layer, ShapeErrorKind::ZeroStride));
+    }
+    let padded = input + 2 * padding;
+    let window = dilation * (kernel_size - 1) + 1;
+    if padded < window {
+        return Err(ShapeError::new(
+            layer,
+            ShapeErrorKind::TooSmall {
+                input,
+                padding,
+                window,
+            },
+        ));
+    }
+    Ok((padded - window) / stride + 1)
+}
+
+/// Returns the `[height, width]` that `config` interpolates an image of `size` to.
+pub(crate) fn interpolate_size(config: &Interpolate2dConfig, size: [usize; 2]) -> [usize; 2] {
+    match (config.output_size, config.scale_factor) {
+        (Some(output_size), _) => output_size,
+        (None, Some(scale_factor)) => {
+            [0, 1].map(|i| (size[i] as f64 * scale_factor[i] as f64) as usize)
+        }
+        (None, None) => size,
+    }
+}
@@ -0,0 +1,92 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    error::ShapeError,
+    shape::{InferShape, LayerShape, last_shape},
+};
+
+/// A per-layer breakdown of a model, built from its config without initializing any weights.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModelSummary {
+    pub input_shape: Vec<usize>,
+    pub output_shape: Vec<usize>,
+    pub layers: Vec<LayerShape>,
+    pub total_params: usize,
+    pub total_macs: usize,
+    /// Approximated as two floating point operations per multiply-accumulate.
+    pub total_flops: usize,
+}
+
+impl ModelSummary {
+    pub fn new(config: &impl InferShape, input_shape: &[usize]) -> Result<Self, ShapeError> {
+        let layers = config.infer_shapes(input_shape)?;
+        let total_params = layers.iter().map(|layer| layer.params).sum();
+        let total_macs = layers.iter().map(|layer| layer.macs).sum();
+        Ok(Self {
+            input_shape: input_shape.to_vec(),
+            output_shape: last_shape(&layers, input_shape),
+            layers,
+            total_params,
+            total_macs,
+            total_flops: 2 * total_macs,
+        })
+    }
+}
+
+/// Formats a number with thousands separators.
+fn separated(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+impl Display for ModelSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rows: Vec<_> = self
+            .layers
+            .iter()
+            .map(|layer| {
+                [
+                    layer.name.clone(),
+                    format!("{:?}", layer.output_shape),
+                    separated(layer.params),
+                    separated(layer.macs),
+                ]
+            })
+            .collect();
+        let header = ["Layer", "Output Shape", "Params", "MACs"];
+        let [name_w, shape_w, params_w, macs_w]: [usize; 4] = std::array::from_fn(|i| {
+            rows.iter()
+                .map(|row| row[i].len())
+                .fold(header[i].len(), usize::max)
+        });
+        let rule = "-".repeat(name_w + shape_w + params_w + macs_w + 6);
+
+        writeln!(f, "Input Shape: {:?}", self.input_shape)?;
+        writeln!(
+            f,
+            "{:<name_w$}  {:<shape_w$}  {:>params_w$}  {:>macs_w$}",
+            header[0], header[1], header[2], header[3]
+        )?;
+        writeln!(f, "{rule}")?;
+        for [name, shape, params, macs] in &rows {
+            writeln!(
+                f,
+                "{name:<name_w$}  {shape:<shape_w$}  {params:>params_w$}  {macs:>macs_w$}"
+            )?;
+        }
+        writeln!(f, "{rule}")?;
+        writeln!(f, "Output Shape: {:?}", self.output_shape)?;
+        writeln!(f, "Total Params: {}", separated(self.total_params))?;
+        writeln!(f, "Total MACs: {}", separated(self.total_macs))?;
+        write!(f, "Total FLOPs: {}", separated(self.total_flops))
+    }
+}
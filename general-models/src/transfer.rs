@@ -0,0 +1,148 @@
+//! Transfer learning: initializing a submodule of a model with the weights of a submodule of
+//! another model, and freezing parts of a model so that they aren't trained.
+//!
+//! Submodules are named by the readable paths of [`crate::weights`], like `encoder` or
+//! `encoder.conv.layers.0`.
+
+use std::collections::HashSet;
+
+use burn::{
+    module::{ModuleMapper, Param, ParamId},
+    prelude::*,
+};
+use burn_store::{KeyRemapper, ModuleSnapshot, SafetensorsStore};
+
+use crate::{
+    error::{LoadModelError, StoreError, UnmatchedPathError},
+    weights::{check_shapes, escape, load_patterns, tensor_names},
+};
+
+/// Prefixes the tensors of a checkpoint outside of the loaded submodule, so that they don't match
+/// any tensor of the model.
+const SKIPPED_PREFIX: &str = "__skipped__.";
+
+/// A module path as a prefix of the names of its tensors, which is empty for the whole model.
+fn dotted(path: &str) -> String {
+    if path.is_empty() {
+        String::new()
+    } else {
+        format!("{path}.")
+    }
+}
+
+/// Loads the submodule `from` of safetensors saved with [`crate::weights::save_safetensors`],
+/// such as the `encoder` of an autoencoder, into the submodule `to` of `model`, such as the
+/// `model` of a classifier. The other tensors of `model` are left as they are.
+///
+/// Either path can be empty for the whole model. Every tensor of `to` must be in `from`.
+pub fn load_submodule<B: Backend, M: Module<B>>(
+    mut model: M,
+    safetensors: Vec<u8>,
+    from: &str,
+    to: &str,
+) -> Result<M, LoadModelError> {
+    let (from, to) = (dotted(from), dotted(to));
+    let names: Vec<_> = tensor_names(&model)
+        .into_iter()
+        .filter(|x| x.readable.starts_with(&to))
+        .collect();
+    if names.is_empty() {
+        return Err(UnmatchedPathError(to.trim_end_matches('.').into()).into());
+    }
+
+    // Every tensor is skipped, and then the ones of `from` are moved to `to`
+    let patterns = [
+        ("^".to_string(), SKIPPED_PREFIX.to_string()),
+        (
+            format!("^{}{}", escape(SKIPPED_PREFIX), escape(&from)),
+            to.clone(),
+        ),
+    ];
+    let remapper =
+        KeyRemapper::from_pattern_iter(patterns.into_iter().chain(load_patterns(&model)))
+            .expect("Expected escaped module paths to be valid regex");
+    let mut store = SafetensorsStore::from_bytes(Some(safetensors))
+        .remap(remapper)
+        .validate(false)
+        .allow_partial(true);
+    let result = model.load_from(&mut store).map_err(StoreError::from)?;
+    check_shapes(&result)?;
+
+    let missing: Vec<_> = names
+        .into_iter()
+        .filter(|x| result.missing.iter().any(|(path, _)| *path == x.path))
+        .map(|x| x.readable)
+        .collect();
+    if !missing.is_empty() {
+        return Err(LoadModelError::MissingTensors(missing));
+    }
+    Ok(model)
+}
+
+/// Whether the readable name of a tensor is within the submodules of `pattern`, whose segments
+/// are either names or ranges of indices, like `encoder.conv.layers.0..2` for the first two
+/// layers. Ranges exclude their end, and either end can be left out, as in `layers.3..`.
+fn matches(pattern: &str, readable: &str) -> bool {
+    let mut segments = readable.split('.');
+    // Ranges are swapped for a character that can't be in a module name, so that their dots
+    // don't separate segments
+    let pattern = pattern.replace("..", ":");
+    pattern.split('.').all(|pattern| {
+        let Some(segment) = segments.next() else {
+            return false;
+        };
+        let Some((start, end)) = pattern.split_once(':') else {
+            return pattern == segment;
+        };
+        let bound = |x: &str, default| {
+            if x.is_empty() {
+                Some(default)
+            } else {
+                x.parse().ok()
+            }
+        };
+        match (segment.parse(), bound(start, 0), bound(end, usize::MAX)) {
+            (Ok(i), Some(start), Some(end)) => (start..end).contains(&i),
+            _ => false,
+        }
+    })
+}
+
+struct Freezer<'a> {
+    frozen: &'a HashSet<ParamId>,
+}
+
+impl<B: Backend> ModuleMapper<B> for Freezer<'_> {
+    fn map_float<const D: usize>(&mut self, param: Param<Tensor<B, D>>) -> Param<Tensor<B, D>> {
+        if self.frozen.contains(&param.id) {
+            param.set_require_grad(false)
+        } else {
+            param
+        }
+    }
+}
+
+/// Stops the submodules of `model` that match any of `patterns` from being trained, such as
+/// `encoder` or `encoder.conv.layers.0..2`. Their gradients aren't computed, so optimizers skip
+/// them. Running statistics, like those of batch norms, are still updated.
+///
+/// Each pattern must match at least one tensor.
+pub fn freeze<B: Backend, M: Module<B>>(
+    model: M,
+    patterns: &[impl AsRef<str>],
+) -> Result<M, UnmatchedPathError> {
+    let names = tensor_names(&model);
+    let mut frozen = HashSet::new();
+    for pattern in patterns {
+        let pattern = pattern.as_ref();
+        let mut matching = names
+            .iter()
+            .filter(|x| matches(pattern, &x.readable))
+            .peekable();
+        if matching.peek().is_none() {
+            return Err(UnmatchedPathError(pattern.into()));
+        }
+        frozen.extend(matching.map(|x| x.id));
+    }
+    Ok(model.map(&mut Freezer { frozen: &frozen }))
+}
@@ -0,0 +1,249 @@
+//! Saving and loading weights as [safetensors](https://huggingface.co/docs/safetensors), and
+//! importing weights trained with PyTorch.
+//!
+//! Burn names tensors after the fields of the modules, including enum variants and tuple
+//! indices, like `Vae.encoder.model.conv.layers.0.1.BatchNorm.gamma`. Files written here use
+//! readable names instead, like `encoder.model.conv.layers.0.norm.gamma`:
+//!
+//! - Enum variants are skipped.
+//! - In the tuples of the layers of a model, the layer itself is skipped, and the other elements
+//!   are named `norm`, `activation` and `reverse` for the reverse direction of a bidirectional
+//!   recurrent layer.
+
+use std::path::Path;
+
+use burn::{module::ParamId, prelude::*};
+use burn_store::{
+    ApplyError, ApplyResult, KeyRemapper, ModuleSnapshot, PyTorchToBurnAdapter, PytorchStore,
+    SafetensorsStore,
+};
+use serde::Deserialize;
+
+use crate::error::{LoadModelError, StoreError};
+
+/// Renames the tensors of a PyTorch state dict to the readable names of the model they are
+/// loaded into.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PytorchNameMapping {
+    /// The key of the state dict within a `.pt` file, such as `state_dict` or `model` for files
+    /// saved as training checkpoints.
+    pub top_level_key: Option<String>,
+    /// Regex patterns and their replacements, applied in order to every tensor name, such as
+    /// `["^features\\.0\\.", "encoder.conv.layers.0."]`.
+    #[serde(default)]
+    pub names: Vec<(String, String)>,
+}
+
+/// Escapes the dots of a module path, the only regex metacharacter it can contain.
+pub(crate) fn escape(path: &str) -> String {
+    path.replace('.', r"\.")
+}
+
+/// Joins the segments of a module path, with a trailing dot unless it is the root module.
+pub(crate) fn prefix(segments: &[&str]) -> String {
+    if segments.is_empty() {
+        String::new()
+    } else {
+        format!("{}.", segments.join("."))
+    }
+}
+
+/// The readable path of the module of a tensor, from the Burn path of the tensor and the
+/// containers of its segments.
+fn readable_module<'a>(path: &'a [String], containers: &[String]) -> Vec<&'a str> {
+    // The last segment is the name of the tensor within its module
+    let module = &path[..path.len() - 1];
+    let mut readable = vec![];
+    for (i, segment) in module.iter().enumerate() {
+        // Each segment is paired with the container it is a field of
+        match containers[i].as_str() {
+            container if container.starts_with("Enum:") => {}
+            "Tuple" => match containers[i + 1].as_str() {
+                "Enum:Norm" => readable.push("norm"),
+                "Enum:Activation" => readable.push("activation"),
+                _ if segment == "0" => {}
+                _ => readable.push("reverse"),
+            },
+            _ => readable.push(segment.as_str()),
+        }
+    }
+    readable
+}
+
+/// Pairs the Burn path of every module of `model` that holds tensors with its readable path.
+/// Modules whose paths are already readable are left out.
+fn module_paths<B: Backend, M: Module<B>>(model: &M) -> Vec<(String, String)> {
+    let mut paths: Vec<(String, String)> = vec![];
+    for snapshot in model.collect(None, None, false) {
+        let (Some(path), Some(containers)) = (&snapshot.path_stack, &snapshot.container_stack)
+        else {
+            continue;
+        };
+        let module: Vec<_> = path[..path.len() - 1].iter().map(String::as_str).collect();
+        let (module, readable) = (prefix(&module), prefix(&readable_module(path, containers)));
+        if module != readable && !paths.iter().any(|(x, _)| *x == module) {
+            paths.push((module, readable));
+        }
+    }
+    paths
+}
+
+/// A tensor of a model, named both by its Burn path and by its readable name.
+pub(crate) struct TensorName {
+    pub id: ParamId,
+    /// Such as `encoder.model.conv.layers.0.0.weight`.
+    pub path: String,
+    /// Such as `encoder.conv.layers.0.weight`.
+    pub readable: String,
+}
+
+/// The names of every tensor of `model`.
+pub(crate) fn tensor_names<B: Backend, M: Module<B>>(model: &M) -> Vec<TensorName> {
+    model
+        .collect(None, None, false)
+        .into_iter()
+        .filter_map(|snapshot| {
+            let (Some(path), Some(containers), Some(id)) = (
+                &snapshot.path_stack,
+                &snapshot.container_stack,
+                snapshot.tensor_id,
+            ) else {
+                return None;
+            };
+            Some(TensorName {
+                id,
+                path: path.join("."),
+                readable: prefix(&readable_module(path, containers)) + path.last()?,
+            })
+        })
+        .collect()
+}
+
+/// Regex patterns that rename the tensors of the modules at the first path of each pair to the
+/// second path.
+fn module_patterns(
+    paths: impl IntoIterator<Item = (String, String)>,
+) -> impl Iterator<Item = (String, String)> {
+    paths
+        .into_iter()
+        .map(|(from, to)| (format!(r"^{}(\w+)$", escape(&from)), format!("{to}$1")))
+}
+
+/// Renames tensors with readable names to the Burn paths of `model`.
+pub(crate) fn load_patterns<B: Backend, M: Module<B>>(
+    model: &M,
+) -> impl Iterator<Item = (String, String)> {
+    module_patterns(
+        module_paths(model)
+            .into_iter()
+            .map(|(module, readable)| (readable, module)),
+    )
+}
+
+/// Saves the weights of `model` to a safetensors file with readable tensor names, overwriting
+/// any existing file.
+pub fn save_safetensors<B: Backend, M: Module<B>>(
+    model: &M,
+    path: impl AsRef<Path>,
+) -> Result<(), StoreError> {
+    let mut store = SafetensorsStore::from_file(path.as_ref())
+        .remap(
+            KeyRemapper::from_pattern_iter(module_patterns(module_paths(model)))
+                .expect("Expected escaped module paths to be valid regex"),
+        )
+        .overwrite(true);
+    model.save_into(&mut store)?;
+    Ok(())
+}
+
+/// Loads weights saved with [`save_safetensors`] into `model`. Every tensor of `model` must be
+/// in the file.
+pub fn load_safetensors<B: Backend, M: Module<B>>(
+    mut model: M,
+    path: impl AsRef<Path>,
+) -> Result<M, StoreError> {
+    let remapper = KeyRemapper::from_pattern_iter(load_patterns(&model))
+        .expect("Expected escaped module paths to be valid regex");
+    let mut store = SafetensorsStore::from_file(path.as_ref()).remap(remapper);
+    model.load_from(&mut store)?;
+    Ok(model)
+}
+
+/// Like [`save_safetensors`], but returns the contents of the file instead of writing it.
+pub fn save_safetensors_bytes<B: Backend, M: Module<B>>(model: &M) -> Result<Vec<u8>, StoreError> {
+    let mut store = SafetensorsStore::from_bytes(None).remap(
+        KeyRemapper::from_pattern_iter(module_patterns(module_paths(model)))
+            .expect("Expected escaped module paths to be valid regex"),
+    );
+    model.save_into(&mut store)?;
+    Ok(store.get_bytes()?)
+}
+
+/// Like [`load_safetensors`], but from the contents of a file. A tensor whose shape differs from
+/// the one in `model` is reported as [`LoadModelError::ShapeMismatch`].
+pub fn load_safetensors_bytes<B: Backend, M: Module<B>>(
+    mut model: M,
+    bytes: Vec<u8>,
+) -> Result<M, LoadModelError> {
+    let remapper = KeyRemapper::from_pattern_iter(load_patterns(&model))
+        .expect("Expected escaped module paths to be valid regex");
+    // Shape mismatches are checked here rather than by the store, which reports them as text
+    let mut store = SafetensorsStore::from_bytes(Some(bytes))
+        .remap(remapper)
+        .validate(false);
+    let result = model.load_from(&mut store).map_err(StoreError::from)?;
+    check_shapes(&result)?;
+    Ok(model)
+}
+
+/// Fails with the first tensor whose shape didn't match, if any.
+pub(crate) fn check_shapes(result: &ApplyResult) -> Result<(), LoadModelError> {
+    match result
+        .errors
+        .iter()
+        .find(|x| matches!(x, ApplyError::ShapeMismatch { .. }))
+    {
+        Some(ApplyError::ShapeMismatch {
+            path,
+            expected,
+            found,
+        }) => Err(LoadModelError::ShapeMismatch {
+            tensor: path.clone(),
+            expected: expected.clone(),
+            found: found.clone(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Loads a PyTorch state dict into `model`, from either a `.pt` file or a safetensors file.
+/// The tensors are renamed by `mapping` and then matched against the readable names of `model`.
+///
+/// Linear weights are transposed and the `weight` and `bias` of norms are used as their `gamma`
+/// and `beta`. Every tensor of `model` must be in the file, but the file may have tensors that
+/// `model` doesn't, such as the `num_batches_tracked` of batch norms.
+pub fn load_pytorch<B: Backend, M: Module<B>>(
+    mut model: M,
+    path: impl AsRef<Path>,
+    mapping: &PytorchNameMapping,
+) -> Result<M, StoreError> {
+    // The mapping goes first, so that the readable names it produces are then renamed
+    let remapper =
+        KeyRemapper::from_pattern_iter(mapping.names.iter().cloned().chain(load_patterns(&model)))
+            .map_err(|e| StoreError::InvalidMapping(e.to_string()))?;
+
+    let path = path.as_ref();
+    if path.extension().is_some_and(|x| x == "safetensors") {
+        let mut store = SafetensorsStore::from_file(path)
+            .with_from_adapter(PyTorchToBurnAdapter)
+            .remap(remapper);
+        model.load_from(&mut store)?;
+    } else {
+        let mut store = PytorchStore::from_file(path).remap(remapper);
+        if let Some(key) = &mapping.top_level_key {
+            store = store.with_top_level_key(key);
+        }
+        model.load_from(&mut store)?;
+    }
+    Ok(model)
+}
@@ -5,31 +5,30 @@ use std::{num::NonZeroUsize, path::Path};
 use burn::{
     Tensor,
     module::Module,
-    nn::{
-        interpolate::{Interpolate2dConfig, InterpolateMode},
-        loss::{MseLoss, Reduction},
-    },
+    nn::interpolate::{Interpolate2dConfig, InterpolateMode},
     prelude::Backend,
     record::{CompactRecorder, Recorder},
-    tensor::Bool,
 };
 use efficient_pca::PCA;
 use general_models::{
     Init, SimpleInfer,
+    bundle::Bundle,
     composite::{
         autoencoder::{AutoEncoderModel, AutoEncoderModelConfig},
         image::{
             Conv2dLinearModel, Conv2dLinearModelConfig, LinearConvTranspose2dModel,
-            LinearConvTranspose2dModelConfig,
+            LinearConvTranspose2dModelConfig, QuantizedConv2dLinearModel,
         },
     },
     error::LoadModelError,
+    quantize::quantize,
     wgpu::WgpuBackend,
 };
 use ndarray::{Array1, Array2};
 use utils::parse_json_file;
 
 pub use burn;
+pub use general_models::metrics::{psnr, psnr_batched, psnr_mask};
 pub use general_models::wgpu;
 
 use crate::pca::load_pca;
@@ -38,8 +37,13 @@ pub mod pca;
 
 const IMAGE_WIDTH: usize = 28;
 
+type DetectorConfig =
+    AutoEncoderModelConfig<Conv2dLinearModelConfig, LinearConvTranspose2dModelConfig>;
+
 pub struct Detector<B: Backend = WgpuBackend> {
     model: AutoEncoderModel<B, Conv2dLinearModel<B>, LinearConvTranspose2dModel<B>>,
+    /// Replaces the encoder once it is quantized.
+    quantized_encoder: Option<QuantizedConv2dLinearModel<B>>,
     // device: B::Device,
     pca: Option<PCA>,
     pub batch_size: NonZeroUsize,
@@ -66,20 +70,34 @@ impl<B: Backend> Detector<B> {
         encoder_weights: impl AsRef<Path>,
         device: &B::Device,
     ) -> Result<Self, LoadModelError> {
-        let autoencoder_config: AutoEncoderModelConfig<
-            Conv2dLinearModelConfig,
-            LinearConvTranspose2dModelConfig,
-        > = parse_json_file(autoencoder_config)?;
+        let autoencoder_config: DetectorConfig = parse_json_file(autoencoder_config)?;
         let mut model = autoencoder_config.init(device);
         let record = CompactRecorder::new().load(encoder_weights.as_ref().into(), device)?;
         model = model.load_record(record);
-        Ok(Self {
+        Ok(Self::new(model))
+    }
+
+    /// Loads the autoencoder from a bundle exported by `proximo export`, which holds its config
+    /// along with its weights.
+    pub fn load_bundle(
+        bundle: impl AsRef<Path>,
+        device: &B::Device,
+    ) -> Result<Self, LoadModelError> {
+        let (model, _) = Bundle::<DetectorConfig>::load(bundle, device)?;
+        Ok(Self::new(model))
+    }
+
+    fn new(
+        model: AutoEncoderModel<B, Conv2dLinearModel<B>, LinearConvTranspose2dModel<B>>,
+    ) -> Self {
+        Self {
             model,
+            quantized_encoder: None,
             pca: None,
             batch_size: NonZeroUsize::new(256).unwrap(),
             target_encodings: vec![],
             distance_falloff: 2.0, // device: device.clone(),
-        })
+        }
     }
 
     /// Encodes the given tensor by sliding the model across it like a kernel. There will be a kernel for each feature size.
@@ -146,7 +164,16 @@ impl<B: Backend> Detector<B> {
     }
 
     pub fn encode_tensor_batch_raw(&self, tensor: Tensor<B, 4>) -> Tensor<B, 2> {
-        self.model.encoder.infer(tensor)
+        match &self.quantized_encoder {
+            Some(encoder) => encoder.infer(tensor),
+            None => self.model.encoder.infer(tensor),
+        }
+    }
+
+    /// Quantizes the encoder to int8 for faster inference on the CPU, calibrating the ranges of
+    /// its activations on `batches`, which should look like the inputs it will encode.
+    pub fn quantize_encoder(&mut self, batches: impl IntoIterator<Item = Tensor<B, 4>>) {
+        self.quantized_encoder = Some(quantize(&self.model.encoder, batches));
     }
 
     pub fn load_pca(&mut self, file: impl AsRef<Path>) -> serde_json::Result<()> {
@@ -222,43 +249,6 @@ impl<B: Backend> Detector<B> {
     }
 }
 
-/// Peak Signal-to-Noise Ratio between two float tensors whose values are in [0, 1].
-///
-/// - \>40 dB: Excellent quality, differences barely perceptible
-/// - 30-40 dB: Good quality, acceptable for most applications
-/// - 20-30 dB: Fair quality, noticeable differences
-/// - <20 dB: Poor quality, significant degradation
-pub fn psnr<B: Backend<FloatElem = f32>, const N: usize>(a: Tensor<B, N>, b: Tensor<B, N>) -> f32 {
-    10.0 * (1.0 / MseLoss::new().forward(a, b, Reduction::Mean).into_scalar()).log10()
-}
-
-/// Peak Signal-to-Noise Ratio between two batched float tensors whose values are in [0, 1]. It is assumed
-/// that the first axis is the batch axis.
-///
-/// - \>40 dB: Excellent quality, differences barely perceptible
-/// - 30-40 dB: Good quality, acceptable for most applications
-/// - 20-30 dB: Fair quality, noticeable differences
-/// - <20 dB: Poor quality, significant degradation
-pub fn psnr_batched<B: Backend<FloatElem = f32>, const N: usize>(
-    a: Tensor<B, N>,
-    b: Tensor<B, N>,
-) -> Tensor<B, 1> {
-    assert_eq!(a.shape(), b.shape(), "The shapes of a and b are different");
-    let batch_size = a.dims()[0];
-    let c = a
-        .sub(b)
-        .square()
-        .mean_dims((1..N).into_iter().collect::<Vec<_>>().as_slice())
-        .reshape([batch_size]);
-    let device = c.device();
-
-    c.recip().log() / (Tensor::ones([batch_size], &device) * 10.0).log() * 10.0
-}
-
-pub fn psnr_mask<B: Backend>(psnr_level: Tensor<B, 1>, min_level: f32) -> Tensor<B, 1, Bool> {
-    psnr_level.greater_elem(min_level)
-}
-
 // pub type VaeModel<B> = VariationalEncoderModel<B, Conv2dLinearModel<B>>;
 // pub type VaeConfig = VariationalEncoderModelConfig<Conv2dLinearModelConfig>;
 // pub type VaeRecord<B> = VariationalEncoderModelRecord<B, Conv2dLinearModelRecord<B>>;
@@ -0,0 +1,6 @@
+SELECT 
+    row_id, 
+    sequence, 
+    target 
+FROM train
+WHERE row_id BETWEEN ?1 AND (?1 + ?2 - 1)
\ No newline at end of file
@@ -0,0 +1,9 @@
+SELECT 
+    test.row_id, 
+    test.label, 
+    images.webp, 
+    images.width, 
+    images.height 
+FROM test
+INNER JOIN images ON test.input = images.row_id
+WHERE test.row_id BETWEEN ?1 AND (?1 + ?2 - 1)
\ No newline at end of file
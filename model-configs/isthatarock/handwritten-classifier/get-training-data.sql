@@ -0,0 +1,9 @@
+SELECT 
+    train.row_id, 
+    train.label, 
+    images.webp, 
+    images.width, 
+    images.height 
+FROM train
+INNER JOIN images ON train.input = images.row_id
+WHERE train.row_id BETWEEN ?1 AND (?1 + ?2 - 1)
\ No newline at end of file
@@ -1,51 +1,60 @@
-use std::{io::Cursor, process::Stdio, sync::atomic::AtomicBool, time::SystemTime};
-
-use base64::{Engine, prelude::BASE64_STANDARD};
-use burn::{
-    module::{AutodiffModule, DisplaySettings, Module, ModuleDisplay},
-    prelude::Backend,
-    record::CompactRecorder,
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::atomic::AtomicBool,
+    time::SystemTime,
 };
-use clap::{Parser, Subcommand};
+
+use burn::prelude::Backend;
+use clap::{Parser, Subcommand, ValueEnum};
 use general_dataset::{
     SqliteDataset, StatefulBatcher,
-    presets::autoencoder::{AutoEncoderImageBatch, AutoEncoderImageBatcher, AutoEncoderImageItem},
+    presets::{
+        autoencoder::{
+            AutoEncoderImageBatch, AutoEncoderImageBatcher, AutoEncoderImageItem,
+            LabeledAutoEncoderImageItem,
+        },
+        classifier::{ImageClassifierBatch, ImageClassifierBatcher, ImageClassifierItem},
+        sequence::SequenceItem,
+    },
 };
-use general_models::{Init, SimpleInfer, SimpleTrain, loss::bce_float_loss};
-use image::{
-    ImageBuffer, ImageDecoder, ImageFormat, Luma, Rgb, buffer::ConvertBuffer,
-    codecs::webp::WebPDecoder,
+use general_models::{
+    Init, SimpleInfer,
+    bundle::{Bundle, config_hash},
+    error::{ExportError, StoreError},
+    metrics::ClassificationStats,
+    onnx::export_onnx,
+    quantize::quantize,
+    summary::ModelSummary,
+    weights::save_safetensors,
 };
-use rand::{Rng, SeedableRng, rngs::SmallRng};
-use rayon::iter::{IndexedParallelIterator, ParallelIterator};
-use serde_json::json;
+use rand::{Rng, SeedableRng, rngs::SmallRng, seq::SliceRandom};
+use serde::Serialize;
 use tracing::info;
 use utils::parse_json_file;
 
-use crate::{
-    app::{
-        config::{ImageAutoEncoderChallenge, ModelType, TrainingConfig, TrainingGradsPlanConfig},
-        presets::autoencoders::{
-            ImageAutoEncoder, ImageAutoEncoderConfig, ImageAutoEncoderPlan,
-            ImageAutoEncoderPlanConfig,
-        },
+use crate::app::{
+    checkpoint::TrainingState,
+    config::{ImageClassifierValidation, ModelType, TrainingConfig},
+    presets::{
+        autoencoders::{ImageAutoEncoder, ImageAutoEncoderConfig},
+        classifiers::{ImageClassifier, ImageClassifierConfig},
+        regressors::{SequenceRegressor, SequenceRegressorConfig},
     },
-    trainable_models::{
-        AdHocLossModel,
-        apply_gradients::{AdHocTrainingPlan, AdHocTrainingPlanConfig, ApplyGradients},
-        vae::sample_vae,
+    training::{
+        Checkpoint, TrainingRun, Viz, autoencoder::ImageAutoEncoderTask,
+        classifier::ImageClassifierTask, regressor::SequenceRegressorTask, run_epochs,
     },
-    training_loop::{train_epoch, validate_model},
 };
 
-use rayon::iter::ParallelDrainRange;
-
 #[cfg(feature = "dhat-heap")]
 #[global_allocator]
 static ALLOC: dhat::Alloc = dhat::Alloc;
 
+pub mod checkpoint;
 pub mod config;
 pub mod presets;
+pub mod training;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -56,358 +65,555 @@ struct Args {
 
 #[derive(Debug, Subcommand)]
 enum Command {
-    Train,
+    Train {
+        /// The backend to train on, which defaults to the first GPU backend that is compiled in
+        #[arg(short, long, value_enum, default_value_t)]
+        backend: TrainingBackend,
+    },
+    /// Continues an interrupted training run from the last checkpoint in its artifact dir, with
+    /// the configs it was started with. Relative paths in them are resolved against the current
+    /// directory, so it should be the one that training was started in.
+    Resume {
+        /// The artifact dir of the run, such as `artifacts/1700000000`
+        artifact_dir: PathBuf,
+        /// The backend to train on, which defaults to the first GPU backend that is compiled in
+        #[arg(short, long, value_enum, default_value_t)]
+        backend: TrainingBackend,
+    },
     Clean,
+    /// Prints a summary of the model without training it
+    Summary,
+    /// Exports a checkpoint of the model to ONNX, to safetensors, or to a bundle with its config
+    Export {
+        /// A checkpoint saved during training, such as `model-9.mpk` in an artifact dir, or
+        /// exported safetensors or a bundle
+        checkpoint: PathBuf,
+        /// Where to write the ONNX model, the weights if it ends in `.safetensors`, or a bundle of
+        /// the config, weights and training config hash if it ends in `.bundle`
+        #[arg(short, long, default_value = "model.onnx")]
+        output: PathBuf,
+    },
+    /// Quantizes a checkpoint of the model to int8 and reports how much worse it does on the
+    /// testing dataset
+    Quantize {
+        /// A checkpoint saved during training, such as `model-9.mpk` in an artifact dir, or
+        /// exported safetensors or a bundle
+        checkpoint: PathBuf,
+        /// The number of random batches of the training dataset to calibrate the ranges of the
+        /// activations on
+        #[arg(short, long, default_value_t = 8)]
+        calibration_batch_count: usize,
+    },
 }
 
-pub fn train() {
-    let clock = quanta::Clock::new();
-    let init_start_time = clock.now();
+/// The backends that proximo was compiled with, from the most preferred. The CPU is always
+/// available, so that models can be trained without a GPU.
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum TrainingBackend {
+    #[cfg(feature = "cuda")]
+    Cuda,
+    #[cfg(feature = "rocm")]
+    Rocm,
+    #[cfg(feature = "wgpu")]
+    Wgpu,
+    Cpu,
+}
 
-    let ctrlc_pressed: &_ = Box::leak(Box::new(AtomicBool::new(false)));
+impl Default for TrainingBackend {
+    fn default() -> Self {
+        Self::value_variants()[0]
+    }
+}
 
-    ctrlc::set_handler(move || {
-        ctrlc_pressed.store(true, std::sync::atomic::Ordering::Relaxed);
-        println!("Cancelling due to Ctrl-C ...");
-    })
-    .expect("Error setting Ctrl-C handler");
+/// Summarizes `model_config` on the first image of `dataset`, checking that the model reproduces
+/// the shape of the expected image.
+fn image_autoencoder_summary(
+    model_config: &ImageAutoEncoderConfig,
+    dataset: &SqliteDataset,
+) -> ModelSummary {
+    let item: AutoEncoderImageItem = dataset.get(0);
+    let channels = model_config.get_input_channels();
+    let summary = ModelSummary::new(
+        model_config,
+        &[channels, item.input_width, item.input_height],
+    )
+    .unwrap_or_else(|e| panic!("Invalid model.json: {e}"));
+    assert_eq!(
+        summary.output_shape,
+        [channels, item.expected_width, item.expected_height],
+        "Expected the output of model.json to match the expected images"
+    );
+    summary
+}
 
-    #[cfg(feature = "wgpu")]
-    type Backend = general_models::wgpu::WgpuBackend;
+/// Summarizes `model_config` on the first image of `dataset`, checking that the model outputs one
+/// score per class.
+fn image_classifier_summary(
+    model_config: &ImageClassifierConfig,
+    dataset: &SqliteDataset,
+) -> ModelSummary {
+    let item: ImageClassifierItem = dataset.get(0);
+    let summary = ModelSummary::new(
+        model_config,
+        &[
+            model_config.model.conv.input_channels,
+            item.width,
+            item.height,
+        ],
+    )
+    .unwrap_or_else(|e| panic!("Invalid model.json: {e}"));
+    expect_labels_below(dataset, summary.output_shape[0]);
+    summary
+}
 
-    #[cfg(feature = "rocm")]
-    type Backend = general_models::rocm::RocmBackend;
+/// The highest label of the items given to it, for [`expect_labels_below`].
+struct MaxLabel(Option<usize>);
 
-    #[cfg(feature = "cuda")]
-    type Backend = general_models::cuda::CudaBackend;
+impl StatefulBatcher<ImageClassifierItem, Option<usize>> for MaxLabel {
+    fn reset(&mut self) {
+        self.0 = None;
+    }
 
-    type AutodiffBackend = burn::backend::Autodiff<Backend>;
+    fn ingest(&mut self, item: ImageClassifierItem) {
+        self.0 = self.0.max(Some(item.label));
+    }
 
-    #[cfg(feature = "wgpu")]
-    let device = general_models::wgpu::get_device();
+    fn finish(&mut self) -> Option<usize> {
+        self.0
+    }
+}
 
-    #[cfg(feature = "rocm")]
-    let device = general_models::rocm::get_device();
+/// Checks that every label of `dataset` has a score among the `num_classes` outputs of the model,
+/// so that a mismatched model.json fails before training rather than once the label comes up.
+fn expect_labels_below(dataset: &SqliteDataset, num_classes: usize) {
+    const BATCH_SIZE: usize = 1024;
+    let max_label = (0..dataset.get_batch_count(BATCH_SIZE))
+        .filter_map(|i| dataset.query(i * BATCH_SIZE, BATCH_SIZE, MaxLabel(None)))
+        .max();
+    if let Some(max_label) = max_label {
+        assert!(
+            max_label < num_classes,
+            "Expected the {num_classes} outputs of model.json to cover every label of the \
+             dataset, got label {max_label}"
+        );
+    }
+}
 
-    #[cfg(feature = "cuda")]
-    let device = general_models::cuda::get_device();
+/// Summarizes `model_config` on the first sequence of `dataset`, checking that the model
+/// reproduces the size of the target vector.
+fn sequence_regressor_summary(
+    model_config: &SequenceRegressorConfig,
+    dataset: &SqliteDataset,
+) -> ModelSummary {
+    let item: SequenceItem = dataset.get(0);
+    let features = model_config.gru.input_size;
+    let seq_len = item.sequence.len() / size_of::<f32>() / features;
+    let summary = ModelSummary::new(model_config, &[seq_len, features])
+        .unwrap_or_else(|e| panic!("Invalid model.json: {e}"));
+    assert_eq!(
+        summary.output_shape,
+        [item.target.len() / size_of::<f32>()],
+        "Expected the output of model.json to match the targets"
+    );
+    summary
+}
+
+fn write_summary(artifact_dir: &Path, summary: &ModelSummary) {
+    info!(
+        "Model has {} params and takes {} MACs per input",
+        summary.total_params, summary.total_macs
+    );
+    std::fs::write(artifact_dir.join("model-summary.txt"), summary.to_string())
+        .expect("Expected model-summary.txt to be writable in artifact dir");
+    std::fs::write(
+        artifact_dir.join("model-summary.json"),
+        serde_json::to_string_pretty(summary).unwrap(),
+    )
+    .expect("Expected model-summary.json to be writable in artifact dir");
+}
+
+/// A bundle of the model of `model_config`, with the hash of training.json.
+fn training_bundle(
+    model_config: &impl Serialize,
+    model_type: ModelType,
+) -> Bundle<serde_json::Value> {
+    let training_config: serde_json::Value =
+        parse_json_file("training").expect("Expected valid training.json");
+    let model_config =
+        serde_json::to_value(model_config).expect("Expected model config to be serializable");
+    Bundle::new(model_config)
+        .with_training_config_hash(config_hash(&training_config))
+        .with_metadata("model_type", format!("{model_type:?}"))
+}
+
+/// Exports the model at `checkpoint` to ONNX, with the input shape of the first item of the
+/// training dataset, to safetensors if `output` ends in `.safetensors`, or to a bundle if it ends
+/// in `.bundle`.
+fn export(checkpoint: &Path, output: &Path) {
+    // Exporting only reads the weights, so it doesn't need the training backend
+    type Backend = burn::backend::NdArray;
+    let device = Default::default();
 
     let training_config: TrainingConfig =
         parse_json_file("training").expect("Expected valid training.json");
+    let training_dataset: SqliteDataset = training_config
+        .training_dataset
+        .try_into()
+        .expect("Expected valid training dataset config");
 
-    let secs = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
+    let to_safetensors = output.extension().is_some_and(|x| x == "safetensors");
+    let to_bundle = output.extension().is_some_and(|x| x == "bundle");
+    let model_type = training_config.model_type;
+    let save = |result: Result<(), StoreError>| {
+        result.unwrap_or_else(|e| panic!("Could not save the weights: {e}"));
+        None
+    };
 
-    let artifact_dir = training_config.artifact_dir.join(secs.to_string());
-    std::fs::create_dir_all(&artifact_dir).expect("Expected artifact dir to be creatable");
+    let onnx = match training_config.model_type {
+        ModelType::ImageAutoEncoder => {
+            let model_config: ImageAutoEncoderConfig =
+                parse_json_file("model").expect("Expected valid model.json");
+            let summary = image_autoencoder_summary(&model_config, &training_dataset);
+            let bundle = to_bundle.then(|| training_bundle(&model_config, model_type));
+            let model: ImageAutoEncoder<Backend> = model_config.init(&device);
+            let model = model
+                .load_checkpoint(checkpoint, &device)
+                .unwrap_or_else(|e| panic!("Expected checkpoint to match model.json: {e}"));
+            if to_safetensors {
+                save(save_safetensors(&model, output))
+            } else if let Some(bundle) = bundle {
+                save(bundle.save(&model, output))
+            } else {
+                Some(model.export_onnx(&summary.input_shape))
+            }
+        }
+        ModelType::ImageClassifier => {
+            let model_config: ImageClassifierConfig =
+                parse_json_file("model").expect("Expected valid model.json");
+            let summary = image_classifier_summary(&model_config, &training_dataset);
+            let bundle = to_bundle.then(|| training_bundle(&model_config, model_type));
+            let model: ImageClassifier<Backend> = model_config.init(&device);
+            let model = model
+                .load_checkpoint(checkpoint, &device)
+                .unwrap_or_else(|e| panic!("Expected checkpoint to match model.json: {e}"));
+            if to_safetensors {
+                save(save_safetensors(&model, output))
+            } else if let Some(bundle) = bundle {
+                save(bundle.save(&model, output))
+            } else {
+                Some(export_onnx(&model, &summary.input_shape))
+            }
+        }
+        ModelType::SequenceRegressor if to_safetensors || to_bundle => {
+            let model_config: SequenceRegressorConfig =
+                parse_json_file("model").expect("Expected valid model.json");
+            let bundle = to_bundle.then(|| training_bundle(&model_config, model_type));
+            let model: SequenceRegressor<Backend> = model_config.init(&device);
+            let model = model
+                .load_checkpoint(checkpoint, &device)
+                .unwrap_or_else(|e| panic!("Expected checkpoint to match model.json: {e}"));
+            match bundle {
+                Some(bundle) => save(bundle.save(&model, output)),
+                None => save(save_safetensors(&model, output)),
+            }
+        }
+        ModelType::SequenceRegressor => Some(Err(ExportError::Unsupported("A GRU".into()))),
+    };
+    if let Some(onnx) = onnx {
+        let onnx = onnx.unwrap_or_else(|e| panic!("Could not export the model: {e}"));
+        std::fs::write(output, onnx).expect("Expected ONNX output to be writable");
+    }
+    info!("Exported {} to {}", checkpoint.display(), output.display());
+}
 
-    let mut training_dataset: SqliteDataset = training_config
+/// Picks up to `count` random batches of `dataset`, returning the index of the first item of
+/// each.
+fn random_batch_indices(
+    dataset: &SqliteDataset,
+    batch_size: usize,
+    count: usize,
+    rng: &mut impl Rng,
+) -> Vec<usize> {
+    let mut indices: Vec<_> = (0..dataset.get_batch_count(batch_size))
+        .map(|x| x * batch_size)
+        .collect();
+    indices.shuffle(rng);
+    indices.truncate(count);
+    indices
+}
+
+/// Quantizes the model at `checkpoint` to int8, calibrated on random batches of the training
+/// dataset, and logs the accuracy or PSNR of both the f32 and the quantized model on the testing
+/// dataset.
+fn quantize_checkpoint(checkpoint: &Path, calibration_batch_count: usize) {
+    // Quantized models are meant for inference on the CPU
+    type Backend = burn::backend::NdArray;
+    let device = Default::default();
+
+    let training_config: TrainingConfig =
+        parse_json_file("training").expect("Expected valid training.json");
+    let training_dataset: SqliteDataset = training_config
         .training_dataset
         .try_into()
         .expect("Expected valid training dataset config");
-
-    let mut testing_dataset: SqliteDataset = training_config
+    let testing_dataset: SqliteDataset = training_config
         .testing_dataset
         .try_into()
-        .expect("Expected valid training dataset config");
-    let mut lr_scheduler = training_config.lr_scheduler.init();
-
-    let mut rng = SmallRng::seed_from_u64(training_config.seed.unwrap_or(secs));
-    Backend::seed(device, rng.random());
-
-    let mut viz_command = training_config.viz_command.into_iter();
-    let mut child = viz_command.next().map(|cmd| {
-        std::process::Command::new(cmd)
-            .args(viz_command)
-            .env("ARTIFACT_DIR", &artifact_dir)
-            .env("TRAINING_BATCH_COUNT", &artifact_dir)
-            .stdin(Stdio::piped())
-            .spawn()
-            .expect("Expected valid viz command")
-    });
+        .expect("Expected valid testing dataset config");
+    let batch_size = training_config.batch_size;
+    let mut rng = SmallRng::seed_from_u64(training_config.seed.unwrap_or_default());
+    let calibration_indices = random_batch_indices(
+        &training_dataset,
+        batch_size,
+        calibration_batch_count,
+        &mut rng,
+    );
+    let testing_indices = random_batch_indices(
+        &testing_dataset,
+        batch_size,
+        training_config.testing_max_batch_count,
+        &mut rng,
+    );
 
     match training_config.model_type {
         ModelType::ImageAutoEncoder => {
-            let challenge_config: ImageAutoEncoderChallenge =
-                parse_json_file("training").expect("Expected valid training.json");
             let model_config: ImageAutoEncoderConfig =
                 parse_json_file("model").expect("Expected valid model.json");
-            type AutodiffModel = ImageAutoEncoder<AutodiffBackend>;
-            type Model = ImageAutoEncoder<Backend>;
-            let mut model: AutodiffModel = model_config.init(device);
-            std::fs::write(
-                artifact_dir.join("model.txt"),
-                model.format(DisplaySettings::new()).as_bytes(),
-            )
-            .expect("Expected model.txt to be writable in artifact dir");
-
-            let grads_plan: TrainingGradsPlanConfig<
-                AdHocTrainingPlanConfig<ImageAutoEncoderPlanConfig>,
-            > = parse_json_file("training").expect("Expected valid training.json");
-            let mut grads_plan = AdHocLossModel::<_, ()>::config_to_plan(grads_plan.grads_plan);
-
-            let mut training_batcher = AutoEncoderImageBatcher::<AutodiffBackend>::new(
-                model.get_input_channels(),
-                device.clone(),
+            let model: ImageAutoEncoder<Backend> = model_config.init(&device);
+            let model = model
+                .load_checkpoint(checkpoint, &device)
+                .unwrap_or_else(|e| panic!("Expected checkpoint to match model.json: {e}"));
+            let ImageAutoEncoder::Normal(model) = model else {
+                panic!(
+                    "Only autoencoders without a variational or quantized latent can be quantized"
+                );
+            };
+            let mut batcher =
+                AutoEncoderImageBatcher::<Backend>::new(model.encoder.get_input_channels(), device);
+            let mut query =
+                |dataset: &SqliteDataset, index: usize| -> AutoEncoderImageBatch<Backend> {
+                    dataset.query::<LabeledAutoEncoderImageItem, _>(index, batch_size, &mut batcher)
+                };
+
+            let quantized = quantize(
+                &model,
+                calibration_indices
+                    .iter()
+                    .map(|&index| query(&training_dataset, index).input),
+            );
+
+            // PSNR over the mean squared error of all the tested pixels, which are in [0, 1], so
+            // that a smaller last batch doesn't weigh as much as a full one
+            let (mut squared_error, mut quantized_squared_error, mut count) = (0.0, 0.0, 0);
+            for &index in &testing_indices {
+                let batch = query(&testing_dataset, index);
+                let sum_squared_error = |output: burn::Tensor<Backend, 4>| -> f64 {
+                    (output - batch.expected.clone())
+                        .square()
+                        .sum()
+                        .into_scalar()
+                        .into()
+                };
+                squared_error += sum_squared_error(model.infer(batch.input.clone()));
+                quantized_squared_error += sum_squared_error(quantized.infer(batch.input.clone()));
+                count += batch.expected.shape().num_elements();
+            }
+            let psnr = |squared_error: f64| 10.0 * (count as f64 / squared_error).log10();
+            let (psnr, quantized_psnr) = (psnr(squared_error), psnr(quantized_squared_error));
+            info!(
+                "PSNR: {psnr:.2} dB; Quantized PSNR: {quantized_psnr:.2} dB; Drop: {:.2} dB",
+                psnr - quantized_psnr
+            );
+        }
+        ModelType::ImageClassifier => {
+            let validation_config: ImageClassifierValidation =
+                parse_json_file("training").expect("Expected valid training.json");
+            let model_config: ImageClassifierConfig =
+                parse_json_file("model").expect("Expected valid model.json");
+            let model: ImageClassifier<Backend> = model_config.init(&device);
+            let model = model
+                .load_checkpoint(checkpoint, &device)
+                .unwrap_or_else(|e| panic!("Expected checkpoint to match model.json: {e}"));
+            let num_classes = model.model.get_output_size();
+            let mut batcher = ImageClassifierBatcher::<Backend>::new(
+                model.model.get_input_channels(),
+                num_classes,
+                device,
             );
-            let mut testing_batcher =
-                AutoEncoderImageBatcher::<Backend>::new(model.get_input_channels(), device.clone());
+            let mut query =
+                |dataset: &SqliteDataset, index: usize| -> ImageClassifierBatch<Backend> {
+                    dataset.query::<ImageClassifierItem, _>(index, batch_size, &mut batcher)
+                };
 
-            let mut input_images = vec![];
+            let quantized = quantize(
+                &model,
+                calibration_indices
+                    .iter()
+                    .map(|&index| query(&training_dataset, index).input),
+            );
 
+            let mut stats = ClassificationStats::new(num_classes, validation_config.top_k);
+            let mut quantized_stats =
+                ClassificationStats::new(num_classes, validation_config.top_k);
+            for &index in &testing_indices {
+                let batch = query(&testing_dataset, index);
+                stats.update(model.logits(batch.input.clone()), batch.labels.clone());
+                quantized_stats.update(quantized.logits(batch.input), batch.labels);
+            }
+            let top_k = stats.get_top_k();
             info!(
-                "Initialized in {:.3}s",
-                init_start_time.elapsed().as_secs_f32()
+                "Accuracy: {:.2}%; Quantized Accuracy: {:.2}%; Drop: {:.2}%",
+                stats.accuracy() * 100.0,
+                quantized_stats.accuracy() * 100.0,
+                (stats.accuracy() - quantized_stats.accuracy()) * 100.0
             );
-            let training_start_time = clock.now();
-            for epoch in 0..training_config.num_epochs {
-                let epoch_start_time = clock.now();
-                let mut batch_i = 0usize;
-
-                training_dataset.shuffle();
-
-                info!("Training Epoch {epoch}");
-
-                let mut trainable_model = AdHocLossModel::new(
-                    model,
-                    |model: &AutodiffModel,
-                     item: AutoEncoderImageBatch<AutodiffBackend>,
-                     plan: &AdHocTrainingPlan<
-                        AutodiffBackend,
-                        ImageAutoEncoder<AutodiffBackend>,
-                    >| {
-                        // item.input = item.input.sub_scalar(0.5);
-                        match model {
-                            ImageAutoEncoder::Normal(model) => {
-                                bce_float_loss(item.expected, model.train(item.input))
-                                // MseLoss::new().forward(
-                                //     model.train(item.input),
-                                //     item.expected,
-                                //     Reduction::Auto,
-                                // )
-                            }
-                            ImageAutoEncoder::Vae(model) => {
-                                let ImageAutoEncoderPlan::Vae(plan) =
-                                    plan.plan().expect("Expected VAE grads plan")
-                                else {
-                                    panic!("Incorrect grads plan");
-                                };
-                                let (mut reconstructed, mut kld) = sample_vae(model, item.input);
-                                reconstructed = reconstructed;
-                                kld = kld * plan.encoder().get_kld_weight();
-                                bce_float_loss(item.expected, reconstructed) + kld
-                                // bce_float_loss(item.expected, reconstructed) + kld
-                            }
-                        }
-                    },
-                );
+            info!(
+                "Top-{top_k} Accuracy: {:.2}%; Quantized Top-{top_k} Accuracy: {:.2}%",
+                stats.top_k_accuracy() * 100.0,
+                quantized_stats.top_k_accuracy() * 100.0
+            );
+        }
+        ModelType::SequenceRegressor => panic!("Recurrent models can't be quantized"),
+    }
+}
 
-                trainable_model = train_epoch::<AutodiffBackend, _, _, _>(
-                    trainable_model,
-                    &mut training_dataset,
-                    training_config.batch_size,
-                    training_config.training_max_batch_count,
-                    &mut training_batcher,
-                    &mut lr_scheduler,
-                    &mut grads_plan,
-                    &mut rng,
-                    device,
-                    |loss, lr| {
-                        let ctrlc_pressed =
-                            ctrlc_pressed.load(std::sync::atomic::Ordering::Relaxed);
-                        let loss = loss.into_scalar();
-                        if let Some(child) = &mut child {
-                            let result = serde_json::to_writer(
-                                child.stdin.as_mut().unwrap(),
-                                &json!({
-                                    "batch_i": batch_i,
-                                    "epoch": epoch,
-                                    "loss": loss,
-                                    "lr": lr
-                                }),
-                            );
-                            if !ctrlc_pressed {
-                                result.expect("Expected child process to be alive");
-                            }
-                        } else {
-                            info!("Batch {batch_i}; Loss: {loss:.4}; LR: {lr:.4}");
-                        }
-                        batch_i += 1;
-                        ctrlc_pressed
-                    },
-                );
+/// Copies training.json and model.json to the artifact dir of a new run, so that it can be resumed
+/// with the same configs.
+fn copy_configs(artifact_dir: &Path) {
+    for name in ["training", "model"] {
+        let config: serde_json::Value =
+            parse_json_file(name).unwrap_or_else(|e| panic!("Expected valid {name}.json: {e}"));
+        std::fs::write(
+            artifact_dir.join(format!("{name}.json")),
+            serde_json::to_vec_pretty(&config).expect("Expected config to be serializable"),
+        )
+        .unwrap_or_else(|e| panic!("Expected {name}.json to be writable in artifact dir: {e}"));
+    }
+}
 
-                model = trainable_model.unwrap();
-
-                match &model {
-                    ImageAutoEncoder::Normal(model) => {
-                        model
-                            .clone()
-                            .save_file(
-                                artifact_dir.join(format!("model-{epoch}.mpk")),
-                                &CompactRecorder::new(),
-                            )
-                            .expect("Expected model to be saveable to artifact dir");
-                    }
-                    ImageAutoEncoder::Vae(model) => {
-                        model
-                            .clone()
-                            .save_file(
-                                artifact_dir.join(format!("model-{epoch}.mpk")),
-                                &CompactRecorder::new(),
-                            )
-                            .expect("Expected model to be saveable to artifact dir");
-                    }
-                }
+/// Trains a new model with training.json and model.json, or continues the run in the artifact
+/// dir of `resume`.
+pub fn train(backend: TrainingBackend, resume: Option<&Path>) {
+    match backend {
+        #[cfg(feature = "cuda")]
+        TrainingBackend::Cuda => train_on::<general_models::cuda::CudaBackend>(
+            general_models::cuda::get_device(),
+            resume,
+        ),
+        #[cfg(feature = "rocm")]
+        TrainingBackend::Rocm => train_on::<general_models::rocm::RocmBackend>(
+            general_models::rocm::get_device(),
+            resume,
+        ),
+        #[cfg(feature = "wgpu")]
+        TrainingBackend::Wgpu => train_on::<general_models::wgpu::WgpuBackend>(
+            general_models::wgpu::get_device(),
+            resume,
+        ),
+        TrainingBackend::Cpu => train_on::<general_models::cpu::NdArrayBackend>(
+            general_models::cpu::get_device(),
+            resume,
+        ),
+    }
+}
 
-                testing_batcher.reset();
-                let mut output_width = 0usize;
-                let mut output_height = 0usize;
-                for _ in 0..challenge_config.challenge_image_count {
-                    let item: AutoEncoderImageItem = testing_dataset.pick_random(&mut rng);
-                    output_width = item.expected_width;
-                    output_height = item.expected_height;
-                    input_images.push(item.webp_input.clone());
-                    testing_batcher.ingest(item);
-                }
-                let batch = testing_batcher.finish();
-                let model: Model = model.valid();
-                let reconstructed = model.infer(batch.input.clone());
-
-                let reconstructed_images: Vec<_> = match model.get_input_channels() {
-                    1 => reconstructed
-                        .iter_dim(0)
-                        .map(|tensor| {
-                            let [_, _, width, height] = tensor.dims();
-                            let buf = tensor.into_data().into_vec::<f32>().unwrap();
-                            let img = ImageBuffer::<Luma<f32>, _>::from_raw(
-                                width as u32,
-                                height as u32,
-                                buf,
-                            )
-                            .unwrap();
-                            let img: ImageBuffer<Rgb<u8>, Vec<_>> = img.convert();
-
-                            img
-                        })
-                        .collect(),
-                    _ => todo!(),
-                };
+fn train_on<B: Backend>(device: &'static B::Device, resume: Option<&Path>) {
+    let clock = quanta::Clock::new();
+    let init_start_time = clock.now();
 
-                if let Some(child) = &mut child {
-                    let images: Vec<_> = input_images
-                        .par_drain(..)
-                        .zip(reconstructed_images)
-                        .map(|(input, output)| {
-                            let mut output_bytes = vec![];
-                            output
-                                .write_to(&mut Cursor::new(&mut output_bytes), ImageFormat::WebP)
-                                .unwrap();
-                            (
-                                BASE64_STANDARD.encode(input),
-                                BASE64_STANDARD.encode(output_bytes),
-                            )
-                        })
-                        .collect();
-                    serde_json::to_writer(
-                        child.stdin.as_mut().unwrap(),
-                        &json!({
-                            "epoch": epoch,
-                            "challenge_images": images,
-                        }),
-                    )
-                    .expect("Expected child process to be alive");
-                } else {
-                    let mosaic_width = output_width as u32 * 2;
-                    let mosaic_height =
-                        output_height as u32 * challenge_config.challenge_image_count as u32;
-                    let mut pixels =
-                        Vec::with_capacity(mosaic_width as usize * mosaic_height as usize);
-                    let mut input_buf = vec![];
-                    input_buf.resize(output_width * output_height * 3, 0);
-
-                    input_images
-                        .iter()
-                        .zip(reconstructed_images.iter())
-                        .for_each(|(input, output)| {
-                            WebPDecoder::new(Cursor::new(input))
-                                .unwrap()
-                                .read_image(&mut input_buf)
-                                .unwrap();
-                            let iter = input_buf
-                                .chunks(output_width * 3)
-                                .zip(output.chunks(output_width * 3));
-                            for (input_row, output_row) in iter {
-                                pixels.extend_from_slice(input_row);
-                                pixels.extend_from_slice(output_row);
-                            }
-                        });
-                    input_images.clear();
-                    ImageBuffer::<Rgb<u8>, _>::from_raw(mosaic_width, mosaic_height, pixels)
-                        .unwrap()
-                        .save(artifact_dir.join(format!("infer-{epoch}.webp")))
-                        .expect("Expected inference image to be saveable");
-                }
+    let ctrlc_pressed: &_ = Box::leak(Box::new(AtomicBool::new(false)));
 
-                if ctrlc_pressed.load(std::sync::atomic::Ordering::Relaxed) {
-                    break;
-                }
+    ctrlc::set_handler(move || {
+        ctrlc_pressed.store(true, std::sync::atomic::Ordering::Relaxed);
+        println!("Cancelling due to Ctrl-C ...");
+    })
+    .expect("Error setting Ctrl-C handler");
 
-                let mut validatable_model = AdHocLossModel::new(
-                    model,
-                    |model: &Model, item: AutoEncoderImageBatch<Backend>| {
-                        bce_float_loss(model.infer(item.input), item.expected)
-                    },
-                );
+    // A resumed run uses the configs that were copied to its artifact dir, which the current ones
+    // may have diverged from
+    let config_dir = resume.unwrap_or(Path::new(""));
+    let training_config: TrainingConfig =
+        parse_json_file(config_dir.join("training")).expect("Expected valid training.json");
 
-                info!("Testing Epoch {epoch}");
-                batch_i = 0;
-                validate_model::<Backend, _, _, _>(
-                    &mut validatable_model,
-                    &mut testing_dataset,
-                    training_config.batch_size,
-                    training_config.testing_max_batch_count,
-                    &mut testing_batcher,
-                    &mut rng,
-                    |loss| {
-                        let ctrlc_pressed =
-                            ctrlc_pressed.load(std::sync::atomic::Ordering::Relaxed);
-                        let loss = loss.into_scalar();
-                        if let Some(child) = &mut child {
-                            let result = serde_json::to_writer(
-                                child.stdin.as_mut().unwrap(),
-                                &json!({
-                                    "batch_i": batch_i,
-                                    "epoch": epoch,
-                                    "loss": loss,
-                                }),
-                            );
-                            if !ctrlc_pressed {
-                                result.expect("Expected child process to be alive");
-                            }
-                        } else {
-                            info!("Batch {batch_i}; Loss: {loss:.4}");
-                        }
-                        batch_i += 1;
-                        ctrlc_pressed
-                    },
-                );
-                let epoch_duration = epoch_start_time.elapsed();
-                info!(
-                    "Epoch Duration: {:.1}s; Remaining: {:.1}s",
-                    epoch_duration.as_secs_f32(),
-                    training_start_time.elapsed().as_secs_f32()
-                        * (training_config.num_epochs as f32 / (epoch + 1) as f32 - 1.0)
-                );
-            }
+    let secs = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
 
+    assert!(
+        training_config
+            .checkpoints
+            .best_metric
+            .is_none_or(|x| training_config.metrics.contains(&x)),
+        "Expected best_metric to be one of metrics"
+    );
+
+    let (artifact_dir, state) = match resume {
+        Some(artifact_dir) => {
+            let state = TrainingState::read(artifact_dir);
             info!(
-                "Total Duration: {:.1}s",
-                training_start_time.elapsed().as_secs_f32()
+                "Resuming from epoch {}, batch {} of {}",
+                state.epoch,
+                state.batch,
+                artifact_dir.display()
             );
+            (artifact_dir.to_path_buf(), state)
         }
+        None => {
+            let artifact_dir = training_config.artifact_dir.join(secs.to_string());
+            std::fs::create_dir_all(&artifact_dir).expect("Expected artifact dir to be creatable");
+            copy_configs(&artifact_dir);
+            let state = TrainingState::new(training_config.seed.unwrap_or(secs));
+            (artifact_dir, state)
+        }
+    };
+
+    let training_dataset: SqliteDataset = training_config
+        .training_dataset
+        .clone()
+        .try_into()
+        .expect("Expected valid training dataset config");
+
+    let testing_dataset: SqliteDataset = training_config
+        .testing_dataset
+        .clone()
+        .try_into()
+        .expect("Expected valid training dataset config");
+    let lr_scheduler = training_config.lr_scheduler.clone().init();
+
+    B::seed(device, state.seed);
+
+    let mut viz_command = training_config.viz_command.iter();
+    let child = viz_command.next().map(|cmd| {
+        std::process::Command::new(cmd)
+            .args(viz_command)
+            .env("ARTIFACT_DIR", &artifact_dir)
+            .env("TRAINING_BATCH_COUNT", &artifact_dir)
+            .stdin(Stdio::piped())
+            .spawn()
+            .expect("Expected valid viz command")
+    });
+
+    let model_type = training_config.model_type;
+    let run = TrainingRun {
+        device,
+        config: training_config,
+        config_dir: config_dir.to_path_buf(),
+        artifact_dir,
+        resumed: resume.is_some(),
+        state,
+        training_dataset,
+        testing_dataset,
+        lr_scheduler,
+        viz: Viz::new(child, ctrlc_pressed),
+        ctrlc_pressed,
+        clock,
+        init_start_time,
+    };
+    match model_type {
+        ModelType::ImageAutoEncoder => run_epochs::<B, ImageAutoEncoderTask<B>>(run),
+        ModelType::ImageClassifier => run_epochs::<B, ImageClassifierTask<B>>(run),
+        ModelType::SequenceRegressor => run_epochs::<B, SequenceRegressorTask<B>>(run),
     }
 }
 
@@ -421,12 +627,47 @@ pub fn main() {
     tracing_subscriber::fmt().init();
 
     match args.command {
-        Command::Train => train(),
+        Command::Train { backend } => train(backend, None),
+        Command::Resume {
+            artifact_dir,
+            backend,
+        } => train(backend, Some(&artifact_dir)),
         Command::Clean => {
             let training_config: TrainingConfig =
                 parse_json_file("training").expect("Expected valid training.json");
             std::fs::remove_dir_all(&training_config.artifact_dir)
                 .expect("Expected artifact dir to be removable");
         }
+        Command::Summary => {
+            let training_config: TrainingConfig =
+                parse_json_file("training").expect("Expected valid training.json");
+            let training_dataset: SqliteDataset = training_config
+                .training_dataset
+                .try_into()
+                .expect("Expected valid training dataset config");
+            let summary = match training_config.model_type {
+                ModelType::ImageAutoEncoder => {
+                    let model_config: ImageAutoEncoderConfig =
+                        parse_json_file("model").expect("Expected valid model.json");
+                    image_autoencoder_summary(&model_config, &training_dataset)
+                }
+                ModelType::ImageClassifier => {
+                    let model_config: ImageClassifierConfig =
+                        parse_json_file("model").expect("Expected valid model.json");
+                    image_classifier_summary(&model_config, &training_dataset)
+                }
+                ModelType::SequenceRegressor => {
+                    let model_config: SequenceRegressorConfig =
+                        parse_json_file("model").expect("Expected valid model.json");
+                    sequence_regressor_summary(&model_config, &training_dataset)
+                }
+            };
+            println!("{summary}");
+        }
+        Command::Export { checkpoint, output } => export(&checkpoint, &output),
+        Command::Quantize {
+            checkpoint,
+            calibration_batch_count,
+        } => quantize_checkpoint(&checkpoint, calibration_batch_count),
     }
 }
@@ -0,0 +1,242 @@
+//! The state of a training run besides the weights of its model, which is written to its artifact
+//! dir after every epoch and when training is interrupted, so that `proximo resume` can continue
+//! the run where it left off. It also tracks the best epoch, for `best.mpk` and early stopping.
+//!
+//! A resumed run draws the same numbers as one that wasn't interrupted: the weights are restored
+//! in full precision from `training-state.mpk` rather than from the half precision
+//! `model-{epoch}.mpk`, the batches come from the RNG of their epoch, and the backend RNG, such as
+//! for dropout, is seeded anew for every batch by [`batch_seed`].
+
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use burn::{
+    lr_scheduler::LrScheduler as _,
+    module::Module,
+    record::{DefaultRecorder, Recorder},
+    tensor::backend::AutodiffBackend,
+};
+use general_models::metrics::{Metrics, RunningStats};
+use rand::{Rng, SeedableRng, rngs::SmallRng};
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::{
+    app::config::CheckpointConfig,
+    trainable_models::apply_gradients::{
+        ApplyGradients, load_plan_record, lr_scheduler::LrScheduler, plan_to_record,
+    },
+};
+
+/// The counters of the run.
+const STATE_FILE: &str = "training-state.json";
+/// The weights of the model and the state of the optimizers and of the LR scheduler, to which the
+/// recorder adds `.mpk`. It's kept in full precision, since the second moments of Adam underflow
+/// in half precision, and so that resuming restores the exact weights.
+const RECORD_FILE: &str = "training-state";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrainingState {
+    /// The epoch that training continues from.
+    pub epoch: usize,
+    /// The number of batches of `epoch` that were already trained on, which is only more than zero
+    /// if training was interrupted during the epoch.
+    pub batch: usize,
+    /// The seed of the run, from which the RNG of every epoch is derived.
+    pub seed: u64,
+    /// The checkpoint of the last epoch, relative to the artifact dir. Resuming restores the
+    /// weights from `training-state.mpk` instead, which is in full precision.
+    pub model: PathBuf,
+    /// The epoch with the best validation metric so far, whose weights are in `best.mpk`.
+    #[serde(default)]
+    pub best: Option<BestEpoch>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct BestEpoch {
+    pub epoch: usize,
+    pub value: f64,
+}
+
+impl TrainingState {
+    /// The state of a new run, before its first epoch.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            epoch: 0,
+            batch: 0,
+            seed,
+            model: PathBuf::new(),
+            best: None,
+        }
+    }
+
+    /// Advances the state past training on `batch_count` batches of `epoch`, whose model was saved
+    /// to `model-{epoch}.mpk`. The gradients of an unfinished effective batch are never applied,
+    /// so an interrupted epoch continues from the start of its last effective batch.
+    pub fn end_epoch(
+        &mut self,
+        epoch: usize,
+        batch_count: usize,
+        grad_accumulate_count: usize,
+        interrupted: bool,
+    ) {
+        let grad_accumulate_count = grad_accumulate_count.max(1);
+        if interrupted {
+            self.epoch = epoch;
+            self.batch = batch_count / grad_accumulate_count * grad_accumulate_count;
+        } else {
+            self.epoch = epoch + 1;
+            self.batch = 0;
+        }
+        self.model = format!("model-{epoch}.mpk").into();
+    }
+
+    /// Records the validation `value` of `epoch`, which is the mean loss unless `best_metric`
+    /// is set, and copies its checkpoint to `best.mpk` if it's the best so far. Checkpoints that
+    /// fall out of the last `keep_last_checkpoints` are removed. Returns whether training should
+    /// stop early.
+    pub fn end_validation(
+        &mut self,
+        artifact_dir: &Path,
+        epoch: usize,
+        value: Option<f64>,
+        config: &CheckpointConfig,
+    ) -> bool {
+        let higher_is_better = config.best_metric.is_some_and(|x| x.higher_is_better());
+        let min_delta = config.early_stopping.as_ref().map_or(0.0, |x| x.min_delta);
+        let improved = value.filter(|x| !x.is_nan()).is_some_and(|value| {
+            self.best.is_none_or(|best| {
+                if higher_is_better {
+                    value > best.value + min_delta
+                } else {
+                    value < best.value - min_delta
+                }
+            })
+        });
+        if improved {
+            let value = value.unwrap();
+            self.best = Some(BestEpoch { epoch, value });
+            std::fs::copy(
+                artifact_dir.join(format!("model-{epoch}.mpk")),
+                artifact_dir.join("best.mpk"),
+            )
+            .expect("Expected best.mpk to be writable in artifact dir");
+            info!("Best epoch so far, with {value:.4}");
+        }
+
+        if let Some(old_epoch) = config
+            .keep_last_checkpoints
+            .and_then(|x| epoch.checked_sub(x.max(1)))
+        {
+            let path = artifact_dir.join(format!("model-{old_epoch}.mpk"));
+            if let Err(e) = std::fs::remove_file(&path)
+                && e.kind() != ErrorKind::NotFound
+            {
+                panic!("Expected {} to be removable: {e}", path.display());
+            }
+        }
+
+        let Some(early_stopping) = &config.early_stopping else {
+            return false;
+        };
+        // Without a best epoch, such as when every value was NaN, no epoch has improved
+        let epochs_without_improvement = self.best.map_or(epoch + 1, |best| epoch - best.epoch);
+        let stop = epochs_without_improvement >= early_stopping.patience.get();
+        if stop {
+            match self.best {
+                Some(best) => info!(
+                    "No improvement in {} epochs since epoch {}, stopping early",
+                    early_stopping.patience, best.epoch
+                ),
+                None => info!(
+                    "No improvement in {} epochs, stopping early",
+                    early_stopping.patience
+                ),
+            }
+        }
+        stop
+    }
+
+    /// Reads the counters of the run in `artifact_dir`.
+    pub fn read(artifact_dir: &Path) -> Self {
+        let bytes = std::fs::read(artifact_dir.join(STATE_FILE))
+            .expect("Expected training-state.json in artifact dir");
+        serde_json::from_slice(&bytes).expect("Expected valid training-state.json")
+    }
+
+    /// Writes the state to `artifact_dir`, along with the weights of `model` and the state of the
+    /// optimizers of `grads_plan` and of `lr_scheduler`.
+    pub fn save<B: AutodiffBackend, M: ApplyGradients<B>, N: Module<B>>(
+        &self,
+        artifact_dir: &Path,
+        model: &N,
+        grads_plan: &mut M::Plan,
+        lr_scheduler: &LrScheduler,
+    ) {
+        let record = (
+            model.clone().into_record(),
+            plan_to_record::<B, M>(grads_plan),
+            lr_scheduler.to_record::<B>(),
+        );
+        DefaultRecorder::new()
+            .record(record, artifact_dir.join(RECORD_FILE))
+            .expect("Expected training state to be saveable to artifact dir");
+        // The counters are written last, so that they never refer to an older record
+        std::fs::write(
+            artifact_dir.join(STATE_FILE),
+            serde_json::to_vec_pretty(self).expect("Expected training state to be serializable"),
+        )
+        .expect("Expected training-state.json to be writable in artifact dir");
+    }
+
+    /// Restores the weights of `model` and the state of the optimizers of `grads_plan` and of
+    /// `lr_scheduler` from `artifact_dir`. The model and the plan must come from the same configs
+    /// as the ones that were saved.
+    pub fn load_record<B: AutodiffBackend, M: ApplyGradients<B>, N: Module<B>>(
+        &self,
+        artifact_dir: &Path,
+        model: N,
+        grads_plan: &mut M::Plan,
+        lr_scheduler: LrScheduler,
+        device: &B::Device,
+    ) -> (N, LrScheduler) {
+        let (model_record, optimizers, lr_scheduler_record) = DefaultRecorder::new()
+            .load(artifact_dir.join(RECORD_FILE), device)
+            .expect("Expected training-state.mpk in artifact dir");
+        load_plan_record::<B, M>(grads_plan, optimizers);
+        (
+            model.load_record(model_record),
+            lr_scheduler.load_record::<B>(lr_scheduler_record),
+        )
+    }
+}
+
+/// The RNG of `epoch`, which only depends on the seed of the run so that a resumed run draws the
+/// same numbers as one that wasn't interrupted.
+pub fn epoch_rng(seed: u64, epoch: usize) -> SmallRng {
+    (0..epoch).fold(SmallRng::seed_from_u64(seed), |mut rng, _| {
+        SmallRng::from_rng(&mut rng)
+    })
+}
+
+/// The seed of the backend RNG, such as for dropout, for `batch` of an epoch whose RNG drew
+/// `epoch_seed`. Every batch is seeded on its own, so that an epoch that is resumed in the middle
+/// draws the same numbers as one that wasn't interrupted.
+pub fn batch_seed(epoch_seed: u64, batch: usize) -> u64 {
+    SmallRng::seed_from_u64(epoch_seed.wrapping_add(batch as u64)).random()
+}
+
+/// The validation metric of an epoch that the best epoch is chosen by, which is `best_metric` or
+/// the mean loss.
+pub fn validation_value(
+    config: &CheckpointConfig,
+    losses: &RunningStats,
+    metrics: &Metrics,
+) -> Option<f64> {
+    match config.best_metric {
+        Some(metric) => metrics.get(metric),
+        None => (!losses.is_empty()).then(|| losses.mean()),
+    }
+}
@@ -1,15 +1,42 @@
-use std::path::PathBuf;
+use std::{
+    num::NonZeroUsize,
+    path::{Path, PathBuf},
+};
 
+use burn::{module::Module, prelude::Backend};
 use general_dataset::SqliteDatasetConfig;
+use general_models::{
+    Init,
+    bundle::read_bundle_weights,
+    error::LoadModelError,
+    loss::LossConfig,
+    metrics::MetricConfig,
+    transfer::load_submodule,
+    weights::{PytorchNameMapping, load_pytorch, save_safetensors_bytes},
+};
 use serde::Deserialize;
-use utils::default_f;
+use utils::{default_f, parse_json_file};
 
-use crate::trainable_models::apply_gradients::lr_scheduler::LrSchedulerConfig;
+use crate::{
+    app::{
+        presets::{
+            autoencoders::{ImageAutoEncoder, ImageAutoEncoderConfig},
+            classifiers::{ImageClassifier, ImageClassifierConfig},
+            regressors::{SequenceRegressor, SequenceRegressorConfig},
+        },
+        training::Checkpoint,
+    },
+    trainable_models::apply_gradients::lr_scheduler::LrSchedulerConfig,
+};
 
 #[derive(Debug, Clone, Copy, Deserialize)]
 pub enum ModelType {
     #[serde(alias = "image-ae", alias = "img-ae")]
     ImageAutoEncoder,
+    #[serde(alias = "image-classifier", alias = "img-classifier")]
+    ImageClassifier,
+    #[serde(alias = "sequence-regressor", alias = "seq-regressor")]
+    SequenceRegressor,
     // #[serde(alias = "image-vae", alias = "img-vae")]
     // ImageVariationalAutoEncoder,
 }
@@ -28,12 +55,168 @@ pub struct TrainingConfig {
     pub training_max_batch_count: usize,
     #[serde(default = "default_max_batch_count")]
     pub testing_max_batch_count: usize,
-    // #[serde(default = "default_grad_accumulate_count")]
-    // pub grad_accumulate_count: usize,
+    /// The number of batches whose gradients are averaged into each optimizer step, for effective
+    /// batches larger than fit in memory. `training_max_batch_count` counts single batches.
+    #[serde(default = "default_grad_accumulate_count")]
+    pub grad_accumulate_count: usize,
     pub training_dataset: SqliteDatasetConfig,
     pub testing_dataset: SqliteDatasetConfig,
     pub lr_scheduler: LrSchedulerConfig,
     pub seed: Option<u64>,
+    /// The loss of the task, used for both training and validation. Autoencoders default to
+    /// `bce`, classifiers to `cross_entropy` and regressors to `mse`.
+    pub loss: Option<LossConfig>,
+    /// Metrics logged after each epoch of validation, along with the loss, such as
+    /// `["psnr", "ssim"]` for autoencoders or `["accuracy", "f1"]` for classifiers.
+    #[serde(default)]
+    pub metrics: Vec<MetricConfig>,
+    /// Which checkpoints are kept, and when to stop early.
+    #[serde(flatten)]
+    pub checkpoints: CheckpointConfig,
+    /// Weights to start training from instead of initializing them randomly, such as
+    /// `{ "path": "artifacts/ae/1700000000/model-19.mpk", "model_type": "ImageAutoEncoder",
+    /// "model": "../ae/model.jsonc", "from": "encoder", "to": "model" }` to start a classifier
+    /// from the encoder of an autoencoder.
+    pub initial_weights: Option<InitialWeightsConfig>,
+    /// Submodules that aren't trained, by their readable names, such as `["model.conv"]` or
+    /// `["encoder.conv.layers.0..2"]` for the first two layers. Ranges exclude their end.
+    #[serde(default)]
+    pub freeze: Vec<String>,
+    /// Trains the model to match the outputs of a frozen teacher model as well.
+    pub distillation: Option<DistillationConfig>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CheckpointConfig {
+    /// The validation metric that the best epoch is chosen by, whose weights are copied to
+    /// `best.mpk`. It must be one of `metrics`, and the validation loss is used if it is left out.
+    pub best_metric: Option<MetricConfig>,
+    /// Stops training once the validation metric stops improving.
+    pub early_stopping: Option<EarlyStoppingConfig>,
+    /// The number of the latest `model-{epoch}.mpk` checkpoints that are kept, besides
+    /// `best.mpk`. Every checkpoint is kept if it is left out.
+    pub keep_last_checkpoints: Option<usize>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct EarlyStoppingConfig {
+    /// The number of epochs without improvement after which training stops.
+    pub patience: NonZeroUsize,
+    /// How much the validation metric has to improve by for the epoch to count as an
+    /// improvement.
+    #[serde(default)]
+    pub min_delta: f64,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct InitialWeightsConfig {
+    /// A checkpoint saved during training, such as `model-19.mpk` in an artifact dir,
+    /// safetensors or a bundle exported by `proximo export`, or a PyTorch state dict in a `.pt`,
+    /// `.pth` or safetensors file.
+    pub path: PathBuf,
+    /// A JSON file with a [`PytorchNameMapping`], which marks `path` as a PyTorch state dict.
+    /// `.pt` and `.pth` files are loaded with an empty mapping if it is left out.
+    pub pytorch_mapping: Option<PathBuf>,
+    /// The type of the model of a `.mpk` checkpoint, if it differs from the trained model.
+    pub model_type: Option<ModelType>,
+    /// The model.json of a `.mpk` checkpoint, if it differs from the trained model.
+    pub model: Option<PathBuf>,
+    /// The submodule of the checkpoint to load, such as `encoder` to reuse the encoder of an
+    /// autoencoder. The whole checkpoint is loaded if it is left out. PyTorch state dicts are
+    /// renamed by their mapping instead.
+    #[serde(default)]
+    pub from: String,
+    /// The submodule of the model that `from` is loaded into, such as `model` for the model of a
+    /// classifier. Every tensor of the submodule must be in `from`.
+    #[serde(default)]
+    pub to: String,
+}
+
+impl InitialWeightsConfig {
+    /// Loads the weights into `model`, which is of type `model_type` and configured by
+    /// model.json.
+    pub fn load<B: Backend, M: Module<B>>(
+        &self,
+        model: M,
+        model_type: ModelType,
+        device: &B::Device,
+    ) -> Result<M, LoadModelError> {
+        let mapping = match &self.pytorch_mapping {
+            Some(path) => Some(parse_json_file(path)?),
+            None if self
+                .path
+                .extension()
+                .is_some_and(|x| x == "pt" || x == "pth") =>
+            {
+                Some(PytorchNameMapping::default())
+            }
+            None => None,
+        };
+        if let Some(mapping) = mapping {
+            return Ok(load_pytorch(model, &self.path, &mapping)?);
+        }
+
+        let safetensors = match self.path.extension().and_then(|x| x.to_str()) {
+            Some("safetensors") => std::fs::read(&self.path)?,
+            Some("bundle") => read_bundle_weights(&self.path)?,
+            _ => checkpoint_safetensors::<B>(
+                &self.path,
+                self.model_type.unwrap_or(model_type),
+                self.model.as_deref().unwrap_or(Path::new("model")),
+                device,
+            )?,
+        };
+        load_submodule(model, safetensors, &self.from, &self.to)
+    }
+}
+
+/// Converts a checkpoint saved during training of a model of type `model_type`, configured by
+/// `model_config`, to safetensors.
+fn checkpoint_safetensors<B: Backend>(
+    path: &Path,
+    model_type: ModelType,
+    model_config: &Path,
+    device: &B::Device,
+) -> Result<Vec<u8>, LoadModelError> {
+    Ok(match model_type {
+        ModelType::ImageAutoEncoder => {
+            let config: ImageAutoEncoderConfig = parse_json_file(model_config)?;
+            let model: ImageAutoEncoder<B> = config.init(device);
+            save_safetensors_bytes(&model.load_checkpoint(path, device)?)?
+        }
+        ModelType::ImageClassifier => {
+            let config: ImageClassifierConfig = parse_json_file(model_config)?;
+            let model: ImageClassifier<B> = config.init(device);
+            save_safetensors_bytes(&model.load_checkpoint(path, device)?)?
+        }
+        ModelType::SequenceRegressor => {
+            let config: SequenceRegressorConfig = parse_json_file(model_config)?;
+            let model: SequenceRegressor<B> = config.init(device);
+            save_safetensors_bytes(&model.load_checkpoint(path, device)?)?
+        }
+    })
+}
+
+/// A frozen teacher model of the same model type as the trained student, usually a larger one.
+///
+/// The distillation loss is added to the loss of the task: the KL divergence between the softened
+/// probabilities of classifiers, the BCE against the reconstructions of autoencoders, and the
+/// regression loss for regressors.
+#[derive(Deserialize, Debug, Clone)]
+pub struct DistillationConfig {
+    /// The model.json of the teacher.
+    pub teacher_model: PathBuf,
+    /// A checkpoint of the teacher, saved during training or exported to safetensors.
+    pub teacher_weights: PathBuf,
+    #[serde(default = "default_distillation_weight")]
+    pub weight: f32,
+    /// Softens the probabilities of classifiers before they are compared.
+    #[serde(default = "default_distillation_temperature")]
+    pub temperature: f32,
+    /// The weight of the MSE between the latent codes of the teacher and the student, which is
+    /// only supported for plain autoencoders with the same latent size.
+    #[serde(default)]
+    pub latent_weight: f32,
 }
 
 #[derive(Deserialize, Debug)]
@@ -42,6 +225,14 @@ pub struct ImageAutoEncoderChallenge {
     pub challenge_image_count: usize,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct ImageClassifierValidation {
+    /// A prediction counts towards the top-k accuracy if the label is among the `top_k` most
+    /// likely classes.
+    #[serde(default = "default_top_k")]
+    pub top_k: usize,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct TrainingGradsPlanConfig<T> {
     pub grads_plan: T,
@@ -49,5 +240,8 @@ pub struct TrainingGradsPlanConfig<T> {
 
 default_f!(default_num_epochs, usize, 10);
 default_f!(default_batch_size, usize, 64);
-// default_f!(default_grad_accumulate_count, usize, 8);
+default_f!(default_top_k, usize, 5);
+default_f!(default_distillation_weight, f32, 1.0);
+default_f!(default_distillation_temperature, f32, 2.0);
+default_f!(default_grad_accumulate_count, usize, 1);
 default_f!(default_max_batch_count, usize, usize::MAX);
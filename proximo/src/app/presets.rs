@@ -1 +1,3 @@
 pub mod autoencoders;
+pub mod classifiers;
+pub mod regressors;
@@ -1,28 +1,50 @@
-use burn::{module::Module, prelude::Backend, tensor::backend::AutodiffBackend};
+use std::path::Path;
+
+use burn::{
+    module::Module,
+    prelude::Backend,
+    record::{CompactRecorder, RecorderError},
+    tensor::backend::AutodiffBackend,
+};
 use general_models::{
     Init, SimpleInfer,
+    bundle::load_bundle_weights,
     composite::{
         autoencoder::{
             AutoEncoderModel, AutoEncoderModelConfig,
+            cvae::{ConditionalVaeModel, ConditionalVaeModelConfig},
             vae::{VariationalEncoderModel, VariationalEncoderModelConfig},
+            vq::{VectorQuantizedEncoderModel, VectorQuantizedEncoderModelConfig},
         },
         image::{
             Conv2dLinearModel, Conv2dLinearModelConfig, LinearConvTranspose2dModel,
             LinearConvTranspose2dModelConfig,
         },
     },
+    error::{ExportError, LoadModelError, ShapeError},
+    onnx::{OnnxGraph, export_onnx},
+    sequential::{SequentialModel, SequentialModelConfig},
+    shape::{InferShape, LayerShape},
+    weights::load_safetensors,
 };
 use serde::{Deserialize, Serialize};
 
-use crate::trainable_models::apply_gradients::{
-    ApplyGradients,
-    autoencoder::{
-        AutoEncoderModelPlan, AutoEncoderModelPlanConfig, VariationalEncoderModelPlan,
-        VariationalEncoderModelPlanConfig,
-    },
-    image::{
-        Conv2dLinearModelPlan, Conv2dLinearModelPlanConfig, LinearConvTranspose2dModelPlan,
-        LinearConvTranspose2dModelPlanConfig,
+use crate::{
+    app::training::Checkpoint,
+    trainable_models::apply_gradients::{
+        ApplyGradients,
+        autoencoder::{
+            AutoEncoderModelPlan, AutoEncoderModelPlanConfig, ConditionalVaeModelPlan,
+            ConditionalVaeModelPlanConfig, VariationalEncoderModelPlan,
+            VariationalEncoderModelPlanConfig, VectorQuantizedEncoderModelPlan,
+            VectorQuantizedEncoderModelPlanConfig,
+        },
+        image::{
+            Conv2dLinearModelPlan, Conv2dLinearModelPlanConfig, LinearConvTranspose2dModelPlan,
+            LinearConvTranspose2dModelPlanConfig,
+        },
+        optimizer::OptimizerVisitor,
+        sequential::{SequentialModelPlan, SequentialModelPlanConfig},
     },
 };
 
@@ -36,6 +58,15 @@ pub enum ImageAutoEncoder<B: Backend> {
             LinearConvTranspose2dModel<B>,
         >,
     ),
+    Sequential(AutoEncoderModel<B, SequentialModel<B>, SequentialModel<B>>),
+    Conditional(ConditionalVaeModel<B, Conv2dLinearModel<B>, LinearConvTranspose2dModel<B>>),
+    Vq(
+        AutoEncoderModel<
+            B,
+            VectorQuantizedEncoderModel<B, Conv2dLinearModel<B>>,
+            LinearConvTranspose2dModel<B>,
+        >,
+    ),
 }
 
 impl<B: Backend> ImageAutoEncoder<B> {
@@ -43,6 +74,108 @@ impl<B: Backend> ImageAutoEncoder<B> {
         match self {
             ImageAutoEncoder::Normal(x) => x.encoder.get_input_channels(),
             ImageAutoEncoder::Vae(x) => x.encoder.model.get_input_channels(),
+            ImageAutoEncoder::Sequential(x) => x.encoder.get_input_shape()[0],
+            ImageAutoEncoder::Conditional(x) => x.encoder.model.get_input_channels(),
+            ImageAutoEncoder::Vq(x) => x.encoder.model.get_input_channels(),
+        }
+    }
+
+    /// The size of the codebook, if the model is vector quantized.
+    pub fn get_num_codes(&self) -> Option<usize> {
+        match self {
+            ImageAutoEncoder::Vq(x) => Some(x.encoder.quantizer.get_num_codes()),
+            _ => None,
+        }
+    }
+
+    /// The number of classes the model is conditioned on, if it is conditional.
+    pub fn get_num_classes(&self) -> Option<usize> {
+        match self {
+            ImageAutoEncoder::Conditional(x) => Some(x.get_condition_size()),
+            _ => None,
+        }
+    }
+
+    /// The latent code of `tensor`, which only plain autoencoders have.
+    pub fn encode(&self, tensor: burn::Tensor<B, 4>) -> Option<burn::Tensor<B, 2>> {
+        match self {
+            ImageAutoEncoder::Normal(x) => Some(x.encoder.infer(tensor)),
+            _ => None,
+        }
+    }
+
+    /// Reconstructs `tensor`, which must come with a `condition` if the model is conditional.
+    pub fn reconstruct(
+        &self,
+        tensor: burn::Tensor<B, 4>,
+        condition: Option<burn::Tensor<B, 2>>,
+    ) -> burn::Tensor<B, 4> {
+        match self {
+            ImageAutoEncoder::Normal(x) => x.infer(tensor),
+            ImageAutoEncoder::Vae(x) => x.infer(tensor),
+            ImageAutoEncoder::Sequential(x) => x.infer(tensor),
+            ImageAutoEncoder::Conditional(x) => x.infer(
+                tensor,
+                condition.expect("Expected a condition for the conditional VAE"),
+            ),
+            ImageAutoEncoder::Vq(x) => x.infer(tensor),
+        }
+    }
+
+    /// Exports the reconstruction of [`Self::reconstruct`] to ONNX. Conditional models take the
+    /// one-hot labels as a second input named `condition`.
+    pub fn export_onnx(&self, input_shape: &[usize]) -> Result<Vec<u8>, ExportError> {
+        match self {
+            ImageAutoEncoder::Normal(x) => export_onnx(x, input_shape),
+            ImageAutoEncoder::Vae(x) => export_onnx(x, input_shape),
+            ImageAutoEncoder::Sequential(x) => export_onnx(x, input_shape),
+            ImageAutoEncoder::Conditional(x) => {
+                let mut graph = OnnxGraph::new();
+                let input = graph.input("input", input_shape);
+                let condition = graph.input("condition", &[x.get_condition_size()]);
+                let output = x.to_onnx(&mut graph, input, condition)?;
+                Ok(graph.finish(&[("output", output)]))
+            }
+            ImageAutoEncoder::Vq(x) => export_onnx(x, input_shape),
+        }
+    }
+}
+
+/// Checkpoints saved during training hold the weights of the inner model.
+impl<B: Backend> Checkpoint<B> for ImageAutoEncoder<B> {
+    fn load_checkpoint(self, path: &Path, device: &B::Device) -> Result<Self, LoadModelError> {
+        if path.extension().is_some_and(|x| x == "safetensors") {
+            return Ok(load_safetensors(self, path)?);
+        }
+        if path.extension().is_some_and(|x| x == "bundle") {
+            return load_bundle_weights(self, path);
+        }
+        let recorder = CompactRecorder::new();
+        Ok(match self {
+            ImageAutoEncoder::Normal(x) => {
+                ImageAutoEncoder::Normal(x.load_file(path, &recorder, device)?)
+            }
+            ImageAutoEncoder::Vae(x) => {
+                ImageAutoEncoder::Vae(x.load_file(path, &recorder, device)?)
+            }
+            ImageAutoEncoder::Sequential(x) => {
+                ImageAutoEncoder::Sequential(x.load_file(path, &recorder, device)?)
+            }
+            ImageAutoEncoder::Conditional(x) => {
+                ImageAutoEncoder::Conditional(x.load_file(path, &recorder, device)?)
+            }
+            ImageAutoEncoder::Vq(x) => ImageAutoEncoder::Vq(x.load_file(path, &recorder, device)?),
+        })
+    }
+
+    fn save_checkpoint(&self, path: &Path) -> Result<(), RecorderError> {
+        let recorder = CompactRecorder::new();
+        match self {
+            ImageAutoEncoder::Normal(x) => x.clone().save_file(path, &recorder),
+            ImageAutoEncoder::Vae(x) => x.clone().save_file(path, &recorder),
+            ImageAutoEncoder::Sequential(x) => x.clone().save_file(path, &recorder),
+            ImageAutoEncoder::Conditional(x) => x.clone().save_file(path, &recorder),
+            ImageAutoEncoder::Vq(x) => x.clone().save_file(path, &recorder),
         }
     }
 }
@@ -51,12 +184,23 @@ impl<B: Backend> ImageAutoEncoder<B> {
 #[serde(untagged)]
 pub enum ImageAutoEncoderConfig {
     Normal(AutoEncoderModelConfig<Conv2dLinearModelConfig, LinearConvTranspose2dModelConfig>),
+    // Goes before `Vae`, which would otherwise accept it by ignoring the condition
+    Conditional(
+        ConditionalVaeModelConfig<Conv2dLinearModelConfig, LinearConvTranspose2dModelConfig>,
+    ),
     Vae(
         AutoEncoderModelConfig<
             VariationalEncoderModelConfig<Conv2dLinearModelConfig>,
             LinearConvTranspose2dModelConfig,
         >,
     ),
+    Vq(
+        AutoEncoderModelConfig<
+            VectorQuantizedEncoderModelConfig<Conv2dLinearModelConfig>,
+            LinearConvTranspose2dModelConfig,
+        >,
+    ),
+    Sequential(AutoEncoderModelConfig<SequentialModelConfig, SequentialModelConfig>),
 }
 
 impl<B: Backend> Init<B, ImageAutoEncoder<B>> for ImageAutoEncoderConfig {
@@ -64,6 +208,33 @@ impl<B: Backend> Init<B, ImageAutoEncoder<B>> for ImageAutoEncoderConfig {
         match self {
             ImageAutoEncoderConfig::Normal(x) => ImageAutoEncoder::Normal(x.init(device)),
             ImageAutoEncoderConfig::Vae(x) => ImageAutoEncoder::Vae(x.init(device)),
+            ImageAutoEncoderConfig::Sequential(x) => ImageAutoEncoder::Sequential(x.init(device)),
+            ImageAutoEncoderConfig::Conditional(x) => ImageAutoEncoder::Conditional(x.init(device)),
+            ImageAutoEncoderConfig::Vq(x) => ImageAutoEncoder::Vq(x.init(device)),
+        }
+    }
+}
+
+impl ImageAutoEncoderConfig {
+    pub fn get_input_channels(&self) -> usize {
+        match self {
+            ImageAutoEncoderConfig::Normal(x) => x.encoder.conv.input_channels,
+            ImageAutoEncoderConfig::Vae(x) => x.encoder.model.conv.input_channels,
+            ImageAutoEncoderConfig::Sequential(x) => x.encoder.input_shape[0],
+            ImageAutoEncoderConfig::Conditional(x) => x.encoder.model.conv.input_channels,
+            ImageAutoEncoderConfig::Vq(x) => x.encoder.model.conv.input_channels,
+        }
+    }
+}
+
+impl InferShape for ImageAutoEncoderConfig {
+    fn infer_shapes(&self, input_shape: &[usize]) -> Result<Vec<LayerShape>, ShapeError> {
+        match self {
+            ImageAutoEncoderConfig::Normal(x) => x.infer_shapes(input_shape),
+            ImageAutoEncoderConfig::Vae(x) => x.infer_shapes(input_shape),
+            ImageAutoEncoderConfig::Sequential(x) => x.infer_shapes(input_shape),
+            ImageAutoEncoderConfig::Conditional(x) => x.infer_shapes(input_shape),
+            ImageAutoEncoderConfig::Vq(x) => x.infer_shapes(input_shape),
         }
     }
 }
@@ -76,6 +247,16 @@ pub enum ImageAutoEncoderPlan<B: AutodiffBackend> {
             LinearConvTranspose2dModelPlan<B>,
         >,
     ),
+    Sequential(AutoEncoderModelPlan<SequentialModelPlan<B>, SequentialModelPlan<B>>),
+    Conditional(
+        ConditionalVaeModelPlan<B, Conv2dLinearModelPlan<B>, LinearConvTranspose2dModelPlan<B>>,
+    ),
+    Vq(
+        AutoEncoderModelPlan<
+            VectorQuantizedEncoderModelPlan<B, Conv2dLinearModelPlan<B>>,
+            LinearConvTranspose2dModelPlan<B>,
+        >,
+    ),
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -87,12 +268,27 @@ pub enum ImageAutoEncoderPlanConfig {
             LinearConvTranspose2dModelPlanConfig,
         >,
     ),
+    // Goes before `Vae`, which would otherwise accept it by ignoring the condition
+    Conditional(
+        ConditionalVaeModelPlanConfig<
+            Conv2dLinearModelPlanConfig,
+            LinearConvTranspose2dModelPlanConfig,
+        >,
+    ),
     Vae(
         AutoEncoderModelPlanConfig<
             VariationalEncoderModelPlanConfig<Conv2dLinearModelPlanConfig>,
             LinearConvTranspose2dModelPlanConfig,
         >,
     ),
+    // Goes before `Sequential`, whose plan only has optional fields
+    Vq(
+        AutoEncoderModelPlanConfig<
+            VectorQuantizedEncoderModelPlanConfig<Conv2dLinearModelPlanConfig>,
+            LinearConvTranspose2dModelPlanConfig,
+        >,
+    ),
+    Sequential(AutoEncoderModelPlanConfig<SequentialModelPlanConfig, SequentialModelPlanConfig>),
 }
 
 impl<B: AutodiffBackend> ApplyGradients<B> for ImageAutoEncoder<B> {
@@ -116,6 +312,27 @@ impl<B: AutodiffBackend> ApplyGradients<B> for ImageAutoEncoder<B> {
                     LinearConvTranspose2dModel<B>,
                 >::config_to_plan(x))
             }
+            ImageAutoEncoderPlanConfig::Sequential(x) => {
+                ImageAutoEncoderPlan::Sequential(AutoEncoderModel::<
+                    B,
+                    SequentialModel<B>,
+                    SequentialModel<B>,
+                >::config_to_plan(x))
+            }
+            ImageAutoEncoderPlanConfig::Vq(x) => {
+                ImageAutoEncoderPlan::Vq(AutoEncoderModel::<
+                    B,
+                    VectorQuantizedEncoderModel<B, Conv2dLinearModel<B>>,
+                    LinearConvTranspose2dModel<B>,
+                >::config_to_plan(x))
+            }
+            ImageAutoEncoderPlanConfig::Conditional(x) => {
+                ImageAutoEncoderPlan::Conditional(ConditionalVaeModel::<
+                    B,
+                    Conv2dLinearModel<B>,
+                    LinearConvTranspose2dModel<B>,
+                >::config_to_plan(x))
+            }
         }
     }
 
@@ -138,6 +355,56 @@ impl<B: AutodiffBackend> ApplyGradients<B> for ImageAutoEncoder<B> {
                 };
                 plan
             }),
+            ImageAutoEncoder::Sequential(x) => x.apply_gradients(lr, grads, {
+                let ImageAutoEncoderPlan::Sequential(plan) = plan else {
+                    panic!("Incorrect model plan");
+                };
+                plan
+            }),
+            ImageAutoEncoder::Conditional(x) => x.apply_gradients(lr, grads, {
+                let ImageAutoEncoderPlan::Conditional(plan) = plan else {
+                    panic!("Incorrect model plan");
+                };
+                plan
+            }),
+            ImageAutoEncoder::Vq(x) => x.apply_gradients(lr, grads, {
+                let ImageAutoEncoderPlan::Vq(plan) = plan else {
+                    panic!("Incorrect model plan");
+                };
+                plan
+            }),
+        }
+    }
+
+    fn visit_optimizers(plan: &mut Self::Plan, visitor: &mut impl OptimizerVisitor<B>) {
+        match plan {
+            ImageAutoEncoderPlan::Normal(plan) => AutoEncoderModel::<
+                B,
+                Conv2dLinearModel<B>,
+                LinearConvTranspose2dModel<B>,
+            >::visit_optimizers(plan, visitor),
+            ImageAutoEncoderPlan::Vae(plan) => AutoEncoderModel::<
+                B,
+                VariationalEncoderModel<B, Conv2dLinearModel<B>>,
+                LinearConvTranspose2dModel<B>,
+            >::visit_optimizers(plan, visitor),
+            ImageAutoEncoderPlan::Sequential(plan) => {
+                AutoEncoderModel::<B, SequentialModel<B>, SequentialModel<B>>::visit_optimizers(
+                    plan, visitor,
+                )
+            }
+            ImageAutoEncoderPlan::Conditional(plan) => ConditionalVaeModel::<
+                B,
+                Conv2dLinearModel<B>,
+                LinearConvTranspose2dModel<B>,
+            >::visit_optimizers(
+                plan, visitor
+            ),
+            ImageAutoEncoderPlan::Vq(plan) => AutoEncoderModel::<
+                B,
+                VectorQuantizedEncoderModel<B, Conv2dLinearModel<B>>,
+                LinearConvTranspose2dModel<B>,
+            >::visit_optimizers(plan, visitor),
         }
     }
 }
@@ -151,11 +418,20 @@ impl<B: AutodiffBackend> ApplyGradients<B> for ImageAutoEncoder<B> {
 //     }
 // }
 
+/// Reconstructs like [`ImageAutoEncoder::reconstruct`]. Conditional models are given an all-zero
+/// condition, which is an unconditioned reconstruction rather than one of any class.
 impl<B: Backend> SimpleInfer<B, 4, 4> for ImageAutoEncoder<B> {
     fn forward(&self, tensor: burn::Tensor<B, 4>) -> burn::Tensor<B, 4> {
-        match self {
-            ImageAutoEncoder::Normal(x) => x.infer(tensor),
-            ImageAutoEncoder::Vae(x) => x.infer(tensor),
-        }
+        let condition = match self {
+            ImageAutoEncoder::Conditional(x) => {
+                let [batch_size, ..] = tensor.dims();
+                Some(burn::Tensor::zeros(
+                    [batch_size, x.get_condition_size()],
+                    &tensor.device(),
+                ))
+            }
+            _ => None,
+        };
+        self.reconstruct(tensor, condition)
     }
 }
@@ -0,0 +1,12 @@
+use general_models::composite::{
+    classifier::{ClassifierModel, ClassifierModelConfig},
+    image::{Conv2dLinearModel, Conv2dLinearModelConfig},
+};
+
+use crate::trainable_models::apply_gradients::image::Conv2dLinearModelPlanConfig;
+
+/// A convolutional image classifier. The output size of its last linear layer is the number of
+/// classes.
+pub type ImageClassifier<B> = ClassifierModel<B, Conv2dLinearModel<B>>;
+pub type ImageClassifierConfig = ClassifierModelConfig<Conv2dLinearModelConfig>;
+pub type ImageClassifierPlanConfig = Conv2dLinearModelPlanConfig;
@@ -0,0 +1,9 @@
+use general_models::composite::sequence::{GruLinearModel, GruLinearModelConfig};
+
+use crate::trainable_models::apply_gradients::sequence::GruLinearModelPlanConfig;
+
+/// A recurrent regressor over `[batch_size, seq_len, features]` sequences. The output size of its
+/// last linear layer is the size of the target vector.
+pub type SequenceRegressor<B> = GruLinearModel<B>;
+pub type SequenceRegressorConfig = GruLinearModelConfig;
+pub type SequenceRegressorPlanConfig = GruLinearModelPlanConfig;
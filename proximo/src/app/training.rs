@@ -0,0 +1,399 @@
+//! The epochs of a training run, which are the same for every model type besides the losses and
+//! what is reported after validation.
+
+use std::{
+    path::{Path, PathBuf},
+    process::Child,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use burn::{
+    Tensor,
+    backend::Autodiff,
+    module::{AutodiffModule, DisplaySettings, Module, ModuleDisplay},
+    prelude::Backend,
+    record::{CompactRecorder, RecorderError},
+    tensor::ElementConversion,
+};
+use general_dataset::{FromSqlRow, SqliteDataset, StatefulBatcher};
+use general_models::{
+    Init, bundle::load_bundle_weights, error::LoadModelError, metrics::Metrics, transfer::freeze,
+    weights::load_safetensors,
+};
+use rand::{Rng, rngs::SmallRng};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use tracing::info;
+use utils::parse_json_file;
+
+use crate::{
+    app::{
+        checkpoint::{TrainingState, batch_seed, epoch_rng, validation_value},
+        config::{DistillationConfig, TrainingConfig, TrainingGradsPlanConfig},
+    },
+    trainable_models::{
+        AdHocLossModel,
+        apply_gradients::{AdHocTrainingPlan, ApplyGradients, lr_scheduler::LrScheduler},
+    },
+    training_loop::{train_epoch, validate_model},
+};
+
+pub mod autoencoder;
+pub mod classifier;
+pub mod regressor;
+
+/// How a model type is saved to and loaded from checkpoints.
+pub trait Checkpoint<B: Backend>: Module<B> {
+    /// Loads a checkpoint saved during training, or weights exported to safetensors or a bundle.
+    fn load_checkpoint(self, path: &Path, device: &B::Device) -> Result<Self, LoadModelError> {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some("safetensors") => Ok(load_safetensors(self, path)?),
+            Some("bundle") => load_bundle_weights(self, path),
+            _ => Ok(self.load_file(path, &CompactRecorder::new(), device)?),
+        }
+    }
+
+    /// Saves a checkpoint, such as `model-{epoch}.mpk`, that [`Self::load_checkpoint`] loads.
+    fn save_checkpoint(&self, path: &Path) -> Result<(), RecorderError> {
+        self.clone().save_file(path, &CompactRecorder::new())
+    }
+}
+
+/// The parts of training that differ between model types: the losses, and what is reported
+/// around validation.
+pub trait TrainingTask<B: Backend>: Sized + Send + Sync {
+    type Model: AutodiffModule<Autodiff<B>, InnerModule = Self::ValidModel>
+        + ApplyGradients<Autodiff<B>>
+        + Checkpoint<Autodiff<B>>
+        + ModuleDisplay
+        + Send;
+    /// The model without autodiff, which is validated.
+    type ValidModel: Send;
+    /// The rows of the datasets.
+    type Row: FromSqlRow;
+    type TrainingBatch: Send;
+    type TestingBatch: Send;
+    type TrainingBatcher: StatefulBatcher<Self::Row, Self::TrainingBatch> + Send;
+    type TestingBatcher: StatefulBatcher<Self::Row, Self::TestingBatch> + Send;
+
+    /// Parses model.json and initializes the task and the model, before its weights are loaded.
+    fn init(run: &TrainingRun<B>) -> (Self, Self::Model);
+
+    fn batchers(
+        &self,
+        model: &Self::Model,
+        device: &B::Device,
+    ) -> (Self::TrainingBatcher, Self::TestingBatcher);
+
+    fn training_loss(
+        &self,
+        model: &Self::Model,
+        batch: Self::TrainingBatch,
+        plan: &AdHocTrainingPlan<Autodiff<B>, Self::Model>,
+    ) -> Tensor<Autodiff<B>, 1>;
+
+    /// The loss of a batch of validation, which also updates `metrics` and anything else that is
+    /// reported in [`Self::after_validation`].
+    fn validation_loss(
+        &mut self,
+        model: &Self::ValidModel,
+        batch: Self::TestingBatch,
+        metrics: &mut Metrics,
+    ) -> Tensor<B, 1>;
+
+    /// Runs before every validation, such as to reset the stats of the previous one.
+    fn before_validation(&mut self, _model: &Self::ValidModel, _epoch: &mut EpochContext) {}
+
+    /// Reports anything besides the loss and metrics of the validation.
+    fn after_validation(&mut self, _epoch: &mut EpochContext) {}
+}
+
+/// What the validation hooks of a [`TrainingTask`] can use of the run.
+pub struct EpochContext<'a> {
+    pub epoch: usize,
+    pub artifact_dir: &'a Path,
+    pub testing_dataset: &'a SqliteDataset,
+    pub rng: &'a mut SmallRng,
+    pub viz: &'a mut Viz,
+}
+
+/// The viz command of the run, which is sent the progress of training as JSON instead of it being
+/// logged.
+pub struct Viz {
+    child: Option<Child>,
+    ctrlc_pressed: &'static AtomicBool,
+}
+
+impl Viz {
+    pub fn new(child: Option<Child>, ctrlc_pressed: &'static AtomicBool) -> Self {
+        Self {
+            child,
+            ctrlc_pressed,
+        }
+    }
+
+    pub fn is_some(&self) -> bool {
+        self.child.is_some()
+    }
+
+    /// Sends `value` to the viz command, returning whether there is one. It may have exited once
+    /// Ctrl-C was pressed, which isn't an error.
+    pub fn send(&mut self, value: &serde_json::Value) -> bool {
+        let Some(child) = &mut self.child else {
+            return false;
+        };
+        let result = serde_json::to_writer(child.stdin.as_mut().unwrap(), value);
+        if !self.ctrlc_pressed.load(Ordering::Relaxed) {
+            result.expect("Expected child process to be alive");
+        }
+        true
+    }
+}
+
+/// A new or resumed training run, with everything that doesn't depend on the model type.
+pub struct TrainingRun<B: Backend> {
+    pub device: &'static B::Device,
+    pub config: TrainingConfig,
+    /// The dir that the configs are read from, which is the artifact dir of a resumed run.
+    pub config_dir: PathBuf,
+    pub artifact_dir: PathBuf,
+    pub resumed: bool,
+    pub state: TrainingState,
+    pub training_dataset: SqliteDataset,
+    pub testing_dataset: SqliteDataset,
+    pub lr_scheduler: LrScheduler,
+    pub viz: Viz,
+    pub ctrlc_pressed: &'static AtomicBool,
+    pub clock: quanta::Clock,
+    pub init_start_time: quanta::Instant,
+}
+
+/// Loads the frozen teacher of `distillation`. Models aren't Sync, but the training loss that uses
+/// it is shared with the training thread.
+pub fn load_teacher<B, C, M>(distillation: &DistillationConfig, device: &B::Device) -> Mutex<M>
+where
+    B: Backend,
+    C: DeserializeOwned + Init<B, M>,
+    M: Checkpoint<B>,
+{
+    let teacher_config: C =
+        parse_json_file(&distillation.teacher_model).expect("Expected valid teacher model.json");
+    let teacher = teacher_config
+        .init(device)
+        .load_checkpoint(&distillation.teacher_weights, device)
+        .unwrap_or_else(|e| panic!("Expected teacher weights to match teacher model.json: {e}"));
+    Mutex::new(teacher)
+}
+
+/// Trains the model of `T` for the remaining epochs of `run`, saving a checkpoint and the state of
+/// the run after every epoch, until all epochs are done, training stops early or Ctrl-C is
+/// pressed.
+pub fn run_epochs<B, T>(mut run: TrainingRun<B>)
+where
+    B: Backend,
+    T: TrainingTask<B>,
+    AdHocTrainingPlan<Autodiff<B>, T::Model>: Send,
+{
+    let device = run.device;
+    let (mut task, mut model) = T::init(&run);
+    let grads_plan: TrainingGradsPlanConfig<
+        <AdHocLossModel<T::Model> as ApplyGradients<Autodiff<B>>>::PlanConfig,
+    > = parse_json_file(run.config_dir.join("training")).expect("Expected valid training.json");
+    let mut grads_plan = AdHocLossModel::<T::Model>::config_to_plan(grads_plan.grads_plan);
+    let mut lr_scheduler = run.lr_scheduler;
+    if run.resumed {
+        (model, lr_scheduler) = run.state.load_record::<_, AdHocLossModel<T::Model>, _>(
+            &run.artifact_dir,
+            model,
+            &mut grads_plan,
+            lr_scheduler,
+            device,
+        );
+    } else if let Some(weights) = &run.config.initial_weights {
+        model = weights
+            .load(model, run.config.model_type, device)
+            .unwrap_or_else(|e| panic!("Expected initial weights to match model.json: {e}"));
+    }
+    model = freeze(model, &run.config.freeze)
+        .unwrap_or_else(|e| panic!("Expected frozen submodules to be in the model: {e}"));
+    std::fs::write(
+        run.artifact_dir.join("model.txt"),
+        model.format(DisplaySettings::new()).as_bytes(),
+    )
+    .expect("Expected model.txt to be writable in artifact dir");
+
+    let (mut training_batcher, mut testing_batcher) = task.batchers(&model, device);
+    let config = &run.config;
+    let artifact_dir = &run.artifact_dir;
+    let state = &mut run.state;
+    let viz = &mut run.viz;
+    let ctrlc_pressed = run.ctrlc_pressed;
+    let mut metrics = Metrics::new(&config.metrics);
+    let first_epoch = state.epoch;
+    let mut skip_batch_count = state.batch;
+    // Every epoch has its own RNG derived from the seed, so that resuming reproduces it
+    let seed = state.seed;
+
+    info!(
+        "Initialized in {:.3}s",
+        run.init_start_time.elapsed().as_secs_f32()
+    );
+    let training_start_time = run.clock.now();
+    for epoch in first_epoch..config.num_epochs {
+        let epoch_start_time = run.clock.now();
+        let mut batch_i = skip_batch_count;
+        let mut rng = epoch_rng(seed, epoch);
+        let epoch_seed = rng.random();
+        let mut training_batch_i = skip_batch_count;
+
+        // The order of an interrupted epoch is kept in the dataset
+        if skip_batch_count == 0 {
+            run.training_dataset.shuffle();
+        }
+
+        info!("Training Epoch {epoch}");
+
+        let trainable_model = AdHocLossModel::new(
+            model,
+            |model: &T::Model,
+             batch: T::TrainingBatch,
+             plan: &AdHocTrainingPlan<Autodiff<B>, T::Model>| {
+                B::seed(device, batch_seed(epoch_seed, training_batch_i));
+                training_batch_i += 1;
+                task.training_loss(model, batch, plan)
+            },
+        );
+        model = train_epoch::<Autodiff<B>, _, T::Row, _>(
+            trainable_model,
+            &mut run.training_dataset,
+            config.batch_size,
+            config.training_max_batch_count,
+            config.grad_accumulate_count,
+            skip_batch_count,
+            &mut training_batcher,
+            &mut lr_scheduler,
+            &mut grads_plan,
+            &mut rng,
+            device,
+            |loss, lr| {
+                let loss = loss.into_scalar().elem::<f32>();
+                let value = json!({
+                    "batch_i": batch_i,
+                    "epoch": epoch,
+                    "loss": loss,
+                    "lr": lr
+                });
+                if !viz.send(&value) {
+                    info!("Batch {batch_i}; Loss: {loss:.4}; LR: {lr:.4}");
+                }
+                batch_i += 1;
+                ctrlc_pressed.load(Ordering::Relaxed)
+            },
+        )
+        .unwrap();
+        skip_batch_count = 0;
+
+        model
+            .save_checkpoint(&artifact_dir.join(format!("model-{epoch}.mpk")))
+            .expect("Expected model to be saveable to artifact dir");
+
+        let interrupted = ctrlc_pressed.load(Ordering::Relaxed);
+        state.end_epoch(epoch, batch_i, config.grad_accumulate_count, interrupted);
+        if interrupted {
+            state.save::<_, AdHocLossModel<T::Model>, _>(
+                artifact_dir,
+                &model,
+                &mut grads_plan,
+                &lr_scheduler,
+            );
+            info!("Resume with `proximo resume {}`", artifact_dir.display());
+            break;
+        }
+
+        let valid_model = model.valid();
+        task.before_validation(
+            &valid_model,
+            &mut EpochContext {
+                epoch,
+                artifact_dir,
+                testing_dataset: &run.testing_dataset,
+                rng: &mut rng,
+                viz,
+            },
+        );
+        let mut validatable_model = AdHocLossModel::new(
+            valid_model,
+            |model: &T::ValidModel, batch: T::TestingBatch, metrics: &mut Metrics| {
+                task.validation_loss(model, batch, metrics)
+            },
+        );
+
+        info!("Testing Epoch {epoch}");
+        batch_i = 0;
+        let losses = validate_model::<B, _, T::Row, _>(
+            &mut validatable_model,
+            &mut run.testing_dataset,
+            config.batch_size,
+            config.testing_max_batch_count,
+            &mut testing_batcher,
+            &mut rng,
+            &mut metrics,
+            |loss| {
+                let value = json!({
+                    "batch_i": batch_i,
+                    "epoch": epoch,
+                    "loss": loss,
+                });
+                if !viz.send(&value) {
+                    info!("Batch {batch_i}; Loss: {loss:.4}");
+                }
+                batch_i += 1;
+                ctrlc_pressed.load(Ordering::Relaxed)
+            },
+        );
+        drop(validatable_model);
+        task.after_validation(&mut EpochContext {
+            epoch,
+            artifact_dir,
+            testing_dataset: &run.testing_dataset,
+            rng: &mut rng,
+            viz,
+        });
+        let epoch_duration = epoch_start_time.elapsed();
+        info!(
+            "Epoch Duration: {:.1}s; Remaining: {:.1}s",
+            epoch_duration.as_secs_f32(),
+            training_start_time.elapsed().as_secs_f32()
+                * ((config.num_epochs - epoch - 1) as f32 / (epoch + 1 - first_epoch) as f32)
+        );
+
+        // Validation that was cut short by Ctrl-C doesn't count
+        let value = if ctrlc_pressed.load(Ordering::Relaxed) {
+            None
+        } else {
+            validation_value(&config.checkpoints, &losses, &metrics)
+        };
+        let stop = state.end_validation(artifact_dir, epoch, value, &config.checkpoints);
+        state.save::<_, AdHocLossModel<T::Model>, _>(
+            artifact_dir,
+            &model,
+            &mut grads_plan,
+            &lr_scheduler,
+        );
+        if ctrlc_pressed.load(Ordering::Relaxed) {
+            info!("Resume with `proximo resume {}`", artifact_dir.display());
+            break;
+        }
+        if stop {
+            break;
+        }
+    }
+
+    info!(
+        "Total Duration: {:.1}s",
+        training_start_time.elapsed().as_secs_f32()
+    );
+}
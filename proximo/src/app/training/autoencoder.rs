@@ -0,0 +1,328 @@
+use std::{io::Cursor, sync::Mutex};
+
+use base64::{Engine, prelude::BASE64_STANDARD};
+use burn::{Tensor, backend::Autodiff, prelude::Backend};
+use general_dataset::{
+    StatefulBatcher,
+    presets::autoencoder::{
+        AutoEncoderImageBatch, AutoEncoderImageBatcher, LabeledAutoEncoderImageItem,
+    },
+};
+use general_models::{
+    Init, SimpleTrain,
+    composite::autoencoder::vq::CodebookUsage,
+    loss::{LossConfig, bce_float_loss, mse},
+    metrics::Metrics,
+};
+use image::{
+    ImageBuffer, ImageDecoder, ImageFormat, Luma, Rgb, buffer::ConvertBuffer,
+    codecs::webp::WebPDecoder,
+};
+use rayon::iter::{IndexedParallelIterator, ParallelDrainRange, ParallelIterator};
+use serde_json::json;
+use tracing::info;
+use utils::parse_json_file;
+
+use crate::{
+    app::{
+        config::{DistillationConfig, ImageAutoEncoderChallenge},
+        image_autoencoder_summary,
+        presets::autoencoders::{ImageAutoEncoder, ImageAutoEncoderConfig, ImageAutoEncoderPlan},
+        training::{EpochContext, TrainingRun, TrainingTask, load_teacher},
+        write_summary,
+    },
+    trainable_models::{
+        apply_gradients::AdHocTrainingPlan,
+        vae::{sample_conditional_vae, sample_vae},
+        vq::sample_vq,
+    },
+};
+
+pub struct ImageAutoEncoderTask<B: Backend> {
+    loss_config: LossConfig,
+    teacher: Option<(Mutex<ImageAutoEncoder<B>>, DistillationConfig)>,
+    challenge_image_count: usize,
+    /// Batches the challenge images, which are reconstructed after every epoch.
+    challenge_batcher: AutoEncoderImageBatcher<B>,
+    /// Tracks codebook collapse of vector quantized models.
+    codebook_usage: Option<CodebookUsage>,
+}
+
+impl<B: Backend> TrainingTask<B> for ImageAutoEncoderTask<B> {
+    type Model = ImageAutoEncoder<Autodiff<B>>;
+    type ValidModel = ImageAutoEncoder<B>;
+    type Row = LabeledAutoEncoderImageItem;
+    type TrainingBatch = AutoEncoderImageBatch<Autodiff<B>>;
+    type TestingBatch = AutoEncoderImageBatch<B>;
+    type TrainingBatcher = AutoEncoderImageBatcher<Autodiff<B>>;
+    type TestingBatcher = AutoEncoderImageBatcher<B>;
+
+    fn init(run: &TrainingRun<B>) -> (Self, Self::Model) {
+        let challenge_config: ImageAutoEncoderChallenge =
+            parse_json_file(run.config_dir.join("training")).expect("Expected valid training.json");
+        let model_config: ImageAutoEncoderConfig =
+            parse_json_file(run.config_dir.join("model")).expect("Expected valid model.json");
+        let summary = image_autoencoder_summary(&model_config, &run.training_dataset);
+        write_summary(&run.artifact_dir, &summary);
+        let model: Self::Model = model_config.init(run.device);
+        let teacher = run.config.distillation.clone().map(|distillation| {
+            let teacher = load_teacher::<B, ImageAutoEncoderConfig, _>(&distillation, run.device);
+            (teacher, distillation)
+        });
+        let task = Self {
+            loss_config: run.config.loss.clone().unwrap_or(LossConfig::Bce),
+            teacher,
+            challenge_image_count: challenge_config.challenge_image_count,
+            challenge_batcher: AutoEncoderImageBatcher::new(
+                model.get_input_channels(),
+                run.device.clone(),
+            )
+            .with_num_classes(model.get_num_classes().unwrap_or(0)),
+            codebook_usage: None,
+        };
+        (task, model)
+    }
+
+    fn batchers(
+        &self,
+        model: &Self::Model,
+        device: &B::Device,
+    ) -> (Self::TrainingBatcher, Self::TestingBatcher) {
+        // Conditional models are given the one-hot labels of the images
+        let num_classes = model.get_num_classes().unwrap_or(0);
+        (
+            AutoEncoderImageBatcher::new(model.get_input_channels(), device.clone())
+                .with_num_classes(num_classes),
+            AutoEncoderImageBatcher::new(model.get_input_channels(), device.clone())
+                .with_num_classes(num_classes),
+        )
+    }
+
+    fn training_loss(
+        &self,
+        model: &Self::Model,
+        item: Self::TrainingBatch,
+        plan: &AdHocTrainingPlan<Autodiff<B>, Self::Model>,
+    ) -> Tensor<Autodiff<B>, 1> {
+        let loss_config = &self.loss_config;
+        // item.input = item.input.sub_scalar(0.5);
+        // The teacher runs without autodiff, so that it stays frozen
+        let teacher_outputs = self.teacher.as_ref().map(|(teacher, distillation)| {
+            let teacher = teacher.lock().unwrap();
+            let input = item.input.clone().inner();
+            let latent = (distillation.latent_weight > 0.0).then(|| {
+                let latent = teacher
+                    .encode(input.clone())
+                    .expect("Expected a plain autoencoder teacher for latent distillation");
+                Tensor::from_inner(latent)
+            });
+            let condition = item.condition.clone().map(Tensor::inner);
+            let reconstructed = Tensor::from_inner(teacher.reconstruct(input, condition));
+            (reconstructed, latent, distillation)
+        });
+        let mut student_latent = None;
+        let (reconstructed, loss) = match model {
+            ImageAutoEncoder::Normal(model) => {
+                let latent = model.encoder.train(item.input);
+                student_latent = Some(latent.clone());
+                let reconstructed = model.decoder.train(latent);
+                (
+                    reconstructed.clone(),
+                    loss_config.forward(item.expected, reconstructed),
+                )
+                // MseLoss::new().forward(
+                //     model.train(item.input),
+                //     item.expected,
+                //     Reduction::Auto,
+                // )
+            }
+            ImageAutoEncoder::Sequential(model) => {
+                let reconstructed = model.train(item.input);
+                (
+                    reconstructed.clone(),
+                    loss_config.forward(item.expected, reconstructed),
+                )
+            }
+            ImageAutoEncoder::Vae(model) => {
+                let ImageAutoEncoderPlan::Vae(plan) = plan.plan().expect("Expected VAE grads plan")
+                else {
+                    panic!("Incorrect grads plan");
+                };
+                let (reconstructed, mut kld) = sample_vae(model, item.input);
+                kld = kld * plan.encoder().get_kld_weight();
+                (
+                    reconstructed.clone(),
+                    loss_config.forward(item.expected, reconstructed) + kld,
+                )
+                // bce_float_loss(item.expected, reconstructed) + kld
+            }
+            ImageAutoEncoder::Conditional(model) => {
+                let ImageAutoEncoderPlan::Conditional(plan) =
+                    plan.plan().expect("Expected conditional VAE grads plan")
+                else {
+                    panic!("Incorrect grads plan");
+                };
+                let (reconstructed, kld) = sample_conditional_vae(
+                    model,
+                    item.input,
+                    item.condition
+                        .expect("Expected labeled images for the conditional VAE"),
+                );
+                (
+                    reconstructed.clone(),
+                    loss_config.forward(item.expected, reconstructed)
+                        + kld * plan.encoder().get_kld_weight(),
+                )
+            }
+            ImageAutoEncoder::Vq(model) => {
+                let ImageAutoEncoderPlan::Vq(plan) =
+                    plan.plan().expect("Expected VQ-VAE grads plan")
+                else {
+                    panic!("Incorrect grads plan");
+                };
+                let (reconstructed, quantized) = sample_vq(model, item.input);
+                (
+                    reconstructed.clone(),
+                    loss_config.forward(item.expected, reconstructed)
+                        + quantized.codebook_loss() * plan.encoder().get_codebook_weight()
+                        + quantized.commitment_loss() * plan.encoder().get_commitment_weight(),
+                )
+            }
+        };
+        let Some((teacher_reconstructed, teacher_latent, distillation)) = teacher_outputs else {
+            return loss;
+        };
+        let mut loss =
+            loss + bce_float_loss(teacher_reconstructed, reconstructed) * distillation.weight;
+        if let Some(teacher_latent) = teacher_latent {
+            let student_latent = student_latent
+                .expect("Expected a plain autoencoder student for latent distillation");
+            loss = loss + mse(teacher_latent, student_latent) * distillation.latent_weight;
+        }
+        loss
+    }
+
+    fn validation_loss(
+        &mut self,
+        model: &Self::ValidModel,
+        item: Self::TestingBatch,
+        metrics: &mut Metrics,
+    ) -> Tensor<B, 1> {
+        if let (ImageAutoEncoder::Vq(model), Some(usage)) = (model, &mut self.codebook_usage) {
+            usage.update(model.encoder.encode(item.input.clone()));
+        }
+        let reconstructed = model.reconstruct(item.input, item.condition);
+        metrics.update(item.expected.clone(), reconstructed.clone());
+        self.loss_config.forward(item.expected, reconstructed)
+    }
+
+    fn before_validation(&mut self, model: &Self::ValidModel, epoch: &mut EpochContext) {
+        self.codebook_usage = model.get_num_codes().map(CodebookUsage::new);
+        self.save_challenge_images(model, epoch);
+    }
+
+    fn after_validation(&mut self, epoch: &mut EpochContext) {
+        let Some(usage) = &self.codebook_usage else {
+            return;
+        };
+        let used_codes = usage.used_codes();
+        let perplexity = usage.perplexity();
+        let value = json!({
+            "epoch": epoch.epoch,
+            "used_codes": used_codes,
+            "codebook_perplexity": perplexity,
+        });
+        if !epoch.viz.send(&value) {
+            info!("Used Codes: {used_codes}; Codebook Perplexity: {perplexity:.2}");
+        }
+    }
+}
+
+impl<B: Backend> ImageAutoEncoderTask<B> {
+    /// Reconstructs random images of the testing dataset, which are sent to the viz command or
+    /// saved next to their inputs to `infer-{epoch}.webp`.
+    fn save_challenge_images(&mut self, model: &ImageAutoEncoder<B>, epoch: &mut EpochContext) {
+        let batcher = &mut self.challenge_batcher;
+        StatefulBatcher::<LabeledAutoEncoderImageItem, _>::reset(batcher);
+        let mut input_images = vec![];
+        let mut output_width = 0usize;
+        let mut output_height = 0usize;
+        for _ in 0..self.challenge_image_count {
+            let item: LabeledAutoEncoderImageItem = epoch.testing_dataset.pick_random(epoch.rng);
+            output_width = item.image.expected_width;
+            output_height = item.image.expected_height;
+            input_images.push(item.image.webp_input.clone());
+            batcher.ingest(item);
+        }
+        let batch = StatefulBatcher::<LabeledAutoEncoderImageItem, _>::finish(batcher);
+        let reconstructed = model.reconstruct(batch.input, batch.condition);
+
+        let reconstructed_images: Vec<_> = match model.get_input_channels() {
+            1 => reconstructed
+                .iter_dim(0)
+                .map(|tensor| {
+                    let [_, _, width, height] = tensor.dims();
+                    let buf = tensor.into_data().into_vec::<f32>().unwrap();
+                    let img =
+                        ImageBuffer::<Luma<f32>, _>::from_raw(width as u32, height as u32, buf)
+                            .unwrap();
+                    let img: ImageBuffer<Rgb<u8>, Vec<_>> = img.convert();
+
+                    img
+                })
+                .collect(),
+            _ => todo!(),
+        };
+
+        if epoch.viz.is_some() {
+            let images: Vec<_> = input_images
+                .par_drain(..)
+                .zip(reconstructed_images)
+                .map(|(input, output)| {
+                    let mut output_bytes = vec![];
+                    output
+                        .write_to(&mut Cursor::new(&mut output_bytes), ImageFormat::WebP)
+                        .unwrap();
+                    (
+                        BASE64_STANDARD.encode(input),
+                        BASE64_STANDARD.encode(output_bytes),
+                    )
+                })
+                .collect();
+            epoch.viz.send(&json!({
+                "epoch": epoch.epoch,
+                "challenge_images": images,
+            }));
+        } else {
+            let mosaic_width = output_width as u32 * 2;
+            let mosaic_height = output_height as u32 * self.challenge_image_count as u32;
+            let mut pixels = Vec::with_capacity(mosaic_width as usize * mosaic_height as usize);
+            let mut input_buf = vec![0; output_width * output_height * 3];
+
+            input_images
+                .iter()
+                .zip(reconstructed_images.iter())
+                .for_each(|(input, output)| {
+                    WebPDecoder::new(Cursor::new(input))
+                        .unwrap()
+                        .read_image(&mut input_buf)
+                        .unwrap();
+                    let iter = input_buf
+                        .chunks(output_width * 3)
+                        .zip(output.chunks(output_width * 3));
+                    for (input_row, output_row) in iter {
+                        pixels.extend_from_slice(input_row);
+                        pixels.extend_from_slice(output_row);
+                    }
+                });
+            ImageBuffer::<Rgb<u8>, _>::from_raw(mosaic_width, mosaic_height, pixels)
+                .unwrap()
+                .save(
+                    epoch
+                        .artifact_dir
+                        .join(format!("infer-{}.webp", epoch.epoch)),
+                )
+                .expect("Expected inference image to be saveable");
+        }
+    }
+}
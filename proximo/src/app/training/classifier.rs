@@ -0,0 +1,143 @@
+use std::sync::Mutex;
+
+use burn::{Tensor, backend::Autodiff, prelude::Backend};
+use general_dataset::presets::classifier::{
+    ImageClassifierBatch, ImageClassifierBatcher, ImageClassifierItem,
+};
+use general_models::{
+    Init, SimpleTrain,
+    loss::{LossConfig, distillation_loss},
+    metrics::{ClassificationStats, Metrics},
+};
+use serde_json::json;
+use tracing::info;
+use utils::parse_json_file;
+
+use crate::{
+    app::{
+        config::{DistillationConfig, ImageClassifierValidation},
+        expect_labels_below, image_classifier_summary,
+        presets::classifiers::{ImageClassifier, ImageClassifierConfig},
+        training::{Checkpoint, EpochContext, TrainingRun, TrainingTask, load_teacher},
+        write_summary,
+    },
+    trainable_models::apply_gradients::AdHocTrainingPlan,
+};
+
+impl<B: Backend> Checkpoint<B> for ImageClassifier<B> {}
+
+pub struct ImageClassifierTask<B: Backend> {
+    loss_config: LossConfig,
+    teacher: Option<(Mutex<ImageClassifier<B>>, DistillationConfig)>,
+    /// The accuracy and confusion matrix of the current validation.
+    stats: ClassificationStats,
+}
+
+impl<B: Backend> TrainingTask<B> for ImageClassifierTask<B> {
+    type Model = ImageClassifier<Autodiff<B>>;
+    type ValidModel = ImageClassifier<B>;
+    type Row = ImageClassifierItem;
+    type TrainingBatch = ImageClassifierBatch<Autodiff<B>>;
+    type TestingBatch = ImageClassifierBatch<B>;
+    type TrainingBatcher = ImageClassifierBatcher<Autodiff<B>>;
+    type TestingBatcher = ImageClassifierBatcher<B>;
+
+    fn init(run: &TrainingRun<B>) -> (Self, Self::Model) {
+        let validation_config: ImageClassifierValidation =
+            parse_json_file(run.config_dir.join("training")).expect("Expected valid training.json");
+        let model_config: ImageClassifierConfig =
+            parse_json_file(run.config_dir.join("model")).expect("Expected valid model.json");
+        let summary = image_classifier_summary(&model_config, &run.training_dataset);
+        expect_labels_below(&run.testing_dataset, summary.output_shape[0]);
+        write_summary(&run.artifact_dir, &summary);
+        let model: Self::Model = model_config.init(run.device);
+        let teacher = run.config.distillation.clone().map(|distillation| {
+            let teacher = load_teacher::<B, ImageClassifierConfig, _>(&distillation, run.device);
+            (teacher, distillation)
+        });
+        let task = Self {
+            loss_config: run.config.loss.clone().unwrap_or(LossConfig::CrossEntropy),
+            teacher,
+            stats: ClassificationStats::new(summary.output_shape[0], validation_config.top_k),
+        };
+        (task, model)
+    }
+
+    fn batchers(
+        &self,
+        model: &Self::Model,
+        device: &B::Device,
+    ) -> (Self::TrainingBatcher, Self::TestingBatcher) {
+        let input_channels = model.model.get_input_channels();
+        let num_classes = model.model.get_output_size();
+        (
+            ImageClassifierBatcher::new(input_channels, num_classes, device.clone()),
+            ImageClassifierBatcher::new(input_channels, num_classes, device.clone()),
+        )
+    }
+
+    fn training_loss(
+        &self,
+        model: &Self::Model,
+        item: Self::TrainingBatch,
+        _plan: &AdHocTrainingPlan<Autodiff<B>, Self::Model>,
+    ) -> Tensor<Autodiff<B>, 1> {
+        let Some((teacher, distillation)) = &self.teacher else {
+            return self
+                .loss_config
+                .forward_classes(item.labels, model.train(item.input));
+        };
+        // The teacher runs without autodiff, so that it stays frozen
+        let teacher = teacher.lock().unwrap();
+        let teacher_logits = Tensor::from_inner(teacher.logits(item.input.clone().inner()));
+        let logits = model.train(item.input);
+        self.loss_config
+            .forward_classes(item.labels, logits.clone())
+            + distillation_loss(teacher_logits, logits, distillation.temperature)
+                * distillation.weight
+    }
+
+    fn validation_loss(
+        &mut self,
+        model: &Self::ValidModel,
+        item: Self::TestingBatch,
+        metrics: &mut Metrics,
+    ) -> Tensor<B, 1> {
+        let logits = model.logits(item.input);
+        self.stats.update(logits.clone(), item.labels.clone());
+        metrics.update_classes(logits.clone(), item.labels.clone());
+        self.loss_config.forward_classes(item.labels, logits)
+    }
+
+    fn before_validation(&mut self, _model: &Self::ValidModel, _epoch: &mut EpochContext) {
+        self.stats.reset();
+    }
+
+    fn after_validation(&mut self, epoch: &mut EpochContext) {
+        let stats = &self.stats;
+        let top_k = stats.get_top_k();
+        let accuracy = stats.accuracy();
+        let top_k_accuracy = stats.top_k_accuracy();
+        std::fs::write(
+            epoch
+                .artifact_dir
+                .join(format!("confusion-{}.txt", epoch.epoch)),
+            stats.to_string(),
+        )
+        .expect("Expected confusion matrix to be writable in artifact dir");
+        let value = json!({
+            "epoch": epoch.epoch,
+            "accuracy": accuracy,
+            "top_k": top_k,
+            "top_k_accuracy": top_k_accuracy,
+            "confusion_matrix": stats.confusion_matrix(),
+        });
+        if !epoch.viz.send(&value) {
+            info!(
+                "Accuracy: {:.2}%; Top-{top_k} Accuracy: {:.2}%\n{stats}",
+                accuracy * 100.0,
+                top_k_accuracy * 100.0
+            );
+        }
+    }
+}
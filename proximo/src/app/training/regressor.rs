@@ -0,0 +1,99 @@
+use std::sync::Mutex;
+
+use burn::{Tensor, backend::Autodiff, prelude::Backend};
+use general_dataset::presets::sequence::{SequenceBatch, SequenceBatcher, SequenceItem};
+use general_models::{Init, SimpleInfer, SimpleTrain, loss::LossConfig, metrics::Metrics};
+use utils::parse_json_file;
+
+use crate::{
+    app::{
+        config::DistillationConfig,
+        presets::regressors::{SequenceRegressor, SequenceRegressorConfig},
+        sequence_regressor_summary,
+        training::{Checkpoint, TrainingRun, TrainingTask, load_teacher},
+        write_summary,
+    },
+    trainable_models::apply_gradients::AdHocTrainingPlan,
+};
+
+impl<B: Backend> Checkpoint<B> for SequenceRegressor<B> {}
+
+pub struct SequenceRegressorTask<B: Backend> {
+    loss_config: LossConfig,
+    teacher: Option<(Mutex<SequenceRegressor<B>>, DistillationConfig)>,
+}
+
+impl<B: Backend> TrainingTask<B> for SequenceRegressorTask<B> {
+    type Model = SequenceRegressor<Autodiff<B>>;
+    type ValidModel = SequenceRegressor<B>;
+    type Row = SequenceItem;
+    type TrainingBatch = SequenceBatch<Autodiff<B>>;
+    type TestingBatch = SequenceBatch<B>;
+    type TrainingBatcher = SequenceBatcher<Autodiff<B>>;
+    type TestingBatcher = SequenceBatcher<B>;
+
+    fn init(run: &TrainingRun<B>) -> (Self, Self::Model) {
+        let model_config: SequenceRegressorConfig =
+            parse_json_file(run.config_dir.join("model")).expect("Expected valid model.json");
+        let summary = sequence_regressor_summary(&model_config, &run.training_dataset);
+        write_summary(&run.artifact_dir, &summary);
+        let teacher = run.config.distillation.clone().map(|distillation| {
+            let teacher = load_teacher::<B, SequenceRegressorConfig, _>(&distillation, run.device);
+            (teacher, distillation)
+        });
+        let task = Self {
+            loss_config: run.config.loss.clone().unwrap_or(LossConfig::Mse),
+            teacher,
+        };
+        (task, model_config.init(run.device))
+    }
+
+    fn batchers(
+        &self,
+        model: &Self::Model,
+        device: &B::Device,
+    ) -> (Self::TrainingBatcher, Self::TestingBatcher) {
+        (
+            SequenceBatcher::new(
+                model.get_input_size(),
+                model.get_output_size(),
+                device.clone(),
+            ),
+            SequenceBatcher::new(
+                model.get_input_size(),
+                model.get_output_size(),
+                device.clone(),
+            ),
+        )
+    }
+
+    fn training_loss(
+        &self,
+        model: &Self::Model,
+        item: Self::TrainingBatch,
+        _plan: &AdHocTrainingPlan<Autodiff<B>, Self::Model>,
+    ) -> Tensor<Autodiff<B>, 1> {
+        let Some((teacher, distillation)) = &self.teacher else {
+            return self
+                .loss_config
+                .forward(item.target, model.train(item.input));
+        };
+        // The teacher runs without autodiff, so that it stays frozen
+        let teacher = teacher.lock().unwrap();
+        let teacher_output = Tensor::from_inner(teacher.infer(item.input.clone().inner()));
+        let output = model.train(item.input);
+        self.loss_config.forward(item.target, output.clone())
+            + self.loss_config.forward(teacher_output.detach(), output) * distillation.weight
+    }
+
+    fn validation_loss(
+        &mut self,
+        model: &Self::ValidModel,
+        item: Self::TestingBatch,
+        metrics: &mut Metrics,
+    ) -> Tensor<B, 1> {
+        let output = model.infer(item.input);
+        metrics.update(item.target.clone(), output.clone());
+        self.loss_config.forward(item.target, output)
+    }
+}
@@ -1,16 +1,45 @@
-use burn::{Tensor, module::AutodiffModule, prelude::Backend, tensor::backend::AutodiffBackend};
+use burn::{
+    Tensor,
+    module::{AutodiffModule, ModuleVisitor, Param},
+    prelude::Backend,
+    tensor::backend::AutodiffBackend,
+};
+use general_models::metrics::Metrics;
 
 use crate::trainable_models::apply_gradients::ApplyGradients;
 
 pub mod apply_gradients;
 pub mod vae;
+pub mod vq;
 
 pub trait ValidatableModel<B: Backend, I> {
-    fn batch_valid(&mut self, batch: I) -> Tensor<B, 1>;
+    /// Returns the loss of the batch, after adding its outputs to `metrics`.
+    fn batch_valid(&mut self, batch: I, metrics: &mut Metrics) -> Tensor<B, 1>;
 }
 
 pub trait TrainableModel<B: AutodiffBackend, I>: ApplyGradients<B> {
     fn batch_train(&mut self, batch: I, plan: &Self::Plan) -> Tensor<B, 1>;
+    /// Adds the gradients of the parameters of the model in `grads` to those in `accumulated`,
+    /// so that several batches can be applied in one step.
+    fn accumulate_gradients(&self, accumulated: &mut B::Gradients, grads: &B::Gradients);
+}
+
+struct GradientsSum<'a, B: AutodiffBackend> {
+    accumulated: &'a mut B::Gradients,
+    grads: &'a B::Gradients,
+}
+
+impl<B: AutodiffBackend> ModuleVisitor<B> for GradientsSum<'_, B> {
+    fn visit_float<const D: usize>(&mut self, param: &Param<Tensor<B, D>>) {
+        let Some(grad) = param.grad(self.grads) else {
+            return;
+        };
+        let sum = match param.grad(self.accumulated) {
+            Some(accumulated) => accumulated + grad,
+            None => grad,
+        };
+        param.grad_replace(self.accumulated, sum);
+    }
 }
 
 pub struct AdHocLossModel<M, F = ()> {
@@ -33,11 +62,11 @@ impl<M, F> AdHocLossModel<M, F> {
 
 impl<F, B, I, M> ValidatableModel<B, I> for AdHocLossModel<M, F>
 where
-    F: FnMut(&M, I) -> Tensor<B, 1>,
+    F: FnMut(&M, I, &mut Metrics) -> Tensor<B, 1>,
     B: Backend,
 {
-    fn batch_valid(&mut self, batch: I) -> Tensor<B, 1> {
-        (self.f)(self.model.as_ref().unwrap(), batch)
+    fn batch_valid(&mut self, batch: I, metrics: &mut Metrics) -> Tensor<B, 1> {
+        (self.f)(self.model.as_ref().unwrap(), batch, metrics)
     }
 }
 
@@ -50,4 +79,11 @@ where
     fn batch_train(&mut self, batch: I, plan: &Self::Plan) -> Tensor<B, 1> {
         (self.f)(self.model.as_ref().unwrap(), batch, plan)
     }
+
+    fn accumulate_gradients(&self, accumulated: &mut B::Gradients, grads: &B::Gradients) {
+        self.model
+            .as_ref()
+            .unwrap()
+            .visit(&mut GradientsSum { accumulated, grads });
+    }
 }
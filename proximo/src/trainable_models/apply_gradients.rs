@@ -4,16 +4,20 @@ use utils::default_f;
 
 use crate::trainable_models::{
     AdHocLossModel,
-    apply_gradients::optimizer::{Optimizer, OptimizerConfig},
+    apply_gradients::optimizer::{Optimizer, OptimizerConfig, OptimizerRecord, OptimizerVisitor},
 };
 
 pub mod lr_scheduler;
 pub mod optimizer;
 
 pub mod autoencoder;
+pub mod classifier;
 pub mod conv;
 pub mod image;
 pub mod linear;
+pub mod recurrent;
+pub mod sequence;
+pub mod sequential;
 
 pub trait ApplyGradients<B: AutodiffBackend> {
     type Plan;
@@ -26,6 +30,57 @@ pub trait ApplyGradients<B: AutodiffBackend> {
         grads: &mut <B as AutodiffBackend>::Gradients,
         plan: &mut Self::Plan,
     );
+    /// Visits every optimizer of `plan`, in the same order for plans of the same config.
+    fn visit_optimizers(plan: &mut Self::Plan, visitor: &mut impl OptimizerVisitor<B>);
+}
+
+struct OptimizerSaver<B: AutodiffBackend> {
+    records: Vec<OptimizerRecord<B>>,
+}
+
+impl<B: AutodiffBackend> OptimizerVisitor<B> for OptimizerSaver<B> {
+    fn visit<M: AutodiffModule<B>>(&mut self, optimizer: &mut Optimizer<B, M>) {
+        self.records.push(optimizer.to_record());
+    }
+}
+
+struct OptimizerLoader<B: AutodiffBackend> {
+    records: std::vec::IntoIter<OptimizerRecord<B>>,
+}
+
+impl<B: AutodiffBackend> OptimizerVisitor<B> for OptimizerLoader<B> {
+    fn visit<M: AutodiffModule<B>>(&mut self, optimizer: &mut Optimizer<B, M>) {
+        let record = self
+            .records
+            .next()
+            .expect("Expected a record for every optimizer of the plan");
+        optimizer.load_record(record);
+    }
+}
+
+/// The state of every optimizer of `plan`, such as to resume training.
+pub fn plan_to_record<B: AutodiffBackend, M: ApplyGradients<B>>(
+    plan: &mut M::Plan,
+) -> Vec<OptimizerRecord<B>> {
+    let mut saver = OptimizerSaver { records: vec![] };
+    M::visit_optimizers(plan, &mut saver);
+    saver.records
+}
+
+/// Restores the state of every optimizer of `plan` from [`plan_to_record`] of a plan of the same
+/// config.
+pub fn load_plan_record<B: AutodiffBackend, M: ApplyGradients<B>>(
+    plan: &mut M::Plan,
+    record: Vec<OptimizerRecord<B>>,
+) {
+    let mut loader = OptimizerLoader {
+        records: record.into_iter(),
+    };
+    M::visit_optimizers(plan, &mut loader);
+    assert!(
+        loader.records.next().is_none(),
+        "Expected the plan to have an optimizer for every record"
+    );
 }
 
 // pub trait ApplyAllGradients<B: AutodiffBackend> {
@@ -137,6 +192,13 @@ impl<B: AutodiffBackend, F, M: ApplyGradients<B> + AutodiffModule<B>> ApplyGradi
                 .step(lr, self.model.take().unwrap(), grads),
         );
     }
+
+    fn visit_optimizers(plan: &mut Self::Plan, visitor: &mut impl OptimizerVisitor<B>) {
+        visitor.visit(&mut plan.default_optimizer);
+        if let Some(plan) = &mut plan.plan {
+            M::visit_optimizers(plan, visitor);
+        }
+    }
 }
 
 default_f!(default_lr_multiplier, f64, 1.0);
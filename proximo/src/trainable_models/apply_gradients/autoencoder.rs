@@ -1,15 +1,24 @@
-use burn::tensor::backend::AutodiffBackend;
+use burn::{optim::GradientsParams, tensor::backend::AutodiffBackend};
 use general_models::{
-    composite::autoencoder::{AutoEncoderModel, vae::VariationalEncoderModel},
+    composite::autoencoder::{
+        AutoEncoderModel,
+        cvae::ConditionalVaeModel,
+        vae::VariationalEncoderModel,
+        vq::{VectorQuantizedEncoderModel, VectorQuantizer},
+    },
     linear::LinearModel,
 };
 use serde::{Deserialize, Serialize};
+use utils::default_f;
 
 use crate::trainable_models::apply_gradients::{
     ApplyGradients,
     linear::{LinearModelPlan, LinearModelPlanConfig},
+    optimizer::{Optimizer, OptimizerConfig, OptimizerVisitor},
 };
 
+use super::default_lr_multiplier;
+
 pub struct AutoEncoderModelPlan<E, D> {
     encoder: E,
     decoder: D,
@@ -47,6 +56,11 @@ impl<B: AutodiffBackend, E: ApplyGradients<B>, D: ApplyGradients<B>> ApplyGradie
         self.decoder.apply_gradients(lr, grads, &mut plan.decoder);
     }
 
+    fn visit_optimizers(plan: &mut Self::Plan, visitor: &mut impl OptimizerVisitor<B>) {
+        E::visit_optimizers(&mut plan.encoder, visitor);
+        D::visit_optimizers(&mut plan.decoder, visitor);
+    }
+
     fn config_to_plan(config: Self::PlanConfig) -> Self::Plan {
         AutoEncoderModelPlan {
             encoder: E::config_to_plan(config.encoder),
@@ -91,6 +105,12 @@ impl<B: AutodiffBackend, T: ApplyGradients<B>> ApplyGradients<B> for Variational
         self.logvar.apply_gradients(lr, grads, &mut plan.logvar);
     }
 
+    fn visit_optimizers(plan: &mut Self::Plan, visitor: &mut impl OptimizerVisitor<B>) {
+        T::visit_optimizers(&mut plan.model, visitor);
+        LinearModel::visit_optimizers(&mut plan.mean, visitor);
+        LinearModel::visit_optimizers(&mut plan.logvar, visitor);
+    }
+
     fn config_to_plan(config: Self::PlanConfig) -> Self::Plan {
         VariationalEncoderModelPlan {
             model: T::config_to_plan(config.model),
@@ -101,6 +121,135 @@ impl<B: AutodiffBackend, T: ApplyGradients<B>> ApplyGradients<B> for Variational
     }
 }
 
+pub struct ConditionalVaeModelPlan<B: AutodiffBackend, M, D> {
+    encoder: VariationalEncoderModelPlan<B, M>,
+    decoder: D,
+    condition: LinearModelPlan<B>,
+}
+
+impl<B: AutodiffBackend, M, D> ConditionalVaeModelPlan<B, M, D> {
+    pub fn encoder(&self) -> &VariationalEncoderModelPlan<B, M> {
+        &self.encoder
+    }
+
+    pub fn decoder(&self) -> &D {
+        &self.decoder
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ConditionalVaeModelPlanConfig<M, D> {
+    pub encoder: VariationalEncoderModelPlanConfig<M>,
+    pub decoder: D,
+    pub condition: LinearModelPlanConfig,
+}
+
+impl<B, M, D> ApplyGradients<B> for ConditionalVaeModel<B, M, D>
+where
+    B: AutodiffBackend,
+    M: ApplyGradients<B>,
+    D: ApplyGradients<B>,
+{
+    type Plan = ConditionalVaeModelPlan<B, M::Plan, D::Plan>;
+    type PlanConfig = ConditionalVaeModelPlanConfig<M::PlanConfig, D::PlanConfig>;
+
+    fn apply_gradients(
+        &mut self,
+        lr: f64,
+        grads: &mut <B as AutodiffBackend>::Gradients,
+        plan: &mut Self::Plan,
+    ) {
+        self.encoder.apply_gradients(lr, grads, &mut plan.encoder);
+        self.decoder.apply_gradients(lr, grads, &mut plan.decoder);
+        self.condition
+            .apply_gradients(lr, grads, &mut plan.condition);
+    }
+
+    fn visit_optimizers(plan: &mut Self::Plan, visitor: &mut impl OptimizerVisitor<B>) {
+        VariationalEncoderModel::<B, M>::visit_optimizers(&mut plan.encoder, visitor);
+        D::visit_optimizers(&mut plan.decoder, visitor);
+        LinearModel::visit_optimizers(&mut plan.condition, visitor);
+    }
+
+    fn config_to_plan(config: Self::PlanConfig) -> Self::Plan {
+        ConditionalVaeModelPlan {
+            encoder: VariationalEncoderModel::<B, M>::config_to_plan(config.encoder),
+            decoder: D::config_to_plan(config.decoder),
+            condition: LinearModel::config_to_plan(config.condition),
+        }
+    }
+}
+
+pub struct VectorQuantizedEncoderModelPlan<B: AutodiffBackend, T> {
+    model: T,
+    codebook_optim: Optimizer<B, VectorQuantizer<B>>,
+    codebook_lr_multiplier: f64,
+    codebook_weight: f64,
+    commitment_weight: f64,
+}
+
+impl<B: AutodiffBackend, T> VectorQuantizedEncoderModelPlan<B, T> {
+    pub fn get_codebook_weight(&self) -> f64 {
+        self.codebook_weight
+    }
+
+    pub fn get_commitment_weight(&self) -> f64 {
+        self.commitment_weight
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VectorQuantizedEncoderModelPlanConfig<T> {
+    pub model: T,
+    pub codebook_optim: OptimizerConfig,
+    #[serde(default = "default_lr_multiplier")]
+    pub codebook_lr_multiplier: f64,
+    #[serde(default = "default_codebook_weight")]
+    pub codebook_weight: f64,
+    #[serde(default = "default_commitment_weight")]
+    pub commitment_weight: f64,
+}
+
+impl<B: AutodiffBackend, T: ApplyGradients<B>> ApplyGradients<B>
+    for VectorQuantizedEncoderModel<B, T>
+{
+    type Plan = VectorQuantizedEncoderModelPlan<B, T::Plan>;
+    type PlanConfig = VectorQuantizedEncoderModelPlanConfig<T::PlanConfig>;
+
+    fn apply_gradients(
+        &mut self,
+        lr: f64,
+        grads: &mut <B as AutodiffBackend>::Gradients,
+        plan: &mut Self::Plan,
+    ) {
+        self.model.apply_gradients(lr, grads, &mut plan.model);
+        let grad_params = GradientsParams::from_module(grads, &self.quantizer);
+        self.quantizer = plan.codebook_optim.step(
+            lr * plan.codebook_lr_multiplier,
+            self.quantizer.clone(),
+            grad_params,
+        );
+    }
+
+    fn visit_optimizers(plan: &mut Self::Plan, visitor: &mut impl OptimizerVisitor<B>) {
+        T::visit_optimizers(&mut plan.model, visitor);
+        visitor.visit(&mut plan.codebook_optim);
+    }
+
+    fn config_to_plan(config: Self::PlanConfig) -> Self::Plan {
+        VectorQuantizedEncoderModelPlan {
+            model: T::config_to_plan(config.model),
+            codebook_optim: config.codebook_optim.init(),
+            codebook_lr_multiplier: config.codebook_lr_multiplier,
+            codebook_weight: config.codebook_weight,
+            commitment_weight: config.commitment_weight,
+        }
+    }
+}
+
+default_f!(default_codebook_weight, f64, 1.0);
+default_f!(default_commitment_weight, f64, 0.25);
+
 // pub struct VariationalEncoderModelTrainingConfig {
 //     pub kld_weight: f64,
 // }
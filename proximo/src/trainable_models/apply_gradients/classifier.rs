@@ -0,0 +1,27 @@
+use burn::tensor::backend::AutodiffBackend;
+use general_models::composite::classifier::ClassifierModel;
+
+use crate::trainable_models::apply_gradients::{ApplyGradients, optimizer::OptimizerVisitor};
+
+/// The softmax head has no parameters, so a classifier is trained with the plan of its model.
+impl<B: AutodiffBackend, M: ApplyGradients<B>> ApplyGradients<B> for ClassifierModel<B, M> {
+    type Plan = M::Plan;
+    type PlanConfig = M::PlanConfig;
+
+    fn config_to_plan(config: Self::PlanConfig) -> Self::Plan {
+        M::config_to_plan(config)
+    }
+
+    fn apply_gradients(
+        &mut self,
+        lr: f64,
+        grads: &mut <B as AutodiffBackend>::Gradients,
+        plan: &mut Self::Plan,
+    ) {
+        self.model.apply_gradients(lr, grads, plan);
+    }
+
+    fn visit_optimizers(plan: &mut Self::Plan, visitor: &mut impl OptimizerVisitor<B>) {
+        M::visit_optimizers(plan, visitor);
+    }
+}
@@ -10,7 +10,7 @@ use serde::{Deserialize, Serialize};
 
 use super::ApplyGradients;
 
-use crate::trainable_models::apply_gradients::optimizer::OptimizerConfig;
+use crate::trainable_models::apply_gradients::optimizer::{OptimizerConfig, OptimizerVisitor};
 
 use general_models::{
     common::Norm,
@@ -100,6 +100,20 @@ impl<B: AutodiffBackend> ApplyGradients<B> for Conv2dModel<B> {
 
             (linear, norm, activation)
         });
+        self.iter_residuals(|projection| {
+            let grad_params = GradientsParams::from_module(grads, &projection);
+            plan.weights_optim
+                .step(lr * plan.weights_lr_multiplier, projection, grad_params)
+        });
+    }
+
+    fn visit_optimizers(plan: &mut Self::Plan, visitor: &mut impl OptimizerVisitor<B>) {
+        visitor.visit(&mut plan.weights_optim);
+        if let Some(bias_optim) = &mut plan.bias_optim {
+            visitor.visit(bias_optim);
+        }
+        visitor.visit(&mut plan.norm_optim);
+        visitor.visit(&mut plan.activation_optim);
     }
 
     fn config_to_plan(config: Self::PlanConfig) -> Self::Plan {
@@ -189,6 +203,15 @@ impl<B: AutodiffBackend> ApplyGradients<B> for ConvTranspose2dModel<B> {
         });
     }
 
+    fn visit_optimizers(plan: &mut Self::Plan, visitor: &mut impl OptimizerVisitor<B>) {
+        visitor.visit(&mut plan.weights_optim);
+        if let Some(bias_optim) = &mut plan.bias_optim {
+            visitor.visit(bias_optim);
+        }
+        visitor.visit(&mut plan.norm_optim);
+        visitor.visit(&mut plan.activation_optim);
+    }
+
     fn config_to_plan(config: Self::PlanConfig) -> Self::Plan {
         let config = config.0;
         ConvTranspose2dModelPlan {
@@ -13,6 +13,7 @@ use crate::trainable_models::apply_gradients::{
         ConvTranspose2dModelPlanConfig,
     },
     linear::{LinearModelPlan, LinearModelPlanConfig},
+    optimizer::OptimizerVisitor,
 };
 
 pub struct Conv2dLinearModelPlan<B: AutodiffBackend> {
@@ -46,6 +47,11 @@ impl<B: AutodiffBackend> ApplyGradients<B> for Conv2dLinearModel<B> {
         self.conv.apply_gradients(lr, grads, &mut plan.conv);
         self.linear.apply_gradients(lr, grads, &mut plan.linear);
     }
+
+    fn visit_optimizers(plan: &mut Self::Plan, visitor: &mut impl OptimizerVisitor<B>) {
+        Conv2dModel::visit_optimizers(&mut plan.conv, visitor);
+        LinearModel::visit_optimizers(&mut plan.linear, visitor);
+    }
 }
 
 pub struct LinearConvTranspose2dModelPlan<B: AutodiffBackend> {
@@ -79,4 +85,9 @@ impl<B: AutodiffBackend> ApplyGradients<B> for LinearConvTranspose2dModel<B> {
         self.conv.apply_gradients(lr, grads, &mut plan.conv);
         self.linear.apply_gradients(lr, grads, &mut plan.linear);
     }
+
+    fn visit_optimizers(plan: &mut Self::Plan, visitor: &mut impl OptimizerVisitor<B>) {
+        LinearModel::visit_optimizers(&mut plan.linear, visitor);
+        ConvTranspose2dModel::visit_optimizers(&mut plan.conv, visitor);
+    }
 }
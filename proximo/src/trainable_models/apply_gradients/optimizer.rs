@@ -1,27 +1,159 @@
+use std::collections::HashMap;
+
 use burn::{
     grad_clipping::GradientClippingConfig,
-    module::AutodiffModule,
+    module::{AutodiffModule, ParamId},
     optim::{
-        Adam, AdamConfig, GradientsParams, Optimizer as BurnOptimizer, adaptor::OptimizerAdaptor,
+        AdaGrad, AdaGradConfig, Adam, AdamConfig, AdamW, AdamWConfig, GradientsParams,
+        Optimizer as BurnOptimizer, RmsProp, RmsPropConfig, Sgd, SgdConfig, SimpleOptimizer,
+        adaptor::OptimizerAdaptor,
         decay::WeightDecayConfig,
+        momentum::MomentumConfig,
+        record::{AdaptorRecord, AdaptorRecordItem},
     },
+    record::{PrecisionSettings, Record},
     tensor::backend::AutodiffBackend,
 };
 use serde::{Deserialize, Serialize};
 use utils::default_f;
 
+use self::lion::Lion;
+
+pub mod lion;
+
 pub enum Optimizer<B: AutodiffBackend, M: AutodiffModule<B>> {
     Adam(OptimizerAdaptor<Adam, M, B>),
+    Sgd(OptimizerAdaptor<Sgd<B::InnerBackend>, M, B>),
+    AdamW(OptimizerAdaptor<AdamW, M, B>),
+    RmsProp(OptimizerAdaptor<RmsProp, M, B>),
+    AdaGrad(OptimizerAdaptor<AdaGrad, M, B>),
+    Lion(OptimizerAdaptor<Lion, M, B>),
 }
 
 impl<B: AutodiffBackend, M: AutodiffModule<B>> Optimizer<B, M> {
     pub fn step(&mut self, lr: f64, module: M, grads: GradientsParams) -> M {
         match self {
             Optimizer::Adam(optimizer_adaptor) => optimizer_adaptor.step(lr, module, grads),
+            Optimizer::Sgd(optimizer_adaptor) => optimizer_adaptor.step(lr, module, grads),
+            Optimizer::AdamW(optimizer_adaptor) => optimizer_adaptor.step(lr, module, grads),
+            Optimizer::RmsProp(optimizer_adaptor) => optimizer_adaptor.step(lr, module, grads),
+            Optimizer::AdaGrad(optimizer_adaptor) => optimizer_adaptor.step(lr, module, grads),
+            Optimizer::Lion(optimizer_adaptor) => optimizer_adaptor.step(lr, module, grads),
+        }
+    }
+
+    pub fn to_record(&self) -> OptimizerRecord<B> {
+        match self {
+            Optimizer::Adam(x) => OptimizerRecord::Adam(x.to_record().into_iter().collect()),
+            Optimizer::Sgd(x) => OptimizerRecord::Sgd(x.to_record().into_iter().collect()),
+            Optimizer::AdamW(x) => OptimizerRecord::AdamW(x.to_record().into_iter().collect()),
+            Optimizer::RmsProp(x) => OptimizerRecord::RmsProp(x.to_record().into_iter().collect()),
+            Optimizer::AdaGrad(x) => OptimizerRecord::AdaGrad(x.to_record().into_iter().collect()),
+            Optimizer::Lion(x) => OptimizerRecord::Lion(x.to_record().into_iter().collect()),
+        }
+    }
+
+    pub fn load_record(&mut self, record: OptimizerRecord<B>) {
+        macro_rules! load {
+            ($x: ident, $record: ident) => {
+                *$x = $x.clone().load_record($record.into_iter().collect())
+            };
+        }
+        match (self, record) {
+            (Optimizer::Adam(x), OptimizerRecord::Adam(record)) => load!(x, record),
+            (Optimizer::Sgd(x), OptimizerRecord::Sgd(record)) => load!(x, record),
+            (Optimizer::AdamW(x), OptimizerRecord::AdamW(record)) => load!(x, record),
+            (Optimizer::RmsProp(x), OptimizerRecord::RmsProp(record)) => load!(x, record),
+            (Optimizer::AdaGrad(x), OptimizerRecord::AdaGrad(record)) => load!(x, record),
+            (Optimizer::Lion(x), OptimizerRecord::Lion(record)) => load!(x, record),
+            _ => panic!("Unexpected record for Optimizer"),
+        }
+    }
+}
+
+/// Visits every optimizer of a plan, such as to save their state.
+pub trait OptimizerVisitor<B: AutodiffBackend> {
+    fn visit<M: AutodiffModule<B>>(&mut self, optimizer: &mut Optimizer<B, M>);
+}
+
+/// The state of an [`Optimizer`] for each of its parameters, such as the moments of Adam.
+pub enum OptimizerRecord<B: AutodiffBackend> {
+    Adam(HashMap<ParamId, AdaptorRecord<Adam, B>>),
+    Sgd(HashMap<ParamId, AdaptorRecord<Sgd<B::InnerBackend>, B>>),
+    AdamW(HashMap<ParamId, AdaptorRecord<AdamW, B>>),
+    RmsProp(HashMap<ParamId, AdaptorRecord<RmsProp, B>>),
+    AdaGrad(HashMap<ParamId, AdaptorRecord<AdaGrad, B>>),
+    Lion(HashMap<ParamId, AdaptorRecord<Lion, B>>),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub enum OptimizerRecordItem<B: AutodiffBackend, S: PrecisionSettings> {
+    Adam(HashMap<String, AdaptorRecordItem<Adam, B, S>>),
+    Sgd(HashMap<String, AdaptorRecordItem<Sgd<B::InnerBackend>, B, S>>),
+    AdamW(HashMap<String, AdaptorRecordItem<AdamW, B, S>>),
+    RmsProp(HashMap<String, AdaptorRecordItem<RmsProp, B, S>>),
+    AdaGrad(HashMap<String, AdaptorRecordItem<AdaGrad, B, S>>),
+    Lion(HashMap<String, AdaptorRecordItem<Lion, B, S>>),
+}
+
+/// The records of the parameters of an optimizer, keyed by their serialized ids.
+fn into_items<O, B, S>(
+    records: HashMap<ParamId, AdaptorRecord<O, B>>,
+) -> HashMap<String, AdaptorRecordItem<O, B, S>>
+where
+    O: SimpleOptimizer<B::InnerBackend>,
+    B: AutodiffBackend,
+    S: PrecisionSettings,
+{
+    records
+        .into_iter()
+        .map(|(id, record)| (id.serialize(), record.into_item()))
+        .collect()
+}
+
+fn from_items<O, B, S>(
+    items: HashMap<String, AdaptorRecordItem<O, B, S>>,
+    device: &B::Device,
+) -> HashMap<ParamId, AdaptorRecord<O, B>>
+where
+    O: SimpleOptimizer<B::InnerBackend>,
+    B: AutodiffBackend,
+    S: PrecisionSettings,
+{
+    items
+        .into_iter()
+        .map(|(id, item)| (ParamId::deserialize(&id), Record::from_item(item, device)))
+        .collect()
+}
+
+impl<B: AutodiffBackend> Record<B> for OptimizerRecord<B> {
+    type Item<S: PrecisionSettings> = OptimizerRecordItem<B, S>;
+
+    fn into_item<S: PrecisionSettings>(self) -> Self::Item<S> {
+        match self {
+            OptimizerRecord::Adam(x) => OptimizerRecordItem::Adam(into_items(x)),
+            OptimizerRecord::Sgd(x) => OptimizerRecordItem::Sgd(into_items(x)),
+            OptimizerRecord::AdamW(x) => OptimizerRecordItem::AdamW(into_items(x)),
+            OptimizerRecord::RmsProp(x) => OptimizerRecordItem::RmsProp(into_items(x)),
+            OptimizerRecord::AdaGrad(x) => OptimizerRecordItem::AdaGrad(into_items(x)),
+            OptimizerRecord::Lion(x) => OptimizerRecordItem::Lion(into_items(x)),
+        }
+    }
+
+    fn from_item<S: PrecisionSettings>(item: Self::Item<S>, device: &B::Device) -> Self {
+        match item {
+            OptimizerRecordItem::Adam(x) => OptimizerRecord::Adam(from_items(x, device)),
+            OptimizerRecordItem::Sgd(x) => OptimizerRecord::Sgd(from_items(x, device)),
+            OptimizerRecordItem::AdamW(x) => OptimizerRecord::AdamW(from_items(x, device)),
+            OptimizerRecordItem::RmsProp(x) => OptimizerRecord::RmsProp(from_items(x, device)),
+            OptimizerRecordItem::AdaGrad(x) => OptimizerRecord::AdaGrad(from_items(x, device)),
+            OptimizerRecordItem::Lion(x) => OptimizerRecord::Lion(from_items(x, device)),
         }
     }
 }
 
+/// The optimizers of a grads plan, whose defaults are those of burn.
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "snake_case")]
 pub enum OptimizerConfig {
@@ -35,26 +167,163 @@ pub enum OptimizerConfig {
         weight_decay: Option<f32>,
         grad_clipping: Option<GradientClippingConfig>,
     },
+    /// Stochastic gradient descent, with momentum if `momentum` is set.
+    Sgd {
+        momentum: Option<f64>,
+        /// Scales down the gradient that is added to the momentum, by `1 - dampening`.
+        #[serde(default = "default_dampening")]
+        dampening: f64,
+        #[serde(default)]
+        nesterov: bool,
+        weight_decay: Option<f32>,
+        grad_clipping: Option<GradientClippingConfig>,
+    },
+    /// Adam with weight decay that is decoupled from the gradients.
+    #[serde(rename = "adamw")]
+    AdamW {
+        #[serde(default = "default_beta_1")]
+        beta_1: f32,
+        #[serde(default = "default_beta_2")]
+        beta_2: f32,
+        #[serde(default = "default_eps")]
+        eps: f32,
+        #[serde(default = "default_adamw_weight_decay")]
+        weight_decay: f32,
+        grad_clipping: Option<GradientClippingConfig>,
+    },
+    #[serde(rename = "rmsprop")]
+    RmsProp {
+        /// The smoothing of the average of the squared gradients.
+        #[serde(default = "default_rmsprop_alpha")]
+        alpha: f32,
+        #[serde(default = "default_rmsprop_momentum")]
+        momentum: f32,
+        #[serde(default = "default_eps")]
+        eps: f32,
+        /// Normalizes the gradients by their variance rather than by their second moment.
+        #[serde(default)]
+        centered: bool,
+        weight_decay: Option<f32>,
+        grad_clipping: Option<GradientClippingConfig>,
+    },
+    #[serde(rename = "adagrad")]
+    AdaGrad {
+        #[serde(default)]
+        lr_decay: f64,
+        #[serde(default = "default_eps")]
+        eps: f32,
+        weight_decay: Option<f32>,
+        grad_clipping: Option<GradientClippingConfig>,
+    },
+    /// See [`lion`].
+    Lion {
+        #[serde(default = "default_beta_1")]
+        beta_1: f32,
+        #[serde(default = "default_lion_beta_2")]
+        beta_2: f32,
+        #[serde(default)]
+        weight_decay: f32,
+        grad_clipping: Option<GradientClippingConfig>,
+    },
 }
 
 impl OptimizerConfig {
     pub fn init<B: AutodiffBackend, M: AutodiffModule<B>>(self) -> Optimizer<B, M> {
+        let weight_decay = |x: Option<f32>| x.map(|penalty| WeightDecayConfig { penalty });
         match self {
             OptimizerConfig::Adam {
                 beta_1,
                 beta_2,
                 eps,
-                weight_decay,
+                weight_decay: penalty,
                 grad_clipping,
             } => Optimizer::Adam(
                 AdamConfig::new()
                     .with_beta_1(beta_1)
                     .with_beta_2(beta_2)
                     .with_epsilon(eps)
-                    .with_weight_decay(weight_decay.map(|penalty| WeightDecayConfig { penalty }))
+                    .with_weight_decay(weight_decay(penalty))
                     .with_grad_clipping(grad_clipping)
                     .init(),
             ),
+            OptimizerConfig::Sgd {
+                momentum,
+                dampening,
+                nesterov,
+                weight_decay: penalty,
+                grad_clipping,
+            } => Optimizer::Sgd(
+                SgdConfig::new()
+                    .with_momentum(momentum.map(|momentum| MomentumConfig {
+                        momentum,
+                        dampening,
+                        nesterov,
+                    }))
+                    .with_weight_decay(weight_decay(penalty))
+                    .with_gradient_clipping(grad_clipping)
+                    .init(),
+            ),
+            OptimizerConfig::AdamW {
+                beta_1,
+                beta_2,
+                eps,
+                weight_decay,
+                grad_clipping,
+            } => Optimizer::AdamW(
+                AdamWConfig::new()
+                    .with_beta_1(beta_1)
+                    .with_beta_2(beta_2)
+                    .with_epsilon(eps)
+                    .with_weight_decay(weight_decay)
+                    .with_grad_clipping(grad_clipping)
+                    .init(),
+            ),
+            OptimizerConfig::RmsProp {
+                alpha,
+                momentum,
+                eps,
+                centered,
+                weight_decay: penalty,
+                grad_clipping,
+            } => Optimizer::RmsProp(
+                RmsPropConfig::new()
+                    .with_alpha(alpha)
+                    .with_momentum(momentum)
+                    .with_epsilon(eps)
+                    .with_centered(centered)
+                    .with_weight_decay(weight_decay(penalty))
+                    .with_grad_clipping(grad_clipping)
+                    .init(),
+            ),
+            OptimizerConfig::AdaGrad {
+                lr_decay,
+                eps,
+                weight_decay: penalty,
+                grad_clipping,
+            } => Optimizer::AdaGrad(
+                AdaGradConfig::new()
+                    .with_lr_decay(lr_decay)
+                    .with_epsilon(eps)
+                    .with_weight_decay(weight_decay(penalty))
+                    .with_grad_clipping(grad_clipping)
+                    .init(),
+            ),
+            OptimizerConfig::Lion {
+                beta_1,
+                beta_2,
+                weight_decay,
+                grad_clipping,
+            } => {
+                let mut optimizer = OptimizerAdaptor::from(Lion {
+                    beta_1,
+                    beta_2,
+                    weight_decay,
+                });
+                if let Some(config) = grad_clipping {
+                    optimizer = optimizer.with_grad_clipping(config.init());
+                }
+                Optimizer::Lion(optimizer)
+            }
         }
     }
 }
@@ -62,3 +331,8 @@ impl OptimizerConfig {
 default_f!(default_beta_1, f32, 0.9);
 default_f!(default_beta_2, f32, 0.999);
 default_f!(default_eps, f32, 1e-5);
+default_f!(default_dampening, f64, 0.1);
+default_f!(default_adamw_weight_decay, f32, 1e-4);
+default_f!(default_rmsprop_alpha, f32, 0.99);
+default_f!(default_rmsprop_momentum, f32, 0.9);
+default_f!(default_lion_beta_2, f32, 0.99);
@@ -0,0 +1,99 @@
+use burn::{
+    nn::{activation::Activation, gru::Gru},
+    optim::GradientsParams,
+    tensor::backend::AutodiffBackend,
+};
+use general_models::{common::Norm, recurrent::grus::GruModel};
+use serde::{Deserialize, Serialize};
+
+use crate::trainable_models::apply_gradients::{
+    ApplyGradients,
+    optimizer::{Optimizer, OptimizerConfig, OptimizerVisitor},
+};
+
+use super::default_lr_multiplier;
+
+pub struct GruModelPlan<B: AutodiffBackend> {
+    weights_optim: Optimizer<B, Gru<B>>,
+    norm_optim: Optimizer<B, Norm<B>>,
+    activation_optim: Optimizer<B, Activation<B>>,
+    weights_lr_multiplier: f64,
+    norm_lr_multiplier: f64,
+    activation_lr_multiplier: f64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GruModelPlanConfig {
+    /// Steps the weights and biases of every gate, in both directions for bidirectional layers.
+    pub weights_optim: OptimizerConfig,
+    pub norm_optim: Option<OptimizerConfig>,
+    pub activation_optim: Option<OptimizerConfig>,
+    #[serde(default = "default_lr_multiplier")]
+    pub weights_lr_multiplier: f64,
+    #[serde(default = "default_lr_multiplier")]
+    pub norm_lr_multiplier: f64,
+    #[serde(default = "default_lr_multiplier")]
+    pub activation_lr_multiplier: f64,
+}
+
+impl<B: AutodiffBackend> ApplyGradients<B> for GruModel<B> {
+    type Plan = GruModelPlan<B>;
+    type PlanConfig = GruModelPlanConfig;
+
+    fn apply_gradients(
+        &mut self,
+        lr: f64,
+        grads: &mut <B as AutodiffBackend>::Gradients,
+        plan: &mut Self::Plan,
+    ) {
+        self.iter_layers(|gru, reverse, norm, activation| {
+            let mut step_gru = |gru: Gru<B>| {
+                let grad_params = GradientsParams::from_module(grads, &gru);
+                plan.weights_optim
+                    .step(lr * plan.weights_lr_multiplier, gru, grad_params)
+            };
+            let gru = step_gru(gru);
+            let reverse = reverse.map(step_gru);
+
+            let norm = norm.map(|norm| {
+                let grad_params = GradientsParams::from_module(grads, &norm);
+                plan.norm_optim
+                    .step(lr * plan.norm_lr_multiplier, norm, grad_params)
+            });
+
+            let activation = activation.map(|activation| {
+                let grad_params = GradientsParams::from_module(grads, &activation);
+                plan.activation_optim.step(
+                    lr * plan.activation_lr_multiplier,
+                    activation,
+                    grad_params,
+                )
+            });
+
+            (gru, reverse, norm, activation)
+        });
+    }
+
+    fn visit_optimizers(plan: &mut Self::Plan, visitor: &mut impl OptimizerVisitor<B>) {
+        visitor.visit(&mut plan.weights_optim);
+        visitor.visit(&mut plan.norm_optim);
+        visitor.visit(&mut plan.activation_optim);
+    }
+
+    fn config_to_plan(config: Self::PlanConfig) -> Self::Plan {
+        GruModelPlan {
+            norm_optim: config
+                .norm_optim
+                .unwrap_or_else(|| config.weights_optim.clone())
+                .init(),
+            activation_optim: config
+                .activation_optim
+                .unwrap_or_else(|| config.weights_optim.clone())
+                .init(),
+            weights_optim: config.weights_optim.init(),
+            weights_lr_multiplier: config.weights_lr_multiplier,
+            norm_lr_multiplier: config.norm_lr_multiplier,
+            activation_lr_multiplier: config.activation_lr_multiplier,
+        }
+    }
+}
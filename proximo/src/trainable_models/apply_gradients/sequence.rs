@@ -0,0 +1,50 @@
+use burn::tensor::backend::AutodiffBackend;
+use general_models::{
+    composite::sequence::GruLinearModel, linear::LinearModel, recurrent::grus::GruModel,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::trainable_models::apply_gradients::{
+    ApplyGradients,
+    linear::{LinearModelPlan, LinearModelPlanConfig},
+    optimizer::OptimizerVisitor,
+    recurrent::{GruModelPlan, GruModelPlanConfig},
+};
+
+pub struct GruLinearModelPlan<B: AutodiffBackend> {
+    gru: GruModelPlan<B>,
+    linear: LinearModelPlan<B>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GruLinearModelPlanConfig {
+    pub gru: GruModelPlanConfig,
+    pub linear: LinearModelPlanConfig,
+}
+
+impl<B: AutodiffBackend> ApplyGradients<B> for GruLinearModel<B> {
+    type Plan = GruLinearModelPlan<B>;
+    type PlanConfig = GruLinearModelPlanConfig;
+
+    fn config_to_plan(config: Self::PlanConfig) -> Self::Plan {
+        GruLinearModelPlan {
+            gru: GruModel::config_to_plan(config.gru),
+            linear: LinearModel::config_to_plan(config.linear),
+        }
+    }
+
+    fn apply_gradients(
+        &mut self,
+        lr: f64,
+        grads: &mut <B as AutodiffBackend>::Gradients,
+        plan: &mut Self::Plan,
+    ) {
+        self.gru.apply_gradients(lr, grads, &mut plan.gru);
+        self.linear.apply_gradients(lr, grads, &mut plan.linear);
+    }
+
+    fn visit_optimizers(plan: &mut Self::Plan, visitor: &mut impl OptimizerVisitor<B>) {
+        GruModel::visit_optimizers(&mut plan.gru, visitor);
+        LinearModel::visit_optimizers(&mut plan.linear, visitor);
+    }
+}
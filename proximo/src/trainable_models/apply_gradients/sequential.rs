@@ -0,0 +1,88 @@
+use burn::tensor::backend::AutodiffBackend;
+use general_models::{
+    conv::{Conv2dModel, ConvTranspose2dModel},
+    linear::LinearModel,
+    sequential::{Block, SequentialModel},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::trainable_models::apply_gradients::{
+    ApplyGradients,
+    conv::{
+        Conv2dModelPlan, Conv2dModelPlanConfig, ConvTranspose2dModelPlan,
+        ConvTranspose2dModelPlanConfig,
+    },
+    linear::{LinearModelPlan, LinearModelPlanConfig},
+    optimizer::OptimizerVisitor,
+};
+
+/// Applies the same plan to every block of a kind. Blocks without a plan are left to the default
+/// optimizer.
+pub struct SequentialModelPlan<B: AutodiffBackend> {
+    conv: Option<Conv2dModelPlan<B>>,
+    conv_transpose: Option<ConvTranspose2dModelPlan<B>>,
+    linear: Option<LinearModelPlan<B>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct SequentialModelPlanConfig {
+    pub conv: Option<Conv2dModelPlanConfig>,
+    pub conv_transpose: Option<ConvTranspose2dModelPlanConfig>,
+    pub linear: Option<LinearModelPlanConfig>,
+}
+
+impl<B: AutodiffBackend> ApplyGradients<B> for SequentialModel<B> {
+    type Plan = SequentialModelPlan<B>;
+    type PlanConfig = SequentialModelPlanConfig;
+
+    fn config_to_plan(config: Self::PlanConfig) -> Self::Plan {
+        SequentialModelPlan {
+            conv: config.conv.map(Conv2dModel::config_to_plan),
+            conv_transpose: config
+                .conv_transpose
+                .map(ConvTranspose2dModel::config_to_plan),
+            linear: config.linear.map(LinearModel::config_to_plan),
+        }
+    }
+
+    fn apply_gradients(
+        &mut self,
+        lr: f64,
+        grads: &mut <B as AutodiffBackend>::Gradients,
+        plan: &mut Self::Plan,
+    ) {
+        self.iter_blocks(|mut block| {
+            match &mut block {
+                Block::Conv(conv) => {
+                    if let Some(plan) = &mut plan.conv {
+                        conv.apply_gradients(lr, grads, plan);
+                    }
+                }
+                Block::ConvTranspose(conv) => {
+                    if let Some(plan) = &mut plan.conv_transpose {
+                        conv.apply_gradients(lr, grads, plan);
+                    }
+                }
+                Block::Linear(linear) => {
+                    if let Some(plan) = &mut plan.linear {
+                        linear.apply_gradients(lr, grads, plan);
+                    }
+                }
+                _ => {}
+            }
+            block
+        });
+    }
+
+    fn visit_optimizers(plan: &mut Self::Plan, visitor: &mut impl OptimizerVisitor<B>) {
+        if let Some(plan) = &mut plan.conv {
+            Conv2dModel::visit_optimizers(plan, visitor);
+        }
+        if let Some(plan) = &mut plan.conv_transpose {
+            ConvTranspose2dModel::visit_optimizers(plan, visitor);
+        }
+        if let Some(plan) = &mut plan.linear {
+            LinearModel::visit_optimizers(plan, visitor);
+        }
+    }
+}
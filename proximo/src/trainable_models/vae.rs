@@ -1,9 +1,22 @@
 use burn::{Tensor, prelude::Backend};
 use general_models::{
     SimpleTrain,
-    composite::autoencoder::{AutoEncoderModel, vae::VariationalEncoderModel},
+    composite::autoencoder::{
+        AutoEncoderModel, cvae::ConditionalVaeModel, vae::VariationalEncoderModel,
+    },
 };
 
+/// The KL divergence between the latent distribution and a standard Gaussian, averaged over the
+/// batch.
+fn kl_divergence<B: Backend>(mean: Tensor<B, 2>, logvar: Tensor<B, 2>) -> Tensor<B, 1> {
+    let kld_element = mean
+        .powf_scalar(2.0)
+        .add(logvar.clone().exp())
+        .sub_scalar(1.0)
+        .sub(logvar);
+    kld_element.sum_dim(1).mean().mul_scalar(0.5)
+}
+
 pub fn sample_vae<B, E, D, const N_I: usize>(
     model: &AutoEncoderModel<B, VariationalEncoderModel<B, E>, D>,
     input: Tensor<B, N_I>,
@@ -19,12 +32,26 @@ where
         .reparameterize(actual_mean.clone(), actual_logvar.clone());
     let actual_reconstructed = model.decoder.train(sampled_latent);
 
-    let kld_element = actual_mean
-        .powf_scalar(2.0)
-        .add(actual_logvar.clone().exp())
-        .sub_scalar(1.0)
-        .sub(actual_logvar);
-    let kld = kld_element.sum_dim(1).mean().mul_scalar(0.5);
+    (
+        actual_reconstructed,
+        kl_divergence(actual_mean, actual_logvar),
+    )
+}
+
+pub fn sample_conditional_vae<B, E, D, const N_I: usize>(
+    model: &ConditionalVaeModel<B, E, D>,
+    input: Tensor<B, N_I>,
+    condition: Tensor<B, 2>,
+) -> (Tensor<B, N_I>, Tensor<B, 1>)
+where
+    B: Backend,
+    E: SimpleTrain<B, N_I, 2>,
+    D: SimpleTrain<B, 2, N_I>,
+{
+    let (actual_reconstructed, actual_mean, actual_logvar) = model.train(input, condition);
 
-    (actual_reconstructed, kld)
+    (
+        actual_reconstructed,
+        kl_divergence(actual_mean, actual_logvar),
+    )
 }
@@ -0,0 +1,25 @@
+use burn::{Tensor, prelude::Backend};
+use general_models::{
+    SimpleTrain,
+    composite::autoencoder::{
+        AutoEncoderModel,
+        vq::{Quantized, VectorQuantizedEncoderModel},
+    },
+};
+
+/// Reconstructs `input` through the quantized latent, whose codebook and commitment losses are
+/// left to the caller.
+pub fn sample_vq<B, E, D, const N_I: usize>(
+    model: &AutoEncoderModel<B, VectorQuantizedEncoderModel<B, E>, D>,
+    input: Tensor<B, N_I>,
+) -> (Tensor<B, N_I>, Quantized<B>)
+where
+    B: Backend,
+    E: SimpleTrain<B, N_I, 2>,
+    D: SimpleTrain<B, 2, N_I>,
+{
+    let quantized = model.encoder.train(input);
+    let reconstructed = model.decoder.train(quantized.straight_through());
+
+    (reconstructed, quantized)
+}
@@ -1,9 +1,16 @@
 use std::sync::Mutex;
 
-use burn::{Tensor, lr_scheduler::LrScheduler, prelude::Backend, tensor::backend::AutodiffBackend};
+use burn::{
+    Tensor,
+    lr_scheduler::LrScheduler,
+    prelude::Backend,
+    tensor::{ElementConversion, backend::AutodiffBackend},
+};
 use general_dataset::{FromSqlRow, SqliteDataset, StatefulBatcher};
+use general_models::metrics::{Metrics, RunningStats};
 use rand::{Rng, seq::SliceRandom};
 use rayon::join;
+use tracing::info;
 
 use crate::trainable_models::{TrainableModel, ValidatableModel, apply_gradients::ApplyGradients};
 
@@ -72,11 +79,19 @@ use crate::trainable_models::{TrainableModel, ValidatableModel, apply_gradients:
 //     }
 // }
 
+/// Trains the model on random batches of `dataset`, taking one optimizer step and one step of
+/// `lr_scheduler` for every `grad_accumulate_count` batches, whose gradients are averaged. Only
+/// whole groups of batches are trained on.
+///
+/// The first `skip_batch_count` batches are skipped, such as to resume an interrupted epoch with
+/// the same `rng`.
 pub fn train_epoch<B, M, Row, Item>(
     mut model: M,
     dataset: &mut SqliteDataset,
     batch_size: usize,
     max_batch_count: usize,
+    grad_accumulate_count: usize,
+    skip_batch_count: usize,
     batcher: &mut (impl StatefulBatcher<Row, Item> + Send),
     lr_scheduler: &mut impl LrScheduler,
     grads_plan: &mut M::Plan,
@@ -99,7 +114,10 @@ where
         .collect();
     block_indices.shuffle(rng);
     block_indices.truncate(max_batch_count);
-    let mut block_indices = block_indices.into_iter();
+    let grad_accumulate_count = grad_accumulate_count.clamp(1, block_indices.len().max(1));
+    block_indices.truncate(block_indices.len() / grad_accumulate_count * grad_accumulate_count);
+    let mut accumulation = GradientAccumulation::new(grad_accumulate_count);
+    let mut block_indices = block_indices.into_iter().skip(skip_batch_count);
     let Some(first_index) = block_indices.next() else {
         return model;
     };
@@ -121,10 +139,7 @@ where
                 )
             },
             || {
-                let loss = model.batch_train(batch, &grads_plan);
-                let mut grads = loss.backward();
-                let lr = lr_scheduler.step();
-                model.apply_gradients(lr, &mut grads, grads_plan);
+                let (loss, lr) = accumulation.train(&mut model, batch, grads_plan, lr_scheduler);
                 (loss, lr, model)
             },
         );
@@ -135,23 +150,76 @@ where
             break;
         }
     }
+    // There is no previous batch when only one is left, such as when resuming an epoch
     let ((), (loss, lr)) = join(
         || {
-            let (loss, lr) = last_results.unwrap();
-            post_batch(loss, lr);
-        },
-        || {
-            let loss = model.batch_train(batch, &grads_plan);
-            let mut grads = loss.backward();
-            let lr = lr_scheduler.step();
-            model.apply_gradients(lr, &mut grads, grads_plan);
-            (loss, lr)
+            if let Some((loss, lr)) = last_results {
+                post_batch(loss, lr);
+            }
         },
+        || accumulation.train(&mut model, batch, grads_plan, lr_scheduler),
     );
     post_batch(loss, lr);
     model
 }
 
+/// The summed gradients of the batches of an effective batch, which are applied together once
+/// `count` batches have been trained on.
+struct GradientAccumulation<B: AutodiffBackend> {
+    count: usize,
+    accumulated_count: usize,
+    grads: Option<B::Gradients>,
+    lr: f64,
+}
+
+impl<B: AutodiffBackend> GradientAccumulation<B> {
+    fn new(count: usize) -> Self {
+        Self {
+            count,
+            accumulated_count: 0,
+            grads: None,
+            lr: 0.0,
+        }
+    }
+
+    /// Trains `model` on `batch`, returning the loss and the learning rate that its gradients are
+    /// applied with.
+    fn train<M, Item>(
+        &mut self,
+        model: &mut M,
+        batch: Item,
+        grads_plan: &mut M::Plan,
+        lr_scheduler: &mut impl LrScheduler,
+    ) -> (Tensor<B, 1>, f64)
+    where
+        M: TrainableModel<B, Item>,
+    {
+        // The scheduler only steps once the gradients are applied, so that an interrupted effective
+        // batch doesn't advance it
+        if self.accumulated_count == 0 {
+            self.lr = lr_scheduler.clone().step();
+        }
+        let loss = model.batch_train(batch, grads_plan);
+        // Scaling each loss averages the gradients over the effective batch
+        let grads = loss.clone().div_scalar(self.count as f64).backward();
+        match &mut self.grads {
+            Some(accumulated) => model.accumulate_gradients(accumulated, &grads),
+            None => self.grads = Some(grads),
+        }
+        self.accumulated_count += 1;
+        if self.accumulated_count == self.count {
+            lr_scheduler.step();
+            let mut grads = self.grads.take().unwrap();
+            model.apply_gradients(self.lr, &mut grads, grads_plan);
+            self.accumulated_count = 0;
+        }
+        (loss, self.lr)
+    }
+}
+
+/// Runs the model on random batches of `dataset`, adding the outputs to `metrics`, which is reset
+/// first. The loss and metrics over all batches are logged at the end, and the losses are
+/// returned.
 pub fn validate_model<B, M, Row, Item>(
     model: &mut M,
     dataset: &mut SqliteDataset,
@@ -159,8 +227,10 @@ pub fn validate_model<B, M, Row, Item>(
     max_batch_count: usize,
     batcher: &mut (impl StatefulBatcher<Row, Item> + Send),
     rng: &mut (impl Rng + Send),
-    mut post_batch: impl FnMut(Tensor<B, 1>) -> bool + Send,
-) where
+    metrics: &mut Metrics,
+    mut post_batch: impl FnMut(f64) -> bool + Send,
+) -> RunningStats
+where
     M: Send,
     B: Backend,
     Row: FromSqlRow,
@@ -169,6 +239,13 @@ pub fn validate_model<B, M, Row, Item>(
 {
     // Sync maker using Mutex
     let mut model = Mutex::new(model);
+    let mut losses = RunningStats::new();
+    let mut post_batch = |loss: Tensor<B, 1>| {
+        let loss = loss.into_scalar().elem::<f64>();
+        losses.push(loss);
+        post_batch(loss)
+    };
+    metrics.reset();
     let mut block_indices: Vec<_> = (0..dataset.get_batch_count(batch_size))
         .map(|x| x * batch_size)
         .collect();
@@ -176,7 +253,7 @@ pub fn validate_model<B, M, Row, Item>(
     block_indices.truncate(max_batch_count);
     let mut block_indices = block_indices.into_iter();
     let Some(first_index) = block_indices.next() else {
-        return;
+        return losses;
     };
     let mut batch = dataset.query(first_index, batch_size, &mut *batcher);
     let mut last_results = None;
@@ -195,7 +272,7 @@ pub fn validate_model<B, M, Row, Item>(
                     },
                 )
             },
-            || model.get_mut().unwrap().batch_valid(batch),
+            || model.get_mut().unwrap().batch_valid(batch, metrics),
         );
         last_results = Some(loss);
         batch = next_batch;
@@ -205,10 +282,134 @@ pub fn validate_model<B, M, Row, Item>(
     }
     let ((), loss) = join(
         || {
-            let loss = last_results.unwrap();
-            post_batch(loss);
+            if let Some(loss) = last_results {
+                post_batch(loss);
+            }
         },
-        || model.get_mut().unwrap().batch_valid(batch),
+        || model.get_mut().unwrap().batch_valid(batch, metrics),
     );
     post_batch(loss);
+
+    if metrics.is_empty() {
+        info!("Validation Loss: {losses}");
+    } else {
+        info!("Validation Loss: {losses}; {metrics}");
+    }
+    losses
+}
+
+#[cfg(test)]
+mod tests {
+    use burn::backend::Autodiff;
+    use general_dataset::presets::sequence::SequenceItem;
+    use general_models::{
+        Init, SimpleTrain,
+        cpu::{NdArrayBackend, get_device},
+        linear::{LinearModel, LinearModelConfig},
+    };
+    use rand::{SeedableRng, rngs::SmallRng};
+
+    use super::*;
+    use crate::trainable_models::{AdHocLossModel, apply_gradients::AdHocTrainingPlan};
+
+    type B = Autodiff<NdArrayBackend>;
+
+    /// A dataset of 10 rows without any data, which doesn't need a database file.
+    fn dataset() -> SqliteDataset {
+        SqliteDataset::new(
+            ":memory:",
+            "WITH RECURSIVE ids(row_id) AS (SELECT 0 UNION ALL SELECT row_id + 1 FROM ids \
+             WHERE row_id < 9) SELECT row_id, x'' AS sequence, x'' AS target FROM ids \
+             WHERE row_id >= ?1 ORDER BY row_id LIMIT ?2"
+                .into(),
+            "SELECT 10 AS len".into(),
+            vec![],
+        )
+        .unwrap()
+    }
+
+    /// Batches rows into their count.
+    struct RowCount(usize);
+
+    impl StatefulBatcher<SequenceItem, usize> for RowCount {
+        fn reset(&mut self) {
+            self.0 = 0;
+        }
+
+        fn ingest(&mut self, _item: SequenceItem) {
+            self.0 += 1;
+        }
+
+        fn finish(&mut self) -> usize {
+            self.0
+        }
+    }
+
+    #[test]
+    fn train_epoch_resumes_with_one_batch_left() {
+        let device = get_device();
+        let config: LinearModelConfig = serde_json::from_value(serde_json::json!({
+            "input_size": 1,
+            "default_activation": null,
+            "default_norm": null,
+            "layers": [1]
+        }))
+        .unwrap();
+        let model = AdHocLossModel::new(
+            Init::<B, LinearModel<B>>::init(config, device),
+            |model: &LinearModel<B>, batch: usize, _: &AdHocTrainingPlan<B, LinearModel<B>>| {
+                let input = Tensor::<B, 2>::ones([batch, 1], device);
+                model.train(input).sum()
+            },
+        );
+        let mut grads_plan = AdHocLossModel::<LinearModel<B>, ()>::config_to_plan(
+            serde_json::from_value(
+                serde_json::json!({ "default_optimizer": { "sgd": {} }, "plan": null }),
+            )
+            .unwrap(),
+        );
+        let mut batch_count = 0;
+        train_epoch::<B, _, SequenceItem, _>(
+            model,
+            &mut dataset(),
+            1,
+            usize::MAX,
+            1,
+            9,
+            &mut RowCount(0),
+            &mut 0.1,
+            &mut grads_plan,
+            &mut SmallRng::seed_from_u64(0),
+            device,
+            |_, _| {
+                batch_count += 1;
+                false
+            },
+        );
+        assert_eq!(batch_count, 1);
+    }
+
+    #[test]
+    fn validate_model_with_one_batch() {
+        let device = get_device();
+        let mut model = AdHocLossModel::new((), |_: &(), batch: usize, _: &mut Metrics| {
+            Tensor::<NdArrayBackend, 1>::from_floats([batch as f32], device)
+        });
+        let mut batch_count = 0;
+        let losses = validate_model::<NdArrayBackend, _, SequenceItem, _>(
+            &mut model,
+            &mut dataset(),
+            4,
+            1,
+            &mut RowCount(0),
+            &mut SmallRng::seed_from_u64(0),
+            &mut Metrics::default(),
+            |_| {
+                batch_count += 1;
+                false
+            },
+        );
+        assert_eq!(batch_count, 1);
+        assert_eq!(losses.count(), 1);
+    }
 }